@@ -0,0 +1,64 @@
+//! Starting the merge daemon on demand
+
+use anyhow::{bail, Context, Result};
+use merge_daemon::client::DaemonClient;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use tokio::process::Command;
+
+/// Whether the daemon accepts connections
+pub async fn is_running(client: &DaemonClient) -> bool {
+    client.status().await.is_ok()
+}
+
+/// Start the daemon for `repo_root` unless it is already running, and
+/// check that it speaks our protocol
+pub async fn ensure_running(
+    client: &DaemonClient,
+    repo_root: &Path,
+    state_dir: &Path,
+) -> Result<()> {
+    if !is_running(client).await {
+        start(client, repo_root, state_dir).await?;
+    }
+
+    client.hello().await.with_context(|| {
+        format!(
+            "Incompatible merge daemon at {:?}, restart it",
+            client.socket()
+        )
+    })?;
+    Ok(())
+}
+
+/// Start the daemon, which detaches and returns once it accepts connections
+async fn start(client: &DaemonClient, repo_root: &Path, state_dir: &Path) -> Result<()> {
+    let output = Command::new(daemon_binary())
+        .arg("--repo")
+        .arg(repo_root)
+        .arg("--socket")
+        .arg(client.socket())
+        .arg("--db")
+        .arg(state_dir.join("state.db"))
+        .stdin(Stdio::null())
+        .output()
+        .await
+        .context("Failed to start merge daemon")?;
+
+    if !output.status.success() {
+        bail!(
+            "Failed to start merge daemon: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+/// The merge daemon binary: next to this one if present, otherwise on PATH
+fn daemon_binary() -> PathBuf {
+    std::env::current_exe()
+        .ok()
+        .and_then(|exe| Some(exe.parent()?.join("merge-daemon")))
+        .filter(|path| path.exists())
+        .unwrap_or_else(|| PathBuf::from("merge-daemon"))
+}
@@ -0,0 +1,672 @@
+//! Hook subcommands
+
+use anyhow::{bail, Context, Result};
+use chrono::Utc;
+use git2::{IndexAddOption, Oid, Repository};
+use merge_daemon::client::CommitResult;
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+use crate::session::{AgentRecord, PromptRecord, Session};
+use crate::{client, publish};
+use crate::{HookContext, HookInput};
+
+/// Words that indicate a prompt will change code
+const CHANGE_KEYWORDS: &[&str] = &[
+    "implement",
+    "add",
+    "create",
+    "fix",
+    "update",
+    "modify",
+    "refactor",
+    "remove",
+    "delete",
+    "change",
+    "write",
+    "build",
+    "develop",
+    "spawn",
+    "test",
+    "optimize",
+    "improve",
+    "document",
+    "configure",
+    "setup",
+];
+
+/// Words left out of generated branch names
+const SKIP_WORDS: &[&str] = &[
+    "the",
+    "a",
+    "an",
+    "to",
+    "and",
+    "or",
+    "for",
+    "in",
+    "on",
+    "with",
+    "that",
+    "this",
+    "is",
+    "are",
+    "be",
+    "will",
+    "please",
+    "can",
+    "you",
+    "i",
+    "implement",
+    "add",
+    "create",
+    "fix",
+    "update",
+    "modify",
+    "refactor",
+    "task",
+    "using",
+    "use",
+    "tool",
+    "must",
+    "each",
+    "make",
+    "sure",
+];
+
+/// Start and push a feature branch and session when a change-making prompt
+/// arrives on the main branch, or record follow-up prompts of the current
+/// session in it and its pull request
+pub async fn prompt_submit(ctx: &HookContext, input: &HookInput) -> Result<Option<Value>> {
+    let prompt = input
+        .prompt
+        .as_deref()
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+        .context("No prompt found")?;
+
+    let repo = Repository::open(&ctx.repo_root)?;
+    let branch = current_branch(&repo);
+    let mut messages: Vec<String> = publish::run(ctx, "issue", &[]).await.into_iter().collect();
+
+    if let Some(mut session) = Session::current(&ctx.state_dir)? {
+        if branch.as_deref() == Some(session.feature_branch.as_str()) {
+            session.prompts.push(PromptRecord {
+                prompt: prompt.to_string(),
+                timestamp: timestamp(),
+            });
+            session.save(&ctx.state_dir)?;
+            messages.extend(publish::run(ctx, "append-prompt", &[prompt]).await);
+            return Ok(prompt_output(messages));
+        }
+    }
+
+    let Some(base_branch) = branch.filter(|b| is_main_branch(b)) else {
+        return Ok(prompt_output(messages));
+    };
+    if !makes_changes(prompt) {
+        return Ok(prompt_output(messages));
+    }
+
+    let feature_branch = branch_name_for(prompt);
+    let head = repo.head()?.peel_to_commit()?;
+    repo.branch(&feature_branch, &head, false)
+        .with_context(|| format!("Failed to create branch {}", feature_branch))?;
+    repo.set_head(&format!("refs/heads/{}", feature_branch))?;
+
+    let session = Session {
+        session_id: format!("session-{}", Utc::now().timestamp()),
+        feature_branch,
+        base_branch,
+        original_prompt: prompt.to_string(),
+        state: "STARTED".to_string(),
+        prompts: vec![],
+        agents: vec![],
+        extra: Default::default(),
+    };
+    session.start(&ctx.state_dir)?;
+    ensure_gitignored(&ctx.repo_root, &ctx.state_dir)?;
+    messages.push(format!(
+        "Feature branch '{}' created. Session ID: {}",
+        session.feature_branch, session.session_id
+    ));
+    messages.extend(publish::run(ctx, "push-branch", &[&session.feature_branch]).await);
+
+    if ctx.autostart {
+        if let Err(e) = client::ensure_running(&ctx.client, &ctx.repo_root, &ctx.state_dir).await {
+            eprintln!("fork-join-hook: {:#}", e);
+        }
+    }
+
+    Ok(prompt_output(messages))
+}
+
+/// UserPromptSubmit output adding `messages` to the prompt's context
+fn prompt_output(messages: Vec<String>) -> Option<Value> {
+    if messages.is_empty() {
+        return None;
+    }
+    Some(json!({
+        "hookSpecificOutput": {
+            "hookEventName": "UserPromptSubmit",
+            "additionalContext": messages.join("\n"),
+        }
+    }))
+}
+
+/// Have the daemon create a worktree and branch for a new agent
+pub async fn agent_spawn(ctx: &HookContext, agent_type: &str, task: &str) -> Result<Option<Value>> {
+    let mut session = Session::current(&ctx.state_dir)?.context("No active fork-join session")?;
+
+    if ctx.autostart {
+        client::ensure_running(&ctx.client, &ctx.repo_root, &ctx.state_dir).await?;
+    }
+
+    let agent_id = format!("agent-{}", &Uuid::new_v4().simple().to_string()[..8]);
+    let registered = ctx
+        .client
+        .register_with_worktree(&agent_id, &session.session_id, &session.feature_branch)
+        .await?;
+
+    session.agents.push(AgentRecord {
+        agent_id: agent_id.clone(),
+        agent_type: agent_type.to_string(),
+        worktree: registered.worktree.clone(),
+        branch: registered.branch.clone(),
+        task: task.to_string(),
+        status: "WORKING".to_string(),
+        spawned_at: timestamp(),
+        commit_message: None,
+        commit_sha: None,
+        merged_at: None,
+        conflict_count: 0,
+        extra: Default::default(),
+    });
+    session.state = "WORKING".to_string();
+    session.save(&ctx.state_dir)?;
+
+    Ok(Some(json!({
+        "agent_spawned": true,
+        "agent_id": agent_id,
+        "worktree": registered.worktree,
+        "branch": registered.branch,
+        "session_id": session.session_id,
+        "working_directory": registered.worktree,
+    })))
+}
+
+/// Commit a finished agent's worktree and enqueue it for merge, or clean
+/// it up if it changed nothing
+pub async fn agent_complete(
+    ctx: &HookContext,
+    agent_id: &str,
+    message: Option<String>,
+) -> Result<Option<Value>> {
+    let mut session = Session::current(&ctx.state_dir)?.context("No active fork-join session")?;
+    let session_id = session.session_id.clone();
+    let feature_branch = session.feature_branch.clone();
+
+    let agent = session
+        .agent_mut(agent_id)
+        .with_context(|| format!("Agent not found in session: {}", agent_id))?;
+    if !agent.worktree.is_dir() {
+        bail!("Worktree not found: {:?}", agent.worktree);
+    }
+
+    let message = message.unwrap_or_else(|| format!("Agent {} work", agent_id));
+    let result = ctx
+        .client
+        .commit_and_enqueue(
+            agent_id,
+            &session_id,
+            &agent.worktree,
+            &message,
+            &feature_branch,
+            false,
+        )
+        .await?;
+
+    let CommitResult::Committed { commit, position } = result else {
+        if let Err(e) = ctx
+            .client
+            .worktree_remove(&agent.worktree, Some(&agent.branch))
+            .await
+        {
+            eprintln!("fork-join-hook: {:#}", e);
+        }
+        // The agent may never have been queued
+        let _ = ctx.client.dequeue(agent_id).await;
+
+        agent.status = "CLEANED_UP".to_string();
+        session.save(&ctx.state_dir)?;
+
+        return Ok(Some(json!({
+            "agent_complete": true,
+            "agent_id": agent_id,
+            "changes": false,
+            "merged": false,
+        })));
+    };
+
+    if !commit.untouched.is_empty() {
+        eprintln!(
+            "fork-join-hook: agent {} changed files it never wrote: {}",
+            agent_id,
+            commit.untouched.join(", ")
+        );
+    }
+
+    agent.commit_message = Some(message);
+    agent.commit_sha = Some(commit.commit_sha.clone());
+    agent.status = "COMMITTED".to_string();
+    session.save(&ctx.state_dir)?;
+
+    Ok(Some(json!({
+        "agent_complete": true,
+        "agent_id": agent_id,
+        "changes": true,
+        "commit_sha": commit.commit_sha,
+        "merge_queued": true,
+        "queue_position": position,
+        "awaiting_merge": true,
+        "untouched": commit.untouched,
+    })))
+}
+
+/// Report files written by Write/Edit tools to the agent that owns them
+pub async fn tool_complete(ctx: &HookContext, input: &HookInput) -> Result<Option<Value>> {
+    if !matches!(
+        input.tool_name.as_deref(),
+        Some("Write" | "Edit" | "MultiEdit")
+    ) {
+        return Ok(None);
+    }
+
+    let Some(file) = input.tool_input["file_path"]
+        .as_str()
+        .or_else(|| input.tool_input["path"].as_str())
+    else {
+        return Ok(None);
+    };
+
+    let Some(session) = Session::current(&ctx.state_dir)? else {
+        return Ok(None);
+    };
+
+    let path = match &input.cwd {
+        Some(cwd) => cwd.join(file),
+        None => std::env::current_dir()?.join(file),
+    };
+    let Some(agent) = session.agent_for_path(&path) else {
+        return Ok(None);
+    };
+
+    ctx.client
+        .track_file(&agent.agent_id, &path.to_string_lossy())
+        .await?;
+
+    Ok(None)
+}
+
+/// Commit whatever is left on the feature branch when the session stops,
+/// then push it and open its pull request
+pub async fn session_complete(ctx: &HookContext) -> Result<Option<Value>> {
+    let Some(session) = Session::current(&ctx.state_dir)? else {
+        return Ok(None);
+    };
+
+    let repo = Repository::open(&ctx.repo_root)?;
+    if current_branch(&repo).as_deref() != Some(session.feature_branch.as_str()) {
+        return Ok(None);
+    }
+
+    let skip = [&ctx.state_dir, &ctx.worktree_dir].map(|dir| {
+        dir.strip_prefix(&ctx.repo_root)
+            .unwrap_or(dir)
+            .to_path_buf()
+    });
+    let committed = commit_all(&repo, &skip, &commit_message_for(&session.feature_branch))?;
+
+    if client::is_running(&ctx.client).await {
+        ctx.client.session_end(&session.session_id).await?;
+    }
+
+    let mut messages: Vec<String> = committed
+        .map(|oid| {
+            format!(
+                "Committed {} on {}",
+                &oid.to_string()[..7],
+                session.feature_branch
+            )
+        })
+        .into_iter()
+        .collect();
+    messages.extend(
+        publish::run(
+            ctx,
+            "pull-request",
+            &[&session.feature_branch, &session.original_prompt],
+        )
+        .await,
+    );
+
+    if messages.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(json!({ "systemMessage": messages.join("\n") })))
+}
+
+/// Stage and commit all changes outside the `skip` directories. Returns
+/// None if there is nothing to commit.
+fn commit_all(repo: &Repository, skip: &[PathBuf], message: &str) -> Result<Option<Oid>> {
+    let mut skip_dirs = |p: &Path, _: &[u8]| -> i32 {
+        if skip.iter().any(|dir| p.starts_with(dir)) {
+            1
+        } else {
+            0
+        }
+    };
+
+    let mut index = repo.index()?;
+    index.add_all(["*"], IndexAddOption::DEFAULT, Some(&mut skip_dirs))?;
+    index.update_all(["*"], Some(&mut skip_dirs))?;
+    index.write()?;
+
+    let parent = repo.head()?.peel_to_commit()?;
+    let tree_id = index.write_tree()?;
+    if tree_id == parent.tree_id() {
+        return Ok(None);
+    }
+
+    let tree = repo.find_tree(tree_id)?;
+    let signature = repo.signature()?;
+    let oid = repo.commit(
+        Some("HEAD"),
+        &signature,
+        &signature,
+        message,
+        &tree,
+        &[&parent],
+    )?;
+
+    Ok(Some(oid))
+}
+
+/// Name of the checked-out branch, if any
+fn current_branch(repo: &Repository) -> Option<String> {
+    let head = repo.head().ok()?;
+    if !head.is_branch() {
+        return None;
+    }
+    head.shorthand().map(str::to_string)
+}
+
+fn is_main_branch(branch: &str) -> bool {
+    branch == "main" || branch == "master"
+}
+
+/// Whether a prompt looks like it will change code
+fn makes_changes(prompt: &str) -> bool {
+    let prompt = prompt.to_lowercase();
+    CHANGE_KEYWORDS.iter().any(|k| prompt.contains(k))
+}
+
+/// Angular-style branch name (`<type>/<description>`) for a prompt
+fn branch_name_for(prompt: &str) -> String {
+    let prompt = prompt.to_lowercase();
+
+    let commit_type = [
+        (
+            "fix",
+            &[
+                "fix", "bug", "error", "issue", "broken", "crash", "fail", "wrong",
+            ][..],
+        ),
+        (
+            "test",
+            &["test", "spec", "unit", "integration", "e2e", "coverage"],
+        ),
+        (
+            "docs",
+            &["document", "readme", "doc", "comment", "jsdoc", "explain"],
+        ),
+        (
+            "refactor",
+            &[
+                "refactor",
+                "restructure",
+                "reorganize",
+                "clean",
+                "simplify",
+                "move",
+                "rename",
+                "extract",
+            ],
+        ),
+        (
+            "perf",
+            &[
+                "performance",
+                "optimize",
+                "speed",
+                "faster",
+                "slow",
+                "cache",
+                "lazy",
+                "efficient",
+            ],
+        ),
+        (
+            "build",
+            &[
+                "build",
+                "dependency",
+                "package",
+                "npm",
+                "yarn",
+                "webpack",
+                "vite",
+                "bundle",
+            ],
+        ),
+        (
+            "ci",
+            &[
+                "ci",
+                "pipeline",
+                "workflow",
+                "github action",
+                "deploy",
+                "release",
+            ],
+        ),
+    ]
+    .iter()
+    .find(|(_, keywords)| keywords.iter().any(|k| prompt.contains(k)))
+    .map_or("feat", |(t, _)| t);
+
+    let head: String = prompt.chars().take(200).collect();
+    let words: Vec<&str> = head
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|w| w.len() > 2 && !SKIP_WORDS.contains(w))
+        .take(4)
+        .collect();
+
+    let mut description = words.join("-");
+    if description.len() > 40 {
+        description.truncate(40);
+        if let Some(cut) = description.rfind('-') {
+            description.truncate(cut);
+        }
+    }
+    if description.is_empty() {
+        description = format!("task-{}", Utc::now().timestamp() % 100_000);
+    }
+
+    format!("{}/{}", commit_type, description)
+}
+
+/// Commit message for work left on a feature branch, from its name
+fn commit_message_for(feature_branch: &str) -> String {
+    match feature_branch.split_once('/') {
+        Some((commit_type, description)) => {
+            format!("{}: {}", commit_type, description.replace('-', " "))
+        }
+        None => format!("Update {}", feature_branch),
+    }
+}
+
+/// Add the state directory to .gitignore unless it is already listed or
+/// explicitly un-ignored
+fn ensure_gitignored(repo_root: &Path, state_dir: &Path) -> Result<()> {
+    let Some(name) = state_dir
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+    else {
+        return Ok(());
+    };
+    let gitignore = repo_root.join(".gitignore");
+    let content = std::fs::read_to_string(&gitignore).unwrap_or_default();
+
+    let listed = content.lines().map(str::trim).any(|line| {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        line.trim_start_matches('!').trim().trim_matches('/') == name
+    });
+    if listed {
+        return Ok(());
+    }
+
+    let mut content = content;
+    if !content.is_empty() && !content.ends_with('\n') {
+        content.push('\n');
+    }
+    content.push_str(&format!("{}/\n", name));
+    std::fs::write(&gitignore, content)?;
+    Ok(())
+}
+
+/// Current time in the format used by the session files
+fn timestamp() -> String {
+    Utc::now().format("%Y-%m-%dT%H:%M:%SZ").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    /// Repository with one commit of README, checked out in a temp dir
+    fn init_repo(dir: &Path) -> Repository {
+        let repo = Repository::init(dir).unwrap();
+        let mut config = repo.config().unwrap();
+        config.set_str("user.name", "test").unwrap();
+        config.set_str("user.email", "test@example.com").unwrap();
+
+        std::fs::write(dir.join("README"), "init\n").unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("README")).unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = repo.signature().unwrap();
+        repo.commit(Some("HEAD"), &signature, &signature, "init", &tree, &[])
+            .unwrap();
+        drop(tree);
+        repo
+    }
+
+    #[test]
+    fn branch_names_carry_type_and_description() {
+        assert_eq!(
+            branch_name_for("Fix the crash when saving files"),
+            "fix/crash-when-saving-files"
+        );
+        assert_eq!(
+            branch_name_for("Add a README section on installation"),
+            "docs/readme-section-installation"
+        );
+        assert_eq!(
+            branch_name_for("Implement authentication middleware configuration validation"),
+            "feat/authentication-middleware-configuration"
+        );
+        assert!(branch_name_for("Add it").starts_with("feat/task-"));
+    }
+
+    #[test]
+    fn change_keywords_match_any_case() {
+        assert!(makes_changes("Please fix the login bug"));
+        assert!(makes_changes("IMPLEMENT caching"));
+        assert!(!makes_changes("What does this function do?"));
+    }
+
+    #[test]
+    fn gitignore_gets_state_dir_once() {
+        let dir = TempDir::new().unwrap();
+        let gitignore = dir.path().join(".gitignore");
+        let state_dir = dir.path().join(".fork-join");
+
+        ensure_gitignored(dir.path(), &state_dir).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&gitignore).unwrap(),
+            ".fork-join/\n"
+        );
+
+        std::fs::write(&gitignore, "target").unwrap();
+        ensure_gitignored(dir.path(), &state_dir).unwrap();
+        ensure_gitignored(dir.path(), &state_dir).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&gitignore).unwrap(),
+            "target\n.fork-join/\n"
+        );
+
+        // A commented-out entry doesn't count
+        std::fs::write(&gitignore, "# .fork-join\n").unwrap();
+        ensure_gitignored(dir.path(), &state_dir).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&gitignore).unwrap(),
+            "# .fork-join\n.fork-join/\n"
+        );
+    }
+
+    #[test]
+    fn gitignore_respects_existing_entries() {
+        let dir = TempDir::new().unwrap();
+        let gitignore = dir.path().join(".gitignore");
+        let state_dir = dir.path().join(".fork-join");
+
+        for content in [".fork-join\n", "/.fork-join/ # state\n", "!.fork-join/\n"] {
+            std::fs::write(&gitignore, content).unwrap();
+            ensure_gitignored(dir.path(), &state_dir).unwrap();
+            assert_eq!(std::fs::read_to_string(&gitignore).unwrap(), content);
+        }
+    }
+
+    #[test]
+    fn commit_all_skips_state_and_worktrees() {
+        let dir = TempDir::new().unwrap();
+        let repo = init_repo(dir.path());
+        let skip = [PathBuf::from(".fork-join"), PathBuf::from(".worktrees")];
+
+        assert_eq!(commit_all(&repo, &skip, "nothing").unwrap(), None);
+
+        std::fs::write(dir.path().join("lib.rs"), "fn main() {}\n").unwrap();
+        std::fs::remove_file(dir.path().join("README")).unwrap();
+        for skipped in &skip {
+            std::fs::create_dir(dir.path().join(skipped)).unwrap();
+            std::fs::write(dir.path().join(skipped).join("file"), "x\n").unwrap();
+        }
+
+        let oid = commit_all(&repo, &skip, "feat: lib").unwrap().unwrap();
+        let commit = repo.find_commit(oid).unwrap();
+        assert_eq!(commit.message(), Some("feat: lib"));
+        let paths: Vec<_> = commit
+            .tree()
+            .unwrap()
+            .iter()
+            .map(|entry| entry.name().unwrap().to_string())
+            .collect();
+        assert_eq!(paths, ["lib.rs"]);
+
+        assert_eq!(commit_all(&repo, &skip, "again").unwrap(), None);
+    }
+}
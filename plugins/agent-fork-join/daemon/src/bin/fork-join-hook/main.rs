@@ -0,0 +1,229 @@
+//! Hook handler for agent-fork-join
+//!
+//! Replaces the bash hooks: each subcommand reads the hook payload from
+//! stdin, talks to the merge daemon over its socket and prints the hook
+//! output JSON. Pushing, pull requests and issue tracking run through the
+//! plugin's `scripts/publish.sh`.
+
+mod client;
+mod hooks;
+mod publish;
+mod session;
+
+use anyhow::{Context, Result};
+use clap::{Parser, Subcommand};
+use serde::Deserialize;
+use std::io::{IsTerminal, Read};
+use std::path::{Path, PathBuf};
+
+use merge_daemon::client::DaemonClient;
+use merge_daemon::socket;
+
+/// Command line arguments
+#[derive(Parser, Debug)]
+#[command(name = "fork-join-hook")]
+#[command(about = "Hook handler for agent-fork-join")]
+#[command(version)]
+struct Args {
+    /// Merge daemon socket (default: the current user's socket for the
+    /// repository)
+    #[arg(long, env = "FORK_JOIN_DAEMON_SOCKET")]
+    socket: Option<PathBuf>,
+
+    /// Fork-join state directory, relative to the repository root
+    #[arg(long, env = "FORK_JOIN_STATE_DIR", default_value = ".fork-join")]
+    state_dir: PathBuf,
+
+    /// Agent worktree directory, relative to the repository root
+    #[arg(long, env = "FORK_JOIN_WORKTREE_DIR", default_value = ".worktrees")]
+    worktree_dir: PathBuf,
+
+    /// Plugin directory holding the GitHub scripts (default: the plugin this
+    /// binary was built in)
+    #[arg(long, env = "CLAUDE_PLUGIN_ROOT")]
+    plugin_root: Option<PathBuf>,
+
+    /// Don't start the merge daemon when it isn't running
+    #[arg(long, env = "FORK_JOIN_NO_AUTOSTART")]
+    no_autostart: bool,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// UserPromptSubmit: start a feature branch and session for change requests
+    PromptSubmit,
+
+    /// Agent spawned: create the agent's worktree through the daemon
+    AgentSpawn {
+        /// Agent type (default: worker)
+        agent_type: Option<String>,
+        /// Task description
+        task: Option<String>,
+    },
+
+    /// Agent finished: commit its worktree and enqueue it for merge
+    AgentComplete {
+        /// Agent ID
+        agent_id: Option<String>,
+        /// Commit message
+        message: Option<String>,
+    },
+
+    /// PostToolUse: record files written by agents
+    ToolComplete,
+
+    /// Stop: commit remaining changes on the feature branch and open a PR
+    SessionComplete,
+}
+
+/// Hook payload read from stdin. Claude hook events and the agent hooks
+/// fill in different fields.
+#[derive(Debug, Default, Deserialize)]
+pub struct HookInput {
+    #[serde(default)]
+    pub cwd: Option<PathBuf>,
+    #[serde(default)]
+    pub prompt: Option<String>,
+    #[serde(default)]
+    pub tool_name: Option<String>,
+    #[serde(default)]
+    pub tool_input: serde_json::Value,
+    #[serde(default)]
+    pub agent_id: Option<String>,
+    #[serde(default)]
+    pub agent_type: Option<String>,
+    #[serde(default)]
+    pub task: Option<String>,
+    #[serde(default)]
+    pub commit_message: Option<String>,
+}
+
+/// Everything a hook needs to know about where it runs
+pub struct HookContext {
+    /// Root of the main working tree, even when run from an agent worktree
+    pub repo_root: PathBuf,
+    /// Absolute fork-join state directory
+    pub state_dir: PathBuf,
+    /// Absolute agent worktree directory
+    pub worktree_dir: PathBuf,
+    pub client: DaemonClient,
+    pub autostart: bool,
+    /// Plugin directory, if its publish script can be found
+    pub plugin_root: Option<PathBuf>,
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    let args = Args::parse();
+
+    // Hooks run git and AI helpers that may trigger hooks themselves
+    if std::env::var("FORK_JOIN_HOOK_CONTEXT").as_deref() == Ok("1") {
+        return;
+    }
+
+    match run(args).await {
+        Ok(Some(output)) => println!("{}", output),
+        Ok(None) => {}
+        Err(e) => {
+            eprintln!("fork-join-hook: {:#}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+async fn run(args: Args) -> Result<Option<serde_json::Value>> {
+    let input = read_input()?;
+
+    let cwd = match &input.cwd {
+        Some(cwd) => cwd.clone(),
+        None => std::env::current_dir()?,
+    };
+    let Some(repo_root) = repo_root(&cwd) else {
+        // Not in a git repository, nothing to do
+        return Ok(None);
+    };
+
+    let socket = match args.socket {
+        Some(socket) => socket,
+        None => socket::default_path(&repo_root)?,
+    };
+
+    let ctx = HookContext {
+        state_dir: repo_root.join(&args.state_dir),
+        worktree_dir: repo_root.join(&args.worktree_dir),
+        client: DaemonClient::new(socket).with_repo(repo_root.to_string_lossy()),
+        repo_root,
+        autostart: !args.no_autostart,
+        plugin_root: plugin_root(args.plugin_root),
+    };
+
+    match args.command {
+        Command::PromptSubmit => hooks::prompt_submit(&ctx, &input).await,
+        Command::AgentSpawn { agent_type, task } => {
+            let agent_type = agent_type
+                .or_else(|| input.agent_type.clone())
+                .unwrap_or_else(|| "worker".to_string());
+            let task = task.or_else(|| input.task.clone()).unwrap_or_default();
+            hooks::agent_spawn(&ctx, &agent_type, &task).await
+        }
+        Command::AgentComplete { agent_id, message } => {
+            let agent_id = agent_id
+                .or_else(|| input.agent_id.clone())
+                .context("Agent ID is required")?;
+            let message = message.or_else(|| input.commit_message.clone());
+            hooks::agent_complete(&ctx, &agent_id, message).await
+        }
+        Command::ToolComplete => hooks::tool_complete(&ctx, &input).await,
+        Command::SessionComplete => hooks::session_complete(&ctx).await,
+    }
+}
+
+/// Read the hook payload from stdin. Non-JSON input is taken as a prompt.
+fn read_input() -> Result<HookInput> {
+    let mut stdin = std::io::stdin();
+    if stdin.is_terminal() {
+        return Ok(HookInput::default());
+    }
+
+    let mut raw = String::new();
+    stdin.read_to_string(&mut raw)?;
+    let raw = raw.trim();
+
+    if raw.is_empty() {
+        Ok(HookInput::default())
+    } else if raw.starts_with('{') {
+        serde_json::from_str(raw).context("Invalid hook payload")
+    } else {
+        Ok(HookInput {
+            prompt: Some(raw.to_string()),
+            ..Default::default()
+        })
+    }
+}
+
+/// Root of the main working tree of the repository containing `cwd`
+fn repo_root(cwd: &Path) -> Option<PathBuf> {
+    let repo = git2::Repository::discover(cwd).ok()?;
+    if repo.is_worktree() {
+        // The commondir file points at the main repository's .git directory
+        let commondir = std::fs::read_to_string(repo.path().join("commondir")).ok()?;
+        let git_dir = repo.path().join(commondir.trim()).canonicalize().ok()?;
+        git_dir.parent().map(Path::to_path_buf)
+    } else {
+        repo.workdir().map(Path::to_path_buf)
+    }
+}
+
+/// Plugin directory containing `scripts/publish.sh`. The binary is built at
+/// `<plugin>/daemon/target/<profile>/fork-join-hook`.
+fn plugin_root(configured: Option<PathBuf>) -> Option<PathBuf> {
+    configured
+        .or_else(|| {
+            let exe = std::env::current_exe().ok()?;
+            exe.ancestors().nth(4).map(Path::to_path_buf)
+        })
+        .filter(|root| root.join("scripts/publish.sh").is_file())
+}
@@ -0,0 +1,49 @@
+//! GitHub and issue tracker steps, run through the plugin's
+//! `scripts/publish.sh`
+
+use std::process::Stdio;
+use tokio::process::Command;
+
+use crate::HookContext;
+
+/// Run a step of the publish script in the repository and return what it
+/// printed for the user. Failures are reported on stderr but don't fail
+/// the hook: a missing remote or `gh` shouldn't stop local work.
+pub async fn run(ctx: &HookContext, step: &str, args: &[&str]) -> Option<String> {
+    let script = ctx.plugin_root.as_ref()?.join("scripts/publish.sh");
+
+    // `output()` would capture stderr too; the script's log belongs on ours
+    let child = Command::new(&script)
+        .arg(step)
+        .args(args)
+        .current_dir(&ctx.repo_root)
+        .env("FORK_JOIN_STATE_DIR", &ctx.state_dir)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn();
+    let output = match child {
+        Ok(child) => child.wait_with_output().await,
+        Err(e) => Err(e),
+    };
+
+    match output {
+        Ok(output) if output.status.success() => {
+            let printed = String::from_utf8_lossy(&output.stdout).trim().to_string();
+            (!printed.is_empty()).then_some(printed)
+        }
+        Ok(output) => {
+            eprintln!(
+                "fork-join-hook: {} {} failed: {}",
+                script.display(),
+                step,
+                output.status
+            );
+            None
+        }
+        Err(e) => {
+            eprintln!("fork-join-hook: failed to run {}: {}", script.display(), e);
+            None
+        }
+    }
+}
@@ -0,0 +1,111 @@
+//! Fork-join session state files under the state directory
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// A fork-join session, stored as `<state_dir>/<session_id>.json`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Session {
+    pub session_id: String,
+    pub feature_branch: String,
+    #[serde(default)]
+    pub base_branch: String,
+    #[serde(default)]
+    pub original_prompt: String,
+    #[serde(default)]
+    pub state: String,
+    #[serde(default)]
+    pub prompts: Vec<PromptRecord>,
+    #[serde(default)]
+    pub agents: Vec<AgentRecord>,
+    /// Fields written by other tools
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+/// A follow-up prompt in a session
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PromptRecord {
+    pub prompt: String,
+    pub timestamp: String,
+}
+
+/// An agent spawned in a session
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AgentRecord {
+    pub agent_id: String,
+    #[serde(default)]
+    pub agent_type: String,
+    pub worktree: PathBuf,
+    pub branch: String,
+    #[serde(default)]
+    pub task: String,
+    pub status: String,
+    #[serde(default)]
+    pub spawned_at: String,
+    #[serde(default)]
+    pub commit_message: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit_sha: Option<String>,
+    #[serde(default)]
+    pub merged_at: Option<String>,
+    #[serde(default)]
+    pub conflict_count: u32,
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+impl Session {
+    /// Load the current session, if any
+    pub fn current(state_dir: &Path) -> Result<Option<Self>> {
+        let Ok(session_id) = std::fs::read_to_string(state_dir.join("current_session")) else {
+            return Ok(None);
+        };
+        let path = session_path(state_dir, session_id.trim());
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let content = std::fs::read_to_string(&path)?;
+        let session = serde_json::from_str(&content)
+            .with_context(|| format!("Invalid session file {:?}", path))?;
+        Ok(Some(session))
+    }
+
+    /// Save the session and make it the current one
+    pub fn start(&self, state_dir: &Path) -> Result<()> {
+        self.save(state_dir)?;
+        std::fs::write(
+            state_dir.join("current_session"),
+            format!("{}\n", self.session_id),
+        )?;
+        Ok(())
+    }
+
+    /// Write the session file atomically
+    pub fn save(&self, state_dir: &Path) -> Result<()> {
+        std::fs::create_dir_all(state_dir)?;
+        let path = session_path(state_dir, &self.session_id);
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_string_pretty(self)?)?;
+        std::fs::rename(&tmp, &path)?;
+        Ok(())
+    }
+
+    /// Find an agent of this session
+    pub fn agent_mut(&mut self, agent_id: &str) -> Option<&mut AgentRecord> {
+        self.agents.iter_mut().find(|a| a.agent_id == agent_id)
+    }
+
+    /// The agent whose worktree contains `path`
+    pub fn agent_for_path(&self, path: &Path) -> Option<&AgentRecord> {
+        self.agents
+            .iter()
+            .find(|a| path.starts_with(&a.worktree) && path != a.worktree)
+    }
+}
+
+fn session_path(state_dir: &Path, session_id: &str) -> PathBuf {
+    state_dir.join(format!("{}.json", session_id))
+}
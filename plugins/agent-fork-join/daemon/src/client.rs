@@ -0,0 +1,410 @@
+//! Async client for the merge daemon socket
+
+use crate::error::{DaemonError, DaemonResult};
+use crate::merger::MergePreview;
+use crate::protocol::{Hello, MergeOutcome, Request, PROTOCOL_VERSION};
+use crate::queue::{
+    EnqueueOptions, QueueEntry, QueueStatus, ReapReport, SessionMergeReport, WorktreeListing,
+};
+use crate::repos::RepoInfo;
+use crate::state::MergeRecord;
+use crate::worktree::{AgentCommit, AgentWorktree};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::UnixStream;
+
+/// Talks to a merge daemon over its Unix socket, one connection per request
+#[derive(Debug, Clone)]
+pub struct DaemonClient {
+    socket: PathBuf,
+    /// Repo ID or absolute repository path sent with every request
+    repo: Option<String>,
+}
+
+/// Outcome of a COMMIT_AND_ENQUEUE request
+#[derive(Debug, Clone)]
+pub enum CommitResult {
+    /// The worktree was committed and enqueued at `position`
+    Committed {
+        commit: AgentCommit,
+        position: usize,
+    },
+    /// The worktree had nothing to commit
+    NoChanges,
+}
+
+#[derive(Deserialize)]
+struct PositionReply {
+    position: usize,
+}
+
+#[derive(Deserialize)]
+struct CommittedReply {
+    #[serde(flatten)]
+    commit: AgentCommit,
+    position: usize,
+}
+
+#[derive(Deserialize)]
+struct ConflictsReply {
+    files: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct EntryReply {
+    entry: QueueEntry,
+}
+
+#[derive(Deserialize)]
+struct WorktreesReply {
+    worktrees: Vec<WorktreeListing>,
+}
+
+#[derive(Deserialize)]
+struct PrunedReply {
+    pruned: Vec<PathBuf>,
+}
+
+#[derive(Deserialize)]
+struct HistoryReply {
+    merges: Vec<MergeRecord>,
+}
+
+#[derive(Deserialize)]
+struct ReposReply {
+    repos: Vec<RepoInfo>,
+}
+
+impl DaemonClient {
+    /// Create a client for the daemon listening on `socket`
+    pub fn new(socket: impl Into<PathBuf>) -> Self {
+        Self {
+            socket: socket.into(),
+            repo: None,
+        }
+    }
+
+    /// Send requests for `repo`, a repo ID or absolute repository path,
+    /// instead of the daemon's default repository
+    pub fn with_repo(mut self, repo: impl Into<String>) -> Self {
+        self.repo = Some(repo.into());
+        self
+    }
+
+    /// Socket this client connects to
+    pub fn socket(&self) -> &Path {
+        &self.socket
+    }
+
+    /// Send a request and return the raw reply. Replies with status ERROR
+    /// are returned as `DaemonError::Remote`.
+    pub async fn send(&self, request: &Request) -> DaemonResult<serde_json::Value> {
+        let stream = UnixStream::connect(&self.socket).await?;
+        let (reader, mut writer) = stream.into_split();
+
+        let mut message = serde_json::to_value(request)?;
+        if let (Some(repo), Some(fields)) = (&self.repo, message.as_object_mut()) {
+            fields.insert("repo".to_string(), repo.clone().into());
+        }
+
+        let mut line = serde_json::to_string(&message)?;
+        line.push('\n');
+        writer.write_all(line.as_bytes()).await?;
+        writer.flush().await?;
+
+        let mut reply = String::new();
+        BufReader::new(reader).read_line(&mut reply).await?;
+        if reply.is_empty() {
+            return Err(DaemonError::Io(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                "connection closed without a reply",
+            )));
+        }
+
+        let reply: serde_json::Value = serde_json::from_str(&reply)?;
+        if reply["status"] == "ERROR" {
+            return Err(DaemonError::Remote(serde_json::from_value(reply)?));
+        }
+
+        Ok(reply)
+    }
+
+    /// Send a request and decode its reply
+    async fn call<T: DeserializeOwned>(&self, request: Request) -> DaemonResult<T> {
+        let reply = self.send(&request).await?;
+        Ok(serde_json::from_value(reply)?)
+    }
+
+    /// Send a request that only replies with a status
+    async fn call_ok(&self, request: Request) -> DaemonResult<()> {
+        self.send(&request).await.map(|_| ())
+    }
+
+    /// Handshake: the daemon's protocol version, version and capabilities.
+    /// Fails if the daemon can't speak this client's protocol version.
+    pub async fn hello(&self) -> DaemonResult<Hello> {
+        self.call(Request::Hello {
+            protocol_version: Some(PROTOCOL_VERSION),
+            client_version: Some(env!("CARGO_PKG_VERSION").to_string()),
+        })
+        .await
+    }
+
+    /// Serve the repository at `path` (absolute), with `config` overriding
+    /// the daemon's configuration for it
+    pub async fn register_repo(
+        &self,
+        path: &Path,
+        config: Option<serde_json::Value>,
+    ) -> DaemonResult<RepoInfo> {
+        self.call(Request::RegisterRepo {
+            path: path.to_string_lossy().into_owned(),
+            config,
+        })
+        .await
+    }
+
+    /// Repositories the daemon serves
+    pub async fn repos(&self) -> DaemonResult<Vec<RepoInfo>> {
+        let reply: ReposReply = self.call(Request::Repos).await?;
+        Ok(reply.repos)
+    }
+
+    /// Register an agent without creating a worktree
+    pub async fn register(&self, agent_id: &str) -> DaemonResult<()> {
+        self.call_ok(Request::Register {
+            agent_id: agent_id.to_string(),
+            session_id: None,
+            base_branch: None,
+            create_worktree: false,
+        })
+        .await
+    }
+
+    /// Register an agent and create its worktree and branch from `base_branch`
+    pub async fn register_with_worktree(
+        &self,
+        agent_id: &str,
+        session_id: &str,
+        base_branch: &str,
+    ) -> DaemonResult<AgentWorktree> {
+        self.call(Request::Register {
+            agent_id: agent_id.to_string(),
+            session_id: Some(session_id.to_string()),
+            base_branch: Some(base_branch.to_string()),
+            create_worktree: true,
+        })
+        .await
+    }
+
+    /// Enqueue a branch for merging. Returns its position in the queue.
+    pub async fn enqueue(
+        &self,
+        agent_id: &str,
+        session_id: &str,
+        branch: &str,
+        worktree: &Path,
+        target_branch: &str,
+        options: EnqueueOptions,
+    ) -> DaemonResult<usize> {
+        let reply: PositionReply = self
+            .call(Request::Enqueue {
+                agent_id: agent_id.to_string(),
+                session_id: session_id.to_string(),
+                branch: branch.to_string(),
+                worktree: worktree.to_string_lossy().into_owned(),
+                target_branch: target_branch.to_string(),
+                priority: options.priority,
+                depends_on: options.depends_on,
+            })
+            .await?;
+        Ok(reply.position)
+    }
+
+    /// Commit an agent's worktree and enqueue it for merging
+    pub async fn commit_and_enqueue(
+        &self,
+        agent_id: &str,
+        session_id: &str,
+        worktree: &Path,
+        message: &str,
+        target_branch: &str,
+        tracked_only: bool,
+    ) -> DaemonResult<CommitResult> {
+        let reply = self
+            .send(&Request::CommitAndEnqueue {
+                agent_id: agent_id.to_string(),
+                session_id: session_id.to_string(),
+                worktree: worktree.to_string_lossy().into_owned(),
+                message: message.to_string(),
+                target_branch: target_branch.to_string(),
+                tracked_only,
+                priority: 0,
+                depends_on: Vec::new(),
+            })
+            .await?;
+
+        if reply["status"] == "NO_CHANGES" {
+            return Ok(CommitResult::NoChanges);
+        }
+
+        let reply: CommittedReply = serde_json::from_value(reply)?;
+        Ok(CommitResult::Committed {
+            commit: reply.commit,
+            position: reply.position,
+        })
+    }
+
+    /// Record a file written by an agent
+    pub async fn track_file(&self, agent_id: &str, path: &str) -> DaemonResult<()> {
+        self.call_ok(Request::TrackFile {
+            agent_id: agent_id.to_string(),
+            path: path.to_string(),
+        })
+        .await
+    }
+
+    /// Change the priority of a queued agent. Returns its new position.
+    pub async fn reprioritize(&self, agent_id: &str, priority: i32) -> DaemonResult<usize> {
+        let reply: PositionReply = self
+            .call(Request::Reprioritize {
+                agent_id: agent_id.to_string(),
+                priority,
+            })
+            .await?;
+        Ok(reply.position)
+    }
+
+    /// Remove an agent from the queue
+    pub async fn dequeue(&self, agent_id: &str) -> DaemonResult<()> {
+        self.call_ok(Request::Dequeue {
+            agent_id: agent_id.to_string(),
+        })
+        .await
+    }
+
+    /// Queue status summary
+    pub async fn status(&self) -> DaemonResult<QueueStatus> {
+        self.call(Request::Status).await
+    }
+
+    /// Files that conflicted in an agent's last merge attempt
+    pub async fn conflicts(&self, agent_id: &str) -> DaemonResult<Vec<String>> {
+        let reply: ConflictsReply = self
+            .call(Request::Conflicts {
+                agent_id: agent_id.to_string(),
+            })
+            .await?;
+        Ok(reply.files)
+    }
+
+    /// An agent's latest queue entry
+    pub async fn entry(&self, agent_id: &str) -> DaemonResult<QueueEntry> {
+        let reply: EntryReply = self
+            .call(Request::Entry {
+                agent_id: agent_id.to_string(),
+            })
+            .await?;
+        Ok(reply.entry)
+    }
+
+    /// Re-queue an agent after conflict resolution. Returns its position.
+    pub async fn retry(&self, agent_id: &str) -> DaemonResult<usize> {
+        let reply: PositionReply = self
+            .call(Request::Retry {
+                agent_id: agent_id.to_string(),
+            })
+            .await?;
+        Ok(reply.position)
+    }
+
+    /// Check whether a branch would merge cleanly, without merging it
+    pub async fn preview(&self, branch: &str, target_branch: &str) -> DaemonResult<MergePreview> {
+        self.call(Request::Preview {
+            branch: branch.to_string(),
+            target_branch: target_branch.to_string(),
+        })
+        .await
+    }
+
+    /// Wait for an agent's merge to finish or conflict, for at most
+    /// `timeout_secs` (the daemon's default if None)
+    pub async fn wait(
+        &self,
+        agent_id: &str,
+        timeout_secs: Option<u64>,
+    ) -> DaemonResult<MergeOutcome> {
+        self.call(Request::Wait {
+            agent_id: agent_id.to_string(),
+            timeout_secs,
+        })
+        .await
+    }
+
+    /// Completed merges, newest first
+    pub async fn history(
+        &self,
+        session_id: Option<&str>,
+        agent_id: Option<&str>,
+        limit: Option<usize>,
+    ) -> DaemonResult<Vec<MergeRecord>> {
+        let reply: HistoryReply = self
+            .call(Request::History {
+                session_id: session_id.map(str::to_string),
+                agent_id: agent_id.map(str::to_string),
+                limit,
+            })
+            .await?;
+        Ok(reply.merges)
+    }
+
+    /// End a session
+    pub async fn session_end(&self, session_id: &str) -> DaemonResult<()> {
+        self.call_ok(Request::SessionEnd {
+            session_id: session_id.to_string(),
+        })
+        .await
+    }
+
+    /// Merge all pending agents of a session in one octopus commit
+    pub async fn session_merge(&self, session_id: &str) -> DaemonResult<SessionMergeReport> {
+        self.call(Request::SessionMerge {
+            session_id: session_id.to_string(),
+        })
+        .await
+    }
+
+    /// Cancel and clean up stale sessions now
+    pub async fn reap(&self, dry_run: bool) -> DaemonResult<ReapReport> {
+        self.call(Request::Reap { dry_run }).await
+    }
+
+    /// List agent worktrees with their disk usage and state
+    pub async fn worktrees(&self) -> DaemonResult<Vec<WorktreeListing>> {
+        let reply: WorktreesReply = self.call(Request::Worktrees).await?;
+        Ok(reply.worktrees)
+    }
+
+    /// Remove an agent's worktree, and its branch if given
+    pub async fn worktree_remove(&self, worktree: &Path, branch: Option<&str>) -> DaemonResult<()> {
+        self.call_ok(Request::WorktreeRemove {
+            worktree: worktree.to_string_lossy().into_owned(),
+            branch: branch.map(str::to_string),
+        })
+        .await
+    }
+
+    /// Clean up worktrees left behind by crashed agents. Returns the
+    /// worktrees removed, or that would be removed on a dry run.
+    pub async fn worktree_prune(&self, dry_run: bool) -> DaemonResult<Vec<PathBuf>> {
+        let reply: PrunedReply = self.call(Request::WorktreePrune { dry_run }).await?;
+        Ok(reply.pruned)
+    }
+
+    /// Ask the daemon to shut down
+    pub async fn shutdown(&self) -> DaemonResult<()> {
+        self.call_ok(Request::Shutdown).await
+    }
+}
@@ -2,6 +2,8 @@
 
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::SocketAddr;
 use std::path::Path;
 
 /// Daemon configuration
@@ -16,9 +18,33 @@ pub struct Config {
     /// Maximum retry attempts for failed merges
     pub max_retries: u32,
 
-    /// Merge strategy: "merge", "rebase", or "squash"
+    /// Delay before the first automatic retry of a transient failure
+    #[serde(default = "default_retry_base_delay_ms")]
+    pub retry_base_delay_ms: u64,
+
+    /// Upper bound on the automatic retry delay
+    #[serde(default = "default_retry_max_delay_ms")]
+    pub retry_max_delay_ms: u64,
+
+    /// Merge strategy: "merge", "rebase", "squash", "fast_forward_only" or "octopus"
     pub merge_strategy: MergeStrategy,
 
+    /// Reject merges that would introduce merge commits into the target
+    #[serde(default)]
+    pub require_linear_history: bool,
+
+    /// Per-target-branch policies, first matching pattern wins
+    #[serde(default)]
+    pub branch_policies: Vec<BranchPolicy>,
+
+    /// How entries of equal priority are ordered across sessions
+    #[serde(default)]
+    pub scheduling_policy: SchedulingPolicy,
+
+    /// Share of merges per session under weighted fair scheduling (default 1)
+    #[serde(default)]
+    pub session_weights: HashMap<String, u32>,
+
     /// Timeout for merge operations in seconds
     pub merge_timeout_secs: u64,
 
@@ -37,13 +63,43 @@ pub struct Config {
     /// Whether to preserve worktrees on merge success (for debugging)
     pub preserve_worktrees: bool,
 
+    /// Number of pre-created worktrees to keep warm per pool branch
+    /// (0 disables the pool)
+    #[serde(default)]
+    pub worktree_pool_size: usize,
+
+    /// Target branches to keep pooled worktrees refreshed to
+    #[serde(default)]
+    pub worktree_pool_branches: Vec<String>,
+
     /// Cleanup stale sessions after this many seconds
     pub session_timeout_secs: u64,
+
+    /// Keep finished entries in memory this long before archiving them
+    #[serde(default = "default_archive_retention_secs")]
+    pub archive_retention_secs: u64,
+
+    /// On shutdown, wait this long for a running merge to finish before
+    /// aborting it
+    #[serde(default = "default_shutdown_drain_timeout_secs")]
+    pub shutdown_drain_timeout_secs: u64,
+
+    /// Which users may send which requests over the socket
+    #[serde(default)]
+    pub access: AccessPolicy,
+
+    /// Optional HTTP API on a loopback address
+    #[serde(default)]
+    pub http: HttpConfig,
+
+    /// Optional Prometheus metrics listener
+    #[serde(default)]
+    pub metrics: MetricsConfig,
 }
 
 /// Merge strategy options
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
-#[serde(rename_all = "lowercase")]
+#[serde(rename_all = "snake_case")]
 pub enum MergeStrategy {
     /// Standard merge commit
     Merge,
@@ -51,6 +107,123 @@ pub enum MergeStrategy {
     Rebase,
     /// Squash all commits into one
     Squash,
+    /// Only fast-forward; rebase first if `auto_rebase` is set, else fail
+    FastForwardOnly,
+    /// Hold entries until SESSION_MERGE, then merge them in one commit
+    Octopus,
+}
+
+/// Scheduling policy options
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SchedulingPolicy {
+    /// Oldest entry first
+    #[default]
+    Fifo,
+    /// Rotate between sessions, oldest entry first within a session
+    RoundRobin,
+    /// Share merges between sessions in proportion to their weights
+    WeightedFair,
+}
+
+/// Merge policy for target branches matching a glob
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BranchPolicy {
+    /// Glob matched against the target branch name, e.g. "release/*"
+    pub pattern: String,
+
+    /// Merge strategy for matching branches
+    pub merge_strategy: MergeStrategy,
+
+    /// Overrides `Config::require_linear_history` for matching branches
+    #[serde(default)]
+    pub require_linear_history: Option<bool>,
+
+    /// Overrides `Config::auto_rebase` for matching branches
+    #[serde(default)]
+    pub auto_rebase: Option<bool>,
+}
+
+/// Which users may talk to the daemon over its socket, checked against
+/// the peer credentials of each connection. The user running the daemon
+/// may always send any request.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccessPolicy {
+    /// Other users allowed to send requests
+    #[serde(default)]
+    pub allowed_uids: Vec<u32>,
+
+    /// Other users also allowed to send admin requests
+    #[serde(default)]
+    pub admin_uids: Vec<u32>,
+
+    /// Request types only admins may send, e.g. "SHUTDOWN"
+    #[serde(default = "default_admin_requests")]
+    pub admin_requests: Vec<String>,
+}
+
+impl Default for AccessPolicy {
+    fn default() -> Self {
+        Self {
+            allowed_uids: vec![],
+            admin_uids: vec![],
+            admin_requests: default_admin_requests(),
+        }
+    }
+}
+
+impl AccessPolicy {
+    /// Whether `uid` may send requests, given the daemon's own uid
+    pub fn allows(&self, owner_uid: u32, uid: u32) -> bool {
+        uid == owner_uid || self.allowed_uids.contains(&uid) || self.admin_uids.contains(&uid)
+    }
+
+    /// Whether `uid` may send admin requests, given the daemon's own uid
+    pub fn is_admin(&self, owner_uid: u32, uid: u32) -> bool {
+        uid == owner_uid || self.admin_uids.contains(&uid)
+    }
+
+    /// Whether requests of `request_type` are admin-only
+    pub fn is_admin_request(&self, request_type: &str) -> bool {
+        self.admin_requests.iter().any(|r| r == request_type)
+    }
+
+    /// Whether users other than the daemon's own may connect
+    pub fn is_shared(&self) -> bool {
+        !self.allowed_uids.is_empty() || !self.admin_uids.is_empty()
+    }
+}
+
+/// HTTP API for clients that can't reach the Unix socket, such as
+/// containers sharing the host's network
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HttpConfig {
+    /// Loopback address to listen on, e.g. "127.0.0.1:7420" (disabled if
+    /// unset)
+    #[serde(default)]
+    pub listen: Option<SocketAddr>,
+
+    /// Token clients must send as `Authorization: Bearer <token>`
+    #[serde(default)]
+    pub token: Option<String>,
+}
+
+/// Prometheus metrics, served without authentication on `GET /metrics` on
+/// a loopback address
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MetricsConfig {
+    /// Loopback address to listen on, e.g. "127.0.0.1:9464" (disabled if
+    /// unset)
+    #[serde(default)]
+    pub listen: Option<SocketAddr>,
+}
+
+/// Effective merge policy for a single target branch
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MergePolicy {
+    pub merge_strategy: MergeStrategy,
+    pub require_linear_history: bool,
+    pub auto_rebase: bool,
 }
 
 impl Default for Config {
@@ -59,30 +232,163 @@ impl Default for Config {
             max_queue_size: 100,
             max_concurrent_merges: 1,
             max_retries: 3,
+            retry_base_delay_ms: default_retry_base_delay_ms(),
+            retry_max_delay_ms: default_retry_max_delay_ms(),
             merge_strategy: MergeStrategy::Rebase,
+            require_linear_history: false,
+            branch_policies: vec![],
+            scheduling_policy: SchedulingPolicy::Fifo,
+            session_weights: HashMap::new(),
             merge_timeout_secs: 300,
             auto_rebase: true,
             agent_branch_prefix: "agent/".to_string(),
             feature_branch_prefix: "feature/".to_string(),
             worktree_dir: ".worktrees".to_string(),
             preserve_worktrees: false,
+            worktree_pool_size: 0,
+            worktree_pool_branches: vec![],
             session_timeout_secs: 3600,
+            archive_retention_secs: default_archive_retention_secs(),
+            shutdown_drain_timeout_secs: default_shutdown_drain_timeout_secs(),
+            access: AccessPolicy::default(),
+            http: HttpConfig::default(),
+            metrics: MetricsConfig::default(),
         }
     }
 }
 
+fn default_retry_base_delay_ms() -> u64 {
+    1_000
+}
+
+fn default_retry_max_delay_ms() -> u64 {
+    60_000
+}
+
+fn default_archive_retention_secs() -> u64 {
+    600
+}
+
+fn default_shutdown_drain_timeout_secs() -> u64 {
+    60
+}
+
+fn default_admin_requests() -> Vec<String> {
+    ["SHUTDOWN", "REGISTER_REPO", "REAP", "WORKTREE_PRUNE"]
+        .map(String::from)
+        .to_vec()
+}
+
 impl Config {
     /// Load configuration from a JSON file
     pub fn from_file(path: &Path) -> Result<Self> {
         let content = std::fs::read_to_string(path)?;
         let config: Self = serde_json::from_str(&content)?;
+        config.validate()?;
         Ok(config)
     }
 
+    /// This configuration with the top-level keys of a JSON object
+    /// replaced, e.g. `{"merge_strategy": "squash"}`
+    pub fn with_overrides(&self, overrides: &serde_json::Value) -> Result<Self> {
+        let serde_json::Value::Object(overrides) = overrides else {
+            anyhow::bail!("Configuration overrides must be a JSON object");
+        };
+
+        let mut merged = serde_json::to_value(self)?;
+        if let serde_json::Value::Object(fields) = &mut merged {
+            fields.extend(overrides.clone());
+        }
+
+        let config: Self = serde_json::from_value(merged)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Check values serde can't, such as branch policy patterns
+    fn validate(&self) -> Result<()> {
+        for policy in &self.branch_policies {
+            if let Err(e) = glob::Pattern::new(&policy.pattern) {
+                anyhow::bail!("Invalid branch policy pattern {:?}: {}", policy.pattern, e);
+            }
+        }
+        for request_type in &self.access.admin_requests {
+            if !crate::protocol::CAPABILITIES.contains(&request_type.as_str()) {
+                anyhow::bail!("Unknown admin request type {:?}", request_type);
+            }
+        }
+        if let Some(listen) = self.http.listen {
+            if !listen.ip().is_loopback() {
+                anyhow::bail!("HTTP API must listen on a loopback address, not {}", listen);
+            }
+            if self.http.token.as_deref().unwrap_or_default().is_empty() {
+                anyhow::bail!("HTTP API requires a token");
+            }
+        }
+        if let Some(listen) = self.metrics.listen {
+            if !listen.ip().is_loopback() {
+                anyhow::bail!("Metrics must listen on a loopback address, not {}", listen);
+            }
+        }
+        Ok(())
+    }
+
+    /// Resolve the merge policy for a target branch
+    pub fn policy_for(&self, target_branch: &str) -> MergePolicy {
+        let matched = self.branch_policies.iter().find(|p| {
+            glob::Pattern::new(&p.pattern)
+                .map(|pattern| pattern.matches(target_branch))
+                .unwrap_or(false)
+        });
+
+        match matched {
+            Some(policy) => MergePolicy {
+                merge_strategy: policy.merge_strategy,
+                require_linear_history: policy
+                    .require_linear_history
+                    .unwrap_or(self.require_linear_history),
+                auto_rebase: policy.auto_rebase.unwrap_or(self.auto_rebase),
+            },
+            None => MergePolicy {
+                merge_strategy: self.merge_strategy,
+                require_linear_history: self.require_linear_history,
+                auto_rebase: self.auto_rebase,
+            },
+        }
+    }
+
     /// Save configuration to a JSON file
+    #[allow(dead_code)]
     pub fn to_file(&self, path: &Path) -> Result<()> {
         let content = serde_json::to_string_pretty(self)?;
         std::fs::write(path, content)?;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(pattern: &str, require_linear_history: Option<bool>) -> BranchPolicy {
+        BranchPolicy {
+            pattern: pattern.to_string(),
+            merge_strategy: MergeStrategy::Merge,
+            require_linear_history,
+            auto_rebase: None,
+        }
+    }
+
+    #[test]
+    fn branch_policies_inherit_linear_history() {
+        let config = Config {
+            require_linear_history: true,
+            branch_policies: vec![policy("release/*", None), policy("scratch/*", Some(false))],
+            ..Config::default()
+        };
+
+        assert!(config.policy_for("main").require_linear_history);
+        assert!(config.policy_for("release/1.0").require_linear_history);
+        assert!(!config.policy_for("scratch/x").require_linear_history);
+    }
+}
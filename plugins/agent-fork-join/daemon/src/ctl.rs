@@ -0,0 +1,363 @@
+//! `merge-daemon ctl`: command line client for a running daemon
+
+use clap::{Args, Subcommand};
+use merge_daemon::client::DaemonClient;
+use merge_daemon::error::{DaemonError, DaemonResult};
+use merge_daemon::queue::EnqueueOptions;
+use merge_daemon::socket;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+/// Exit code of `ctl wait` when the merge is still pending at the timeout
+const EXIT_TIMED_OUT: u8 = 3;
+
+/// Exit code of `ctl wait` when the merge conflicted
+const EXIT_CONFLICT: u8 = 4;
+
+/// Exit code of `ctl wait` when the merge failed or was cancelled
+const EXIT_NOT_MERGED: u8 = 5;
+
+/// Exit code when the daemon can't be reached
+const EXIT_UNAVAILABLE: u8 = 69;
+
+/// Exit codes for daemon error codes. Other errors exit with 1; usage
+/// errors exit with 2.
+const ERROR_EXIT_CODES: &[(&str, u8)] = &[
+    ("MERGE_CONFLICT", EXIT_CONFLICT),
+    ("AGENT_NOT_FOUND", 10),
+    ("SESSION_NOT_FOUND", 11),
+    ("BRANCH_NOT_FOUND", 12),
+    ("QUEUE_FULL", 13),
+    ("AGENT_ALREADY_QUEUED", 14),
+    ("DEPENDENCY_CYCLE", 15),
+    ("MAX_RETRIES_EXCEEDED", 16),
+    ("SHUTTING_DOWN", 17),
+    ("INVALID_REQUEST", 18),
+    ("UNKNOWN_REQUEST", 18),
+    ("UNSUPPORTED_PROTOCOL", 18),
+    ("REPO_NOT_FOUND", 19),
+    ("PERMISSION_DENIED", 20),
+    ("WORKTREE_BUSY", 21),
+];
+
+#[derive(Args, Debug)]
+pub struct CtlArgs {
+    /// Print replies as JSON
+    #[arg(long, global = true)]
+    json: bool,
+
+    /// Repository to act on, as a path or repo ID (default: the daemon's
+    /// default repository)
+    #[arg(long = "repo", id = "ctl_repo", global = true)]
+    repo: Option<String>,
+
+    #[command(subcommand)]
+    command: CtlCommand,
+}
+
+#[derive(Subcommand, Debug)]
+enum CtlCommand {
+    /// Show the merge queue
+    Status,
+
+    /// Enqueue a branch for merging
+    Enqueue {
+        agent_id: String,
+        #[arg(long)]
+        session_id: String,
+        #[arg(long)]
+        branch: String,
+        #[arg(long)]
+        worktree: PathBuf,
+        /// Branch to merge into
+        #[arg(long)]
+        target: String,
+        /// Scheduling priority; higher values merge first
+        #[arg(long, default_value_t = 0, allow_negative_numbers = true)]
+        priority: i32,
+        /// Agent that must be merged first (repeatable)
+        #[arg(long)]
+        depends_on: Vec<String>,
+    },
+
+    /// Remove an agent from the queue
+    Dequeue { agent_id: String },
+
+    /// Re-queue an agent after resolving its conflicts
+    Retry { agent_id: String },
+
+    /// Wait for an agent's merge. Exits 3 on timeout, 4 on conflict and
+    /// 5 if the merge failed or was cancelled.
+    Wait {
+        agent_id: String,
+        /// Seconds to wait (default: the daemon's)
+        #[arg(long)]
+        timeout: Option<u64>,
+    },
+
+    /// List completed merges, newest first
+    History {
+        #[arg(long)]
+        session_id: Option<String>,
+        #[arg(long)]
+        agent_id: Option<String>,
+        #[arg(long)]
+        limit: Option<usize>,
+    },
+
+    /// List the files an agent conflicted on
+    Conflicts { agent_id: String },
+
+    /// List the repositories the daemon serves
+    Repos,
+
+    /// Serve another repository from this daemon
+    RegisterRepo {
+        path: PathBuf,
+        /// Configuration overrides for the repository, as a JSON object
+        #[arg(long)]
+        config: Option<String>,
+    },
+}
+
+/// Run a ctl command against the daemon on `socket`, or the default socket
+/// of the repository given with `--repo` or the current directory
+pub async fn run(socket: Option<&Path>, args: CtlArgs) -> ExitCode {
+    // The daemon needs absolute paths; anything else is a repo ID
+    let repo_path = args
+        .repo
+        .as_ref()
+        .and_then(|repo| std::fs::canonicalize(repo).ok());
+
+    let result = match socket {
+        Some(socket) => Ok(socket.to_path_buf()),
+        None => socket::default_path(repo_path.as_deref().unwrap_or(Path::new("."))),
+    };
+    let result = match result {
+        Ok(socket) => {
+            let mut client = DaemonClient::new(socket);
+            if let Some(repo) = &args.repo {
+                client = client.with_repo(match &repo_path {
+                    Some(path) => path.to_string_lossy().into_owned(),
+                    None => repo.clone(),
+                });
+            }
+            execute(&client, args.command, args.json).await
+        }
+        Err(e) => Err(e),
+    };
+
+    match result {
+        Ok(code) => code,
+        Err(e) => {
+            if args.json {
+                print_json(&serde_json::json!({
+                    "status": "ERROR",
+                    "error": e.to_string(),
+                    "code": e.code(),
+                    "retryable": e.retryable(),
+                    "details": e.details(),
+                }));
+            } else {
+                eprintln!("merge-daemon: {}", e);
+            }
+            ExitCode::from(exit_code_for(&e))
+        }
+    }
+}
+
+async fn execute(client: &DaemonClient, command: CtlCommand, json: bool) -> DaemonResult<ExitCode> {
+    match command {
+        CtlCommand::Status => {
+            let status = client.status().await?;
+            if json {
+                print_json(&status);
+                return Ok(ExitCode::SUCCESS);
+            }
+
+            println!(
+                "Queue: {} active ({} pending, {} processing)",
+                status.length, status.pending, status.processing
+            );
+            for agent in &status.agents {
+                println!("  {}", agent);
+            }
+            if !status.retrying.is_empty() {
+                println!("Retrying:");
+                for retry in &status.retrying {
+                    println!(
+                        "  {} (attempt {}, next at {}): {}",
+                        retry.agent_id,
+                        retry.attempts,
+                        retry.next_retry_at.format("%Y-%m-%d %H:%M:%S"),
+                        retry.last_error.as_deref().unwrap_or("unknown error")
+                    );
+                }
+            }
+            if !status.awaiting.is_empty() {
+                println!("Awaiting dependencies:");
+                for awaited in &status.awaiting {
+                    println!("  {} (on {})", awaited.agent_id, awaited.missing.join(", "));
+                }
+            }
+        }
+
+        CtlCommand::Enqueue {
+            agent_id,
+            session_id,
+            branch,
+            worktree,
+            target,
+            priority,
+            depends_on,
+        } => {
+            let position = client
+                .enqueue(
+                    &agent_id,
+                    &session_id,
+                    &branch,
+                    &worktree,
+                    &target,
+                    EnqueueOptions {
+                        priority,
+                        depends_on,
+                    },
+                )
+                .await?;
+            print_position(json, "Enqueued", &agent_id, position);
+        }
+
+        CtlCommand::Dequeue { agent_id } => {
+            client.dequeue(&agent_id).await?;
+            if json {
+                print_json(&serde_json::json!({ "status": "OK", "agent_id": agent_id }));
+            } else {
+                println!("Dequeued {}", agent_id);
+            }
+        }
+
+        CtlCommand::Retry { agent_id } => {
+            let position = client.retry(&agent_id).await?;
+            print_position(json, "Re-queued", &agent_id, position);
+        }
+
+        CtlCommand::Wait { agent_id, timeout } => {
+            let outcome = client.wait(&agent_id, timeout).await?;
+            if json {
+                print_json(&outcome);
+            } else {
+                match &outcome.details {
+                    Some(details) => println!("{}: {} ({})", agent_id, outcome.result, details),
+                    None => println!("{}: {}", agent_id, outcome.result),
+                }
+            }
+
+            return Ok(match outcome.result.as_str() {
+                "MERGED" => ExitCode::SUCCESS,
+                "PENDING" | "PROCESSING" => ExitCode::from(EXIT_TIMED_OUT),
+                "CONFLICT" => ExitCode::from(EXIT_CONFLICT),
+                _ => ExitCode::from(EXIT_NOT_MERGED),
+            });
+        }
+
+        CtlCommand::History {
+            session_id,
+            agent_id,
+            limit,
+        } => {
+            let merges = client
+                .history(session_id.as_deref(), agent_id.as_deref(), limit)
+                .await?;
+            if json {
+                print_json(&merges);
+                return Ok(ExitCode::SUCCESS);
+            }
+
+            if merges.is_empty() {
+                println!("No merges");
+            }
+            for merge in &merges {
+                let commit = merge.commit_sha.as_deref().unwrap_or("-");
+                println!(
+                    "{}  {:.10}  {}  {}",
+                    merge.merged_at, commit, merge.agent_id, merge.session_id
+                );
+            }
+        }
+
+        CtlCommand::Conflicts { agent_id } => {
+            let files = client.conflicts(&agent_id).await?;
+            if json {
+                print_json(&files);
+            } else if files.is_empty() {
+                println!("No conflicts for {}", agent_id);
+            } else {
+                for file in &files {
+                    println!("{}", file);
+                }
+            }
+        }
+
+        CtlCommand::Repos => {
+            let repos = client.repos().await?;
+            if json {
+                print_json(&repos);
+                return Ok(ExitCode::SUCCESS);
+            }
+
+            for repo in &repos {
+                let default = if repo.default { "  (default)" } else { "" };
+                println!("{}  {}{}", repo.repo_id, repo.path.display(), default);
+            }
+        }
+
+        CtlCommand::RegisterRepo { path, config } => {
+            let path = std::fs::canonicalize(&path)
+                .map_err(|_| DaemonError::RepoNotFound(path.display().to_string()))?;
+            let config = config
+                .map(|config| serde_json::from_str(&config))
+                .transpose()?;
+            let repo = client.register_repo(&path, config).await?;
+            if json {
+                print_json(&repo);
+            } else {
+                println!("Registered {} as {}", repo.path.display(), repo.repo_id);
+            }
+        }
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Exit code for a failed command
+fn exit_code_for(error: &DaemonError) -> u8 {
+    // Local I/O errors mean the socket couldn't be reached
+    if let DaemonError::Io(_) = error {
+        return EXIT_UNAVAILABLE;
+    }
+
+    ERROR_EXIT_CODES
+        .iter()
+        .find(|(code, _)| *code == error.code())
+        .map_or(1, |(_, exit_code)| *exit_code)
+}
+
+fn print_position(json: bool, action: &str, agent_id: &str, position: usize) {
+    if json {
+        print_json(&serde_json::json!({
+            "status": "OK",
+            "agent_id": agent_id,
+            "position": position,
+        }));
+    } else {
+        println!("{} {} at position {}", action, agent_id, position);
+    }
+}
+
+fn print_json<T: Serialize>(value: &T) {
+    match serde_json::to_string_pretty(value) {
+        Ok(json) => println!("{}", json),
+        Err(e) => eprintln!("merge-daemon: {}", e),
+    }
+}
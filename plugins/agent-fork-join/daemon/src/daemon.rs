@@ -0,0 +1,263 @@
+//! Running the merge daemon, in-process or behind a socket
+
+use crate::config::Config;
+use crate::error::{DaemonError, DaemonResult};
+use crate::handler;
+use crate::http::HttpServer;
+use crate::ipc::{Connections, IpcServer};
+use crate::pidfile::PidFile;
+use crate::protocol::{Request, Response};
+use crate::queue::MergeQueue;
+use crate::repos::Repos;
+use crate::state::StateManager;
+use std::net::SocketAddr;
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::task::JoinHandle;
+use tokio::time::{Duration, Instant};
+use tracing::{info, warn};
+
+/// Configures and starts a [`Daemon`]
+///
+/// By default the daemon keeps its state in memory and doesn't listen on
+/// a socket; requests go through [`Daemon::handle`]. The HTTP API and
+/// the metrics listener are started when the configuration sets
+/// `http.listen` and `metrics.listen`.
+///
+/// ```no_run
+/// # async fn example() -> merge_daemon::DaemonResult<()> {
+/// use merge_daemon::{DaemonBuilder, Request};
+///
+/// let daemon = DaemonBuilder::new("/path/to/repo").start().await?;
+/// let status = daemon.handle(Request::Status).await;
+/// daemon.shutdown().await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct DaemonBuilder {
+    repo_path: PathBuf,
+    config: Config,
+    db_path: Option<PathBuf>,
+    socket_path: Option<PathBuf>,
+}
+
+impl DaemonBuilder {
+    /// Start configuring a daemon for the repository at `repo_path`
+    pub fn new(repo_path: impl Into<PathBuf>) -> Self {
+        Self {
+            repo_path: repo_path.into(),
+            config: Config::default(),
+            db_path: None,
+            socket_path: None,
+        }
+    }
+
+    /// Use `config` instead of the default configuration
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Persist state in the SQLite database at `path`, recovering any
+    /// pending merges found there. Only one daemon may use a database at a
+    /// time; it holds a lock on `daemon.pid` next to it.
+    pub fn db_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.db_path = Some(path.into());
+        self
+    }
+
+    /// Also serve requests on the Unix socket at `path`
+    pub fn socket(mut self, path: impl Into<PathBuf>) -> Self {
+        self.socket_path = Some(path.into());
+        self
+    }
+
+    /// Recover state and start the daemon's background tasks. Fails with
+    /// `AlreadyRunning` if another daemon uses the same database, or
+    /// `SocketInUse` if another daemon listens on the socket.
+    pub async fn start(self) -> DaemonResult<Daemon> {
+        let pid_file = match &self.db_path {
+            Some(db_path) => Some(PidFile::acquire(PidFile::for_db(db_path))?),
+            None => None,
+        };
+
+        if let Some(socket_path) = &self.socket_path {
+            if UnixStream::connect(socket_path).is_ok() {
+                if let Some(pid_file) = pid_file {
+                    pid_file.release();
+                }
+                return Err(DaemonError::SocketInUse(socket_path.clone()));
+            }
+        }
+
+        // Initialize state manager (persistent storage)
+        let state_manager = match &self.db_path {
+            Some(db_path) => {
+                let state_manager = StateManager::new(db_path).await?;
+                info!("State database initialized at {:?}", db_path);
+                state_manager
+            }
+            None => StateManager::in_memory().await?,
+        };
+
+        let drain_timeout = Duration::from_secs(self.config.shutdown_drain_timeout_secs);
+        let access = self.config.access.clone();
+        let http = self.config.http.clone();
+        let metrics_listen = self.config.metrics.listen;
+        let repos =
+            Arc::new(Repos::open(self.repo_path, state_manager.clone(), self.config).await?);
+
+        let mut servers = Vec::new();
+        let mut connections: Option<Arc<Connections>> = None;
+        let mut http_addr = None;
+
+        if let Some(socket_path) = &self.socket_path {
+            // Remove stale socket file if it exists
+            if socket_path.exists() {
+                warn!("Removing stale socket file: {:?}", socket_path);
+                std::fs::remove_file(socket_path)?;
+            }
+
+            let ipc = IpcServer::new(
+                socket_path.clone(),
+                repos.clone(),
+                state_manager.clone(),
+                access,
+            )?;
+            connections = Some(ipc.connections());
+            servers.push(tokio::spawn(async move {
+                if let Err(e) = ipc.run().await {
+                    tracing::error!("IPC server error: {}", e);
+                }
+            }));
+        }
+
+        if let Some(listen) = http.listen {
+            let token = http.token.unwrap_or_default();
+            let connections = connections.get_or_insert_with(Arc::default).clone();
+            let server = HttpServer::bind(listen, token, repos.clone(), connections).await?;
+            http_addr = Some(server.local_addr());
+            servers.push(tokio::spawn(async move {
+                if let Err(e) = server.run().await {
+                    tracing::error!("HTTP server error: {}", e);
+                }
+            }));
+        }
+
+        let mut metrics_addr = None;
+        if let Some(listen) = metrics_listen {
+            let connections = connections.get_or_insert_with(Arc::default).clone();
+            let server = HttpServer::bind_metrics(listen, repos.clone(), connections).await?;
+            metrics_addr = Some(server.local_addr());
+            servers.push(tokio::spawn(async move {
+                if let Err(e) = server.run().await {
+                    tracing::error!("Metrics server error: {}", e);
+                }
+            }));
+        }
+
+        Ok(Daemon {
+            repos,
+            state_manager,
+            socket_path: self.socket_path,
+            pid_file,
+            drain_timeout,
+            servers,
+            connections,
+            http_addr,
+            metrics_addr,
+        })
+    }
+}
+
+/// A running merge daemon
+pub struct Daemon {
+    repos: Arc<Repos>,
+    state_manager: StateManager,
+    socket_path: Option<PathBuf>,
+    pid_file: Option<PidFile>,
+    drain_timeout: Duration,
+    servers: Vec<JoinHandle<()>>,
+    connections: Option<Arc<Connections>>,
+    http_addr: Option<SocketAddr>,
+    metrics_addr: Option<SocketAddr>,
+}
+
+impl Daemon {
+    /// The default repository's merge queue
+    pub fn queue(&self) -> &MergeQueue {
+        self.repos.default_queue()
+    }
+
+    /// The repositories the daemon serves
+    pub fn repos(&self) -> &Repos {
+        &self.repos
+    }
+
+    /// The address the HTTP API listens on, if enabled
+    pub fn http_addr(&self) -> Option<SocketAddr> {
+        self.http_addr
+    }
+
+    /// The address metrics are served on, if enabled
+    pub fn metrics_addr(&self) -> Option<SocketAddr> {
+        self.metrics_addr
+    }
+
+    /// The daemon's persistent state
+    pub fn state(&self) -> &StateManager {
+        &self.state_manager
+    }
+
+    /// Handle a request in-process, as if it had come in on the socket
+    /// without a `repo`
+    pub async fn handle(&self, request: Request) -> Response {
+        handler::route_request(request, None, &self.repos).await
+    }
+
+    /// Handle a request in-process for `repo`, the ID or absolute path of
+    /// a registered repository
+    pub async fn handle_in(&self, repo: &str, request: Request) -> Response {
+        handler::route_request(request, Some(repo), &self.repos).await
+    }
+
+    /// Resolves once shutdown has started, e.g. by a SHUTDOWN request
+    pub async fn shutdown_requested(&self) {
+        self.repos.shutdown_requested().await;
+    }
+
+    /// Shut down gracefully. New merges are rejected with `ShuttingDown`
+    /// while the merges in progress finish, for at most the drain timeout.
+    /// Then state is saved, the background tasks are stopped and the
+    /// socket and PID file are removed.
+    pub async fn shutdown(mut self) -> DaemonResult<()> {
+        let deadline = Instant::now() + self.drain_timeout;
+        self.repos.stop(deadline).await?;
+
+        // Let clients receive their last replies
+        if let Some(connections) = &self.connections {
+            if tokio::time::timeout_at(deadline, connections.closed())
+                .await
+                .is_err()
+            {
+                warn!("Closing {} open client connection(s)", connections.open());
+            }
+        }
+        for server in &self.servers {
+            server.abort();
+        }
+
+        if let Some(socket_path) = &self.socket_path {
+            if socket_path.exists() {
+                std::fs::remove_file(socket_path)?;
+            }
+        }
+        if let Some(pid_file) = self.pid_file.take() {
+            pid_file.release();
+        }
+
+        Ok(())
+    }
+}
@@ -1,9 +1,12 @@
 //! Error types for the merge daemon
 
+use serde::{Deserialize, Serialize};
+use serde_json::json;
 use thiserror::Error;
 
 /// Errors that can occur in the merge daemon
 #[derive(Error, Debug)]
+#[allow(dead_code)]
 pub enum DaemonError {
     #[error("Git error: {0}")]
     Git(#[from] git2::Error),
@@ -26,6 +29,9 @@ pub enum DaemonError {
     #[error("Branch not found: {0}")]
     BranchNotFound(String),
 
+    #[error("Repository not found: {0}")]
+    RepoNotFound(String),
+
     #[error("Merge conflict in files: {0:?}")]
     MergeConflict(Vec<String>),
 
@@ -35,12 +41,21 @@ pub enum DaemonError {
     #[error("Agent already in queue: {0}")]
     AgentAlreadyQueued(String),
 
+    #[error("Dependency cycle involving agent: {0}")]
+    DependencyCycle(String),
+
     #[error("Invalid request: {0}")]
     InvalidRequest(String),
 
     #[error("Worktree error: {0}")]
     Worktree(String),
 
+    #[error("Worktree {worktree:?} is in use by the merge of agent {agent_id}")]
+    WorktreeBusy {
+        worktree: std::path::PathBuf,
+        agent_id: String,
+    },
+
     #[error("Rebase failed: {0}")]
     RebaseFailed(String),
 
@@ -52,7 +67,169 @@ pub enum DaemonError {
 
     #[error("Configuration error: {0}")]
     Config(String),
+
+    #[error(
+        "Merge daemon already running (pid {})",
+        .0.map_or_else(|| "unknown".to_string(), |pid| pid.to_string())
+    )]
+    AlreadyRunning(Option<u32>),
+
+    #[error("Socket already in use by another daemon: {0:?}")]
+    SocketInUse(std::path::PathBuf),
+
+    #[error("Refusing to use socket directory {dir:?}: {reason}")]
+    UnsafeSocketDir {
+        dir: std::path::PathBuf,
+        reason: String,
+    },
+
+    #[error("Permission denied: uid {uid} may not send {request}")]
+    PermissionDenied { request: String, uid: u32 },
+
+    #[error("Missing or invalid bearer token")]
+    Unauthorized,
+
+    /// An error reported by the daemon to a client
+    #[error("{}", .0.error)]
+    Remote(RemoteError),
+}
+
+/// An error response as received by a client
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemoteError {
+    pub error: String,
+    /// UNKNOWN for daemons that predate error codes
+    #[serde(default = "unknown_code")]
+    pub code: String,
+    #[serde(default)]
+    pub retryable: bool,
+    #[serde(default)]
+    pub details: Option<serde_json::Value>,
+}
+
+fn unknown_code() -> String {
+    "UNKNOWN".to_string()
+}
+
+impl DaemonError {
+    /// Machine-readable error code, stable across releases
+    pub fn code(&self) -> &str {
+        match self {
+            DaemonError::Git(_) => "GIT",
+            DaemonError::Io(_) => "IO",
+            DaemonError::Database(_) => "DATABASE",
+            DaemonError::Json(_) => "JSON",
+            DaemonError::AgentNotFound(_) => "AGENT_NOT_FOUND",
+            DaemonError::SessionNotFound(_) => "SESSION_NOT_FOUND",
+            DaemonError::BranchNotFound(_) => "BRANCH_NOT_FOUND",
+            DaemonError::RepoNotFound(_) => "REPO_NOT_FOUND",
+            DaemonError::MergeConflict(_) => "MERGE_CONFLICT",
+            DaemonError::QueueFull(_) => "QUEUE_FULL",
+            DaemonError::AgentAlreadyQueued(_) => "AGENT_ALREADY_QUEUED",
+            DaemonError::DependencyCycle(_) => "DEPENDENCY_CYCLE",
+            DaemonError::InvalidRequest(_) => "INVALID_REQUEST",
+            DaemonError::Worktree(_) => "WORKTREE",
+            DaemonError::WorktreeBusy { .. } => "WORKTREE_BUSY",
+            DaemonError::RebaseFailed(_) => "REBASE_FAILED",
+            DaemonError::MaxRetriesExceeded(_) => "MAX_RETRIES_EXCEEDED",
+            DaemonError::ShuttingDown => "SHUTTING_DOWN",
+            DaemonError::Config(_) => "CONFIG",
+            DaemonError::AlreadyRunning(_) => "ALREADY_RUNNING",
+            DaemonError::SocketInUse(_) => "SOCKET_IN_USE",
+            DaemonError::UnsafeSocketDir { .. } => "UNSAFE_SOCKET_DIR",
+            DaemonError::PermissionDenied { .. } => "PERMISSION_DENIED",
+            DaemonError::Unauthorized => "UNAUTHORIZED",
+            DaemonError::Remote(remote) => &remote.code,
+        }
+    }
+
+    /// Whether the same request may succeed if sent again later
+    pub fn retryable(&self) -> bool {
+        match self {
+            DaemonError::Git(e) => crate::merger::is_transient(e),
+            DaemonError::Io(e) => matches!(
+                e.kind(),
+                std::io::ErrorKind::Interrupted
+                    | std::io::ErrorKind::WouldBlock
+                    | std::io::ErrorKind::TimedOut
+                    | std::io::ErrorKind::ResourceBusy
+                    | std::io::ErrorKind::ConnectionRefused
+                    | std::io::ErrorKind::ConnectionReset
+                    | std::io::ErrorKind::ConnectionAborted
+            ),
+            // Another connection holds a lock; constraint violations and
+            // the like fail the same way every time
+            DaemonError::Database(e) => matches!(
+                e.sqlite_error_code(),
+                Some(rusqlite::ErrorCode::DatabaseBusy | rusqlite::ErrorCode::DatabaseLocked)
+            ),
+            DaemonError::QueueFull(_)
+            | DaemonError::ShuttingDown
+            | DaemonError::WorktreeBusy { .. } => true,
+            DaemonError::Remote(remote) => remote.retryable,
+            _ => false,
+        }
+    }
+
+    /// Variant-specific fields for clients, such as the queue limit
+    pub fn details(&self) -> Option<serde_json::Value> {
+        match self {
+            DaemonError::AgentNotFound(agent_id)
+            | DaemonError::AgentAlreadyQueued(agent_id)
+            | DaemonError::DependencyCycle(agent_id)
+            | DaemonError::MaxRetriesExceeded(agent_id) => Some(json!({ "agent_id": agent_id })),
+            DaemonError::SessionNotFound(session_id) => Some(json!({ "session_id": session_id })),
+            DaemonError::BranchNotFound(branch) => Some(json!({ "branch": branch })),
+            DaemonError::RepoNotFound(repo) => Some(json!({ "repo": repo })),
+            DaemonError::MergeConflict(files) => Some(json!({ "files": files })),
+            DaemonError::QueueFull(limit) => Some(json!({ "limit": limit })),
+            DaemonError::AlreadyRunning(pid) => Some(json!({ "pid": pid })),
+            DaemonError::SocketInUse(socket) => Some(json!({ "socket": socket })),
+            DaemonError::WorktreeBusy { worktree, agent_id } => {
+                Some(json!({ "worktree": worktree, "agent_id": agent_id }))
+            }
+            DaemonError::UnsafeSocketDir { dir, reason } => {
+                Some(json!({ "dir": dir, "reason": reason }))
+            }
+            DaemonError::PermissionDenied { request, uid } => {
+                Some(json!({ "request": request, "uid": uid }))
+            }
+            DaemonError::Remote(remote) => remote.details.clone(),
+            _ => None,
+        }
+    }
 }
 
 /// Result type alias for daemon operations
 pub type DaemonResult<T> = Result<T, DaemonError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::ErrorKind;
+
+    #[test]
+    fn only_transient_failures_are_retryable() {
+        let git = |code| DaemonError::Git(git2::Error::new(code, git2::ErrorClass::Index, "git"));
+        assert!(git(git2::ErrorCode::Locked).retryable());
+        assert!(git(git2::ErrorCode::Modified).retryable());
+        assert!(!git(git2::ErrorCode::Conflict).retryable());
+
+        let io = |kind| DaemonError::Io(std::io::Error::from(kind));
+        assert!(io(ErrorKind::Interrupted).retryable());
+        assert!(io(ErrorKind::TimedOut).retryable());
+        assert!(!io(ErrorKind::NotFound).retryable());
+        assert!(!io(ErrorKind::PermissionDenied).retryable());
+
+        let sqlite = |code| {
+            DaemonError::Database(rusqlite::Error::SqliteFailure(
+                rusqlite::ffi::Error::new(code),
+                None,
+            ))
+        };
+        assert!(sqlite(rusqlite::ffi::SQLITE_BUSY).retryable());
+        assert!(sqlite(rusqlite::ffi::SQLITE_LOCKED).retryable());
+        assert!(!sqlite(rusqlite::ffi::SQLITE_CONSTRAINT).retryable());
+        assert!(!DaemonError::Database(rusqlite::Error::QueryReturnedNoRows).retryable());
+    }
+}
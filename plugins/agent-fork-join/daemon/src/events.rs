@@ -0,0 +1,23 @@
+//! Events broadcast by the daemon to interested subscribers
+
+use crate::queue::{QueueEntry, ReapReport};
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+/// Capacity of the event channel; slow subscribers miss older events
+const EVENT_CHANNEL_CAPACITY: usize = 256;
+
+/// Something noteworthy that happened in the daemon
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum DaemonEvent {
+    /// A queue entry was added or changed, e.g. its merge finished
+    EntryUpdated(QueueEntry),
+    /// Stale sessions and entries were cancelled and cleaned up
+    Reaped(ReapReport),
+}
+
+/// Create the daemon's event channel
+pub fn channel() -> broadcast::Sender<DaemonEvent> {
+    broadcast::channel(EVENT_CHANNEL_CAPACITY).0
+}
@@ -0,0 +1,362 @@
+//! Request handling, independent of the transport the request came in on
+
+use crate::error::DaemonError;
+use crate::protocol::{
+    Hello, MergeOutcome, Request, RequestError, Response, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION,
+};
+use crate::queue::{CommitOptions, EnqueueOptions, MergeQueue};
+use crate::repos::Repos;
+use std::path::{Path, PathBuf};
+use tracing::{debug, info};
+
+/// How long a WAIT request blocks unless it gives its own timeout
+const DEFAULT_WAIT_TIMEOUT_SECS: u64 = 300;
+
+/// Merges returned by HISTORY unless it gives its own limit
+const DEFAULT_HISTORY_LIMIT: usize = 50;
+
+/// Handle a request for the repository `repo`, a repo ID or absolute
+/// path, or the default repository if none is given
+pub async fn route_request(request: Request, repo: Option<&str>, repos: &Repos) -> Response {
+    match request {
+        Request::RegisterRepo { path, config } => {
+            match repos.register(Path::new(&path), config.as_ref()).await {
+                Ok(repo) => Response::Repo { status: "OK", repo },
+                Err(e) => Response::error(&e),
+            }
+        }
+
+        Request::Repos => Response::Repos {
+            status: "OK",
+            repos: repos.list().await,
+        },
+
+        // The handshake doesn't depend on the repository, so a client can
+        // send it before its repository is registered
+        Request::Hello {
+            protocol_version,
+            client_version,
+        } => hello(protocol_version, client_version.as_deref()),
+
+        request => match repos.resolve(repo).await {
+            Ok(queue) => handle_request(request, &queue).await,
+            Err(e) => Response::error(&e),
+        },
+    }
+}
+
+/// Answer a HELLO, rejecting protocol versions the daemon can't speak
+fn hello(protocol_version: Option<u32>, client_version: Option<&str>) -> Response {
+    debug!(
+        "Hello from client {} (protocol {:?})",
+        client_version.unwrap_or("unknown"),
+        protocol_version
+    );
+
+    match protocol_version {
+        Some(version) if !(MIN_PROTOCOL_VERSION..=PROTOCOL_VERSION).contains(&version) => {
+            Response::rejected(RequestError::UnsupportedProtocol {
+                protocol_version: version,
+            })
+        }
+        _ => Response::Hello {
+            status: "OK",
+            hello: Hello::current(),
+        },
+    }
+}
+
+/// Handle a single request against the queue
+pub async fn handle_request(request: Request, queue: &MergeQueue) -> Response {
+    match request {
+        Request::Hello {
+            protocol_version,
+            client_version,
+        } => hello(protocol_version, client_version.as_deref()),
+
+        Request::RegisterRepo { .. } | Request::Repos => Response::error(
+            &DaemonError::InvalidRequest("repositories are managed by the daemon".to_string()),
+        ),
+
+        Request::Register {
+            agent_id,
+            session_id,
+            base_branch,
+            create_worktree,
+        } => {
+            debug!("Registered agent: {}", agent_id);
+
+            if !create_worktree {
+                return Response::Ok { status: "OK" };
+            }
+
+            let (Some(session_id), Some(base_branch)) = (session_id, base_branch) else {
+                return Response::error(&DaemonError::InvalidRequest(
+                    "create_worktree requires session_id and base_branch".to_string(),
+                ));
+            };
+
+            match queue
+                .worktrees()
+                .create(&agent_id, &session_id, &base_branch)
+            {
+                Ok(worktree) => Response::Worktree {
+                    status: "OK",
+                    worktree,
+                },
+                Err(e) => Response::error(&e),
+            }
+        }
+
+        Request::Enqueue {
+            agent_id,
+            session_id,
+            branch,
+            worktree,
+            target_branch,
+            priority,
+            depends_on,
+        } => {
+            match queue
+                .enqueue(
+                    agent_id,
+                    session_id,
+                    branch,
+                    PathBuf::from(worktree),
+                    target_branch,
+                    EnqueueOptions {
+                        priority,
+                        depends_on,
+                    },
+                )
+                .await
+            {
+                Ok(position) => Response::Position {
+                    status: "OK",
+                    position,
+                },
+                Err(e) => Response::error(&e),
+            }
+        }
+
+        Request::Dequeue { agent_id } => match queue.dequeue(&agent_id).await {
+            Ok(_) => Response::Ok { status: "OK" },
+            Err(e) => Response::error(&e),
+        },
+
+        Request::CommitAndEnqueue {
+            agent_id,
+            session_id,
+            worktree,
+            message,
+            target_branch,
+            tracked_only,
+            priority,
+            depends_on,
+        } => {
+            match queue
+                .commit_and_enqueue(
+                    agent_id.clone(),
+                    session_id,
+                    PathBuf::from(worktree),
+                    target_branch,
+                    CommitOptions {
+                        message,
+                        tracked_only,
+                    },
+                    EnqueueOptions {
+                        priority,
+                        depends_on,
+                    },
+                )
+                .await
+            {
+                Ok(Some((commit, position))) => Response::Committed {
+                    status: "OK",
+                    commit,
+                    position,
+                },
+                Ok(None) => Response::NoChanges {
+                    status: "NO_CHANGES",
+                    agent_id,
+                },
+                Err(e) => Response::error(&e),
+            }
+        }
+
+        Request::TrackFile { agent_id, path } => match queue.track_file(&agent_id, &path).await {
+            Ok(()) => Response::Ok { status: "OK" },
+            Err(e) => Response::error(&e),
+        },
+
+        Request::Reprioritize { agent_id, priority } => {
+            match queue.reprioritize(&agent_id, priority).await {
+                Ok(position) => Response::Position {
+                    status: "OK",
+                    position,
+                },
+                Err(e) => Response::error(&e),
+            }
+        }
+
+        Request::Status => Response::Status {
+            status: "OK",
+            queue: queue.status().await,
+        },
+
+        Request::Conflicts { agent_id } => match queue.get_conflicts(&agent_id).await {
+            Ok(files) => Response::Conflicts {
+                status: "OK",
+                files,
+            },
+            Err(e) => Response::error(&e),
+        },
+
+        Request::Entry { agent_id } => match queue.latest_entry(&agent_id).await {
+            Ok(Some(entry)) => Response::Entry {
+                status: "OK",
+                entry,
+            },
+            Ok(None) => Response::error(&DaemonError::AgentNotFound(agent_id)),
+            Err(e) => Response::error(&e),
+        },
+
+        Request::Retry { agent_id } => match queue.retry(&agent_id).await {
+            Ok(position) => Response::Position {
+                status: "OK",
+                position,
+            },
+            Err(e) => Response::error(&e),
+        },
+
+        Request::Preview {
+            branch,
+            target_branch,
+        } => match queue.preview(&branch, &target_branch).await {
+            Ok(preview) => Response::Preview {
+                status: "OK",
+                preview,
+            },
+            Err(e) => Response::error(&e),
+        },
+
+        Request::Wait {
+            agent_id,
+            timeout_secs,
+        } => {
+            debug!("Wait requested for agent: {}", agent_id);
+            let timeout = std::time::Duration::from_secs(
+                timeout_secs.unwrap_or(DEFAULT_WAIT_TIMEOUT_SECS),
+            );
+
+            match queue.wait(&agent_id, timeout).await {
+                Ok(entry) => {
+                    let details = if entry.conflict_files.is_empty() {
+                        entry.last_error
+                    } else {
+                        Some(entry.conflict_files.join(", "))
+                    };
+
+                    Response::MergeResult {
+                        status: "OK",
+                        outcome: MergeOutcome {
+                            result: format!("{:?}", entry.status).to_uppercase(),
+                            details,
+                        },
+                    }
+                }
+                Err(e) => Response::error(&e),
+            }
+        }
+
+        Request::History {
+            session_id,
+            agent_id,
+            limit,
+        } => {
+            match queue
+                .merge_history(
+                    session_id.as_deref(),
+                    agent_id.as_deref(),
+                    limit.unwrap_or(DEFAULT_HISTORY_LIMIT),
+                )
+                .await
+            {
+                Ok(merges) => Response::History {
+                    status: "OK",
+                    merges,
+                },
+                Err(e) => Response::error(&e),
+            }
+        }
+
+        Request::SessionEnd { session_id } => {
+            debug!("Session ended: {}", session_id);
+            Response::Ok { status: "OK" }
+        }
+
+        Request::SessionMerge { session_id } => match queue.session_merge(&session_id).await {
+            Ok(report) => Response::SessionMerge {
+                status: "OK",
+                report,
+            },
+            Err(e) => Response::error(&e),
+        },
+
+        Request::Reap { dry_run } => match queue.reap(dry_run).await {
+            Ok(report) => Response::Reap {
+                status: "OK",
+                report,
+            },
+            Err(e) => Response::error(&e),
+        },
+
+        Request::Worktrees => match queue.list_worktrees().await {
+            Ok(worktrees) => Response::Worktrees {
+                status: "OK",
+                worktrees,
+            },
+            Err(e) => Response::error(&e),
+        },
+
+        Request::WorktreeRemove { worktree, branch } => {
+            let path = PathBuf::from(&worktree);
+            if let Some(agent_id) = queue.merging_from(&path).await {
+                return Response::error(&DaemonError::WorktreeBusy {
+                    worktree: path,
+                    agent_id,
+                });
+            }
+
+            let removed = queue.worktrees().remove(&path);
+            let removed = match (removed, branch) {
+                (Ok(true), Some(branch)) => queue.worktrees().delete_branch(&branch).map(|_| true),
+                (removed, _) => removed,
+            };
+
+            match removed {
+                Ok(true) => Response::Ok { status: "OK" },
+                Ok(false) => Response::error(&DaemonError::Worktree(format!(
+                    "not a managed worktree: {}",
+                    worktree
+                ))),
+                Err(e) => Response::error(&e),
+            }
+        }
+
+        Request::WorktreePrune { dry_run } => match queue.worktrees().prune(dry_run) {
+            Ok(pruned) => Response::Pruned {
+                status: "OK",
+                dry_run,
+                pruned,
+            },
+            Err(e) => Response::error(&e),
+        },
+
+        Request::Shutdown => {
+            info!("Shutdown requested via IPC");
+            queue.shutdown().await;
+            Response::Ok { status: "OK" }
+        }
+    }
+}
@@ -0,0 +1,513 @@
+//! Loopback HTTP API, mirroring the socket requests
+//!
+//! | Method   | Path                 | Request                   |
+//! | -------- | -------------------- | ------------------------- |
+//! | `GET`    | `/queue`             | STATUS                    |
+//! | `POST`   | `/enqueue`           | ENQUEUE, fields as body   |
+//! | `GET`    | `/entries/{agent}`   | ENTRY                     |
+//! | `DELETE` | `/entries/{agent}`   | DEQUEUE                   |
+//! | `GET`    | `/events`            | Server-sent daemon events |
+//!
+//! Every endpoint takes an optional `?repo=` like the socket's `repo`, and
+//! requires `Authorization: Bearer <token>`. Replies are the same JSON as
+//! on the socket, with an HTTP status derived from the error code. One
+//! request is served per connection.
+//!
+//! A separate listener serves Prometheus metrics on `GET /metrics`.
+
+use crate::error::{DaemonError, DaemonResult};
+use crate::events::DaemonEvent;
+use crate::handler;
+use crate::ipc::Connections;
+use crate::protocol::{self, Response};
+use crate::repos::Repos;
+use serde_json::{json, Map, Value};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast;
+use tokio::time::Duration;
+use tracing::{debug, error, info, warn};
+
+/// Largest request line plus headers accepted
+const MAX_HEAD_BYTES: u64 = 16 * 1024;
+
+/// Largest request body accepted
+const MAX_BODY_BYTES: usize = 1024 * 1024;
+
+/// How long a client may take to send its request
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Interval between keepalive comments on idle event streams
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Content type of the Prometheus text format
+const METRICS_CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+/// HTTP server for clients that can't reach the Unix socket, or for
+/// metrics scrapers
+pub struct HttpServer {
+    addr: SocketAddr,
+    listener: TcpListener,
+    service: Arc<Service>,
+    repos: Arc<Repos>,
+    connections: Arc<Connections>,
+}
+
+/// What a listener serves
+#[derive(Debug)]
+enum Service {
+    /// The queue endpoints, to clients with the token
+    Api { token: String },
+    /// `GET /metrics`, to anyone
+    Metrics,
+}
+
+/// A parsed HTTP request
+#[derive(Debug)]
+struct HttpRequest {
+    method: String,
+    path: String,
+    /// Decoded `repo` query parameter
+    repo: Option<String>,
+    authorization: Option<String>,
+    body: Vec<u8>,
+}
+
+/// How to answer a request
+enum Reply {
+    Json(u16, Value),
+    Metrics(String),
+    Events(broadcast::Receiver<DaemonEvent>),
+}
+
+impl HttpServer {
+    /// Listen on the loopback address `addr`, accepting requests that
+    /// carry `token`. Open connections are counted in `connections`.
+    pub async fn bind(
+        addr: SocketAddr,
+        token: String,
+        repos: Arc<Repos>,
+        connections: Arc<Connections>,
+    ) -> DaemonResult<Self> {
+        if !addr.ip().is_loopback() {
+            return Err(DaemonError::Config(format!(
+                "HTTP API must listen on a loopback address, not {}",
+                addr
+            )));
+        }
+        if token.is_empty() {
+            return Err(DaemonError::Config("HTTP API requires a token".to_string()));
+        }
+
+        Self::bind_service(addr, Service::Api { token }, repos, connections).await
+    }
+
+    /// Listen on the loopback address `addr` for metrics scrapes. Open
+    /// connections are counted in `connections`.
+    pub async fn bind_metrics(
+        addr: SocketAddr,
+        repos: Arc<Repos>,
+        connections: Arc<Connections>,
+    ) -> DaemonResult<Self> {
+        if !addr.ip().is_loopback() {
+            return Err(DaemonError::Config(format!(
+                "Metrics must listen on a loopback address, not {}",
+                addr
+            )));
+        }
+
+        Self::bind_service(addr, Service::Metrics, repos, connections).await
+    }
+
+    async fn bind_service(
+        addr: SocketAddr,
+        service: Service,
+        repos: Arc<Repos>,
+        connections: Arc<Connections>,
+    ) -> DaemonResult<Self> {
+        let listener = TcpListener::bind(addr).await?;
+        let addr = listener.local_addr()?;
+
+        Ok(Self {
+            addr,
+            listener,
+            service: Arc::new(service),
+            repos,
+            connections,
+        })
+    }
+
+    /// The address the server listens on
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Run the HTTP server
+    pub async fn run(&self) -> DaemonResult<()> {
+        match *self.service {
+            Service::Api { .. } => info!("HTTP API listening on {}", self.addr),
+            Service::Metrics => info!("Metrics listening on {}", self.addr),
+        }
+
+        loop {
+            match self.listener.accept().await {
+                Ok((stream, peer)) => {
+                    let repos = self.repos.clone();
+                    let service = self.service.clone();
+                    let guard = self.connections.track();
+                    tokio::spawn(async move {
+                        let _guard = guard;
+                        if let Err(e) = handle_connection(stream, peer, &service, &repos).await {
+                            error!("HTTP connection error: {}", e);
+                        }
+                    });
+                }
+                Err(e) => {
+                    warn!("HTTP accept error: {}", e);
+                }
+            }
+        }
+    }
+}
+
+/// Serve a single request
+async fn handle_connection(
+    stream: TcpStream,
+    peer: SocketAddr,
+    service: &Service,
+    repos: &Repos,
+) -> DaemonResult<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+
+    let request = match tokio::time::timeout(READ_TIMEOUT, read_request(&mut reader)).await {
+        Ok(Ok(request)) => request,
+        Ok(Err(reply)) => return write_reply(&mut writer, reply).await,
+        Err(_) => {
+            let reply = error_reply(408, "request timed out");
+            return write_reply(&mut writer, reply).await;
+        }
+    };
+    debug!("HTTP {} {} from {}", request.method, request.path, peer);
+
+    let reply = match service {
+        Service::Metrics => match (request.method.as_str(), request.path.as_str()) {
+            ("GET", "/metrics") => Reply::Metrics(repos.metrics().await),
+            (_, "/metrics") => error_reply(405, "method not allowed"),
+            _ => error_reply(404, "no such endpoint"),
+        },
+        Service::Api { token } if !authorized(token, request.authorization.as_deref()) => {
+            warn!("Rejected HTTP request without a valid token from {}", peer);
+            json_reply(Response::error(&DaemonError::Unauthorized))
+        }
+        Service::Api { .. } => route(&request, peer, repos).await,
+    };
+
+    match reply {
+        Reply::Events(events) => stream_events(&mut writer, events, repos).await,
+        reply => write_reply(&mut writer, reply).await,
+    }
+}
+
+/// Answer a request by turning it into the matching socket request
+async fn route(request: &HttpRequest, peer: SocketAddr, repos: &Repos) -> Reply {
+    let segments: Vec<&str> = request.path.trim_matches('/').split('/').collect();
+
+    let (request_type, fields) = match (request.method.as_str(), segments.as_slice()) {
+        ("GET", ["queue"]) => ("STATUS", Map::new()),
+        ("POST", ["enqueue"]) => match serde_json::from_slice(&request.body) {
+            Ok(Value::Object(fields)) => ("ENQUEUE", fields),
+            _ => return error_reply(400, "body must be a JSON object"),
+        },
+        ("GET", ["entries", agent_id]) => ("ENTRY", agent_fields(agent_id)),
+        ("DELETE", ["entries", agent_id]) => ("DEQUEUE", agent_fields(agent_id)),
+        ("GET", ["events"]) => {
+            return match repos.resolve(request.repo.as_deref()).await {
+                Ok(queue) => Reply::Events(queue.subscribe()),
+                Err(e) => json_reply(Response::error(&e)),
+            };
+        }
+        (_, ["queue"] | ["enqueue"] | ["entries", _] | ["events"]) => {
+            return error_reply(405, "method not allowed");
+        }
+        _ => return error_reply(404, "no such endpoint"),
+    };
+
+    let mut object = fields;
+    object.insert("type".to_string(), json!(request_type));
+    if let Some(repo) = &request.repo {
+        object.insert("repo".to_string(), json!(repo));
+    }
+
+    // Parse as a socket request line, so both get the same validation
+    let (meta, parsed) = protocol::parse_request(&Value::Object(object).to_string());
+    let response = match parsed {
+        Ok(parsed) => {
+            if !parsed.is_read_only() {
+                info!("{} via HTTP from {}", request_type, peer);
+            }
+            handler::route_request(parsed, meta.repo.as_deref(), repos).await
+        }
+        Err(reason) => Response::rejected(reason),
+    };
+
+    json_reply(response)
+}
+
+fn agent_fields(agent_id: &str) -> Map<String, Value> {
+    Map::from_iter([("agent_id".to_string(), json!(percent_decode(agent_id)))])
+}
+
+/// Reply with a daemon response, with the HTTP status for its error code
+fn json_reply(response: Response) -> Reply {
+    let status = match &response {
+        Response::Error { code, .. } => http_status(code),
+        _ => 200,
+    };
+    Reply::Json(status, serde_json::to_value(&response).unwrap_or_default())
+}
+
+/// Reply with an error about the HTTP request itself
+fn error_reply(status: u16, message: &str) -> Reply {
+    let reason = protocol::RequestError::Malformed {
+        message: message.to_string(),
+    };
+    let response = serde_json::to_value(Response::rejected(reason)).unwrap_or_default();
+    Reply::Json(status, response)
+}
+
+/// HTTP status for a daemon error code
+fn http_status(code: &str) -> u16 {
+    match code {
+        "AGENT_NOT_FOUND" | "SESSION_NOT_FOUND" | "BRANCH_NOT_FOUND" | "REPO_NOT_FOUND" => 404,
+        "INVALID_REQUEST" | "UNKNOWN_REQUEST" | "UNSUPPORTED_PROTOCOL" | "DEPENDENCY_CYCLE" => 400,
+        "UNAUTHORIZED" => 401,
+        "PERMISSION_DENIED" => 403,
+        "AGENT_ALREADY_QUEUED" | "MERGE_CONFLICT" | "WORKTREE_BUSY" => 409,
+        "QUEUE_FULL" | "SHUTTING_DOWN" => 503,
+        _ => 500,
+    }
+}
+
+/// Whether the `Authorization` header carries `token`
+fn authorized(token: &str, authorization: Option<&str>) -> bool {
+    let Some(given) = authorization.and_then(|value| value.strip_prefix("Bearer ")) else {
+        return false;
+    };
+    let given = given.trim().as_bytes();
+    let token = token.as_bytes();
+
+    // Compare in constant time
+    given.len() == token.len()
+        && given
+            .iter()
+            .zip(token)
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// Read a request line, headers and body
+async fn read_request<R>(reader: &mut BufReader<R>) -> Result<HttpRequest, Reply>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut head = (&mut *reader).take(MAX_HEAD_BYTES);
+    let mut line = String::new();
+
+    if head.read_line(&mut line).await.unwrap_or(0) == 0 {
+        return Err(error_reply(400, "empty request"));
+    }
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Err(error_reply(400, "invalid request line"));
+    };
+    let method = method.to_string();
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let path = path.to_string();
+    let repo = query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == "repo")
+        .map(|(_, value)| percent_decode(value));
+
+    let mut authorization = None;
+    let mut content_length = 0;
+    loop {
+        line.clear();
+        if head.read_line(&mut line).await.unwrap_or(0) == 0 {
+            return Err(error_reply(431, "request headers too large or incomplete"));
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+
+        let Some((name, value)) = header.split_once(':') else {
+            return Err(error_reply(400, "invalid header"));
+        };
+        let value = value.trim();
+        if name.eq_ignore_ascii_case("authorization") {
+            authorization = Some(value.to_string());
+        } else if name.eq_ignore_ascii_case("content-length") {
+            content_length = value
+                .parse()
+                .map_err(|_| error_reply(400, "invalid Content-Length"))?;
+        }
+    }
+
+    if content_length > MAX_BODY_BYTES {
+        return Err(error_reply(413, "request body too large"));
+    }
+    let mut body = vec![0; content_length];
+    if reader.read_exact(&mut body).await.is_err() {
+        return Err(error_reply(400, "incomplete request body"));
+    }
+
+    Ok(HttpRequest {
+        method,
+        path,
+        repo,
+        authorization,
+        body,
+    })
+}
+
+/// Decode `%XX` escapes and `+` in a URL component
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (b'+', _) => {
+                decoded.push(b' ');
+                i += 1;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+async fn write_reply<W>(writer: &mut W, reply: Reply) -> DaemonResult<()>
+where
+    W: AsyncWriteExt + Unpin,
+{
+    match reply {
+        Reply::Json(status, body) => {
+            let mut headers = vec![("Content-Type", "application/json")];
+            if status == 401 {
+                headers.push(("WWW-Authenticate", "Bearer"));
+            }
+            write_response(writer, status, &headers, &serde_json::to_vec(&body)?).await
+        }
+        Reply::Metrics(text) => {
+            let headers = [("Content-Type", METRICS_CONTENT_TYPE)];
+            write_response(writer, 200, &headers, text.as_bytes()).await
+        }
+        Reply::Events(_) => Ok(()),
+    }
+}
+
+async fn write_response<W>(
+    writer: &mut W,
+    status: u16,
+    headers: &[(&str, &str)],
+    body: &[u8],
+) -> DaemonResult<()>
+where
+    W: AsyncWriteExt + Unpin,
+{
+    let mut head = format!("HTTP/1.1 {} {}\r\n", status, reason_phrase(status));
+    for (name, value) in headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str(&format!(
+        "Content-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    ));
+
+    writer.write_all(head.as_bytes()).await?;
+    writer.write_all(body).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+/// Send daemon events as server-sent events until the client goes away
+/// or the daemon shuts down
+async fn stream_events<W>(
+    writer: &mut W,
+    mut events: broadcast::Receiver<DaemonEvent>,
+    repos: &Repos,
+) -> DaemonResult<()>
+where
+    W: AsyncWriteExt + Unpin,
+{
+    writer
+        .write_all(
+            b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\n\
+              Cache-Control: no-cache\r\nConnection: close\r\n\r\n",
+        )
+        .await?;
+    writer.flush().await?;
+
+    let mut keepalive = tokio::time::interval(KEEPALIVE_INTERVAL);
+    keepalive.tick().await;
+
+    loop {
+        let message = tokio::select! {
+            event = events.recv() => match event {
+                Ok(event) => {
+                    let data = serde_json::to_value(&event)?;
+                    let name = data["event"].as_str().unwrap_or("message").to_string();
+                    format!("event: {}\ndata: {}\n\n", name, data)
+                }
+                Err(broadcast::error::RecvError::Lagged(missed)) => {
+                    format!("event: LAGGED\ndata: {}\n\n", json!({ "missed": missed }))
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            _ = keepalive.tick() => ": keepalive\n\n".to_string(),
+            _ = repos.shutdown_requested() => break,
+        };
+
+        // The client went away
+        if writer.write_all(message.as_bytes()).await.is_err() || writer.flush().await.is_err() {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        409 => "Conflict",
+        413 => "Payload Too Large",
+        431 => "Request Header Fields Too Large",
+        503 => "Service Unavailable",
+        _ => "Internal Server Error",
+    }
+}
@@ -1,115 +1,200 @@
 //! IPC server using Unix domain sockets
 
-use crate::error::DaemonResult;
-use crate::queue::MergeQueue;
+use crate::config::AccessPolicy;
+use crate::error::{DaemonError, DaemonResult};
+use crate::handler;
+use crate::protocol::{self, Envelope, Request, RequestMeta, Response};
+use crate::repos::Repos;
+use crate::socket;
 use crate::state::StateManager;
-use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::unix::UCred;
 use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::Notify;
 use tracing::{debug, error, info, warn};
 
 /// IPC server for handling client requests
 pub struct IpcServer {
     socket_path: PathBuf,
-    queue: MergeQueue,
+    listener: UnixListener,
+    repos: Arc<Repos>,
     #[allow(dead_code)]
     state_manager: StateManager,
+    connections: Arc<Connections>,
+    access: Arc<Access>,
 }
 
-/// Request types from clients
-#[derive(Debug, Deserialize)]
-#[serde(tag = "type", rename_all = "SCREAMING_SNAKE_CASE")]
-pub enum Request {
-    /// Register a new agent
-    Register { agent_id: String },
+/// The access policy, and the user it's relative to
+#[derive(Debug)]
+struct Access {
+    /// uid of the user running the daemon
+    owner_uid: u32,
+    policy: AccessPolicy,
+}
 
-    /// Enqueue a branch for merging
-    Enqueue {
-        agent_id: String,
-        session_id: String,
-        branch: String,
-        worktree: String,
-        target_branch: String,
-    },
+/// The process on the other end of a connection
+#[derive(Debug, Clone, Copy)]
+struct Peer {
+    uid: u32,
+    pid: Option<i32>,
+}
 
-    /// Remove an agent from the queue
-    Dequeue { agent_id: String },
+impl From<UCred> for Peer {
+    fn from(cred: UCred) -> Self {
+        Self {
+            uid: cred.uid(),
+            pid: cred.pid(),
+        }
+    }
+}
 
-    /// Get queue status
-    Status,
+impl Peer {
+    fn describe_pid(&self) -> String {
+        self.pid
+            .map_or_else(|| "unknown".to_string(), |pid| pid.to_string())
+    }
+}
 
-    /// Get conflicts for an agent
-    Conflicts { agent_id: String },
+impl Access {
+    /// Check that `peer` may send `request`
+    fn authorize(&self, peer: Peer, meta: &RequestMeta, request: &Request) -> DaemonResult<()> {
+        let request_type = meta.request_type.as_deref().unwrap_or_default();
+        self.check(peer, request_type)?;
 
-    /// Retry a failed merge
-    Retry { agent_id: String },
+        if !request.is_read_only() {
+            info!(
+                "{} from uid {} (pid {}){}",
+                request_type,
+                peer.uid,
+                peer.describe_pid(),
+                meta.repo
+                    .as_deref()
+                    .map(|repo| format!(" for {}", repo))
+                    .unwrap_or_default()
+            );
+        }
+        Ok(())
+    }
 
-    /// Wait for merge result (blocking)
-    Wait { agent_id: String },
+    /// Check that `peer` may send requests of `request_type`
+    fn check(&self, peer: Peer, request_type: &str) -> DaemonResult<()> {
+        let allowed = if self.policy.is_admin_request(request_type) {
+            self.policy.is_admin(self.owner_uid, peer.uid)
+        } else {
+            self.policy.allows(self.owner_uid, peer.uid)
+        };
+        if allowed {
+            return Ok(());
+        }
 
-    /// End a session
-    SessionEnd { session_id: String },
+        warn!(
+            "Denied {} from uid {} (pid {})",
+            request_type,
+            peer.uid,
+            peer.describe_pid()
+        );
+        Err(DaemonError::PermissionDenied {
+            request: request_type.to_string(),
+            uid: peer.uid,
+        })
+    }
+}
 
-    /// Shutdown the daemon
-    Shutdown,
+/// Open client connections, so shutdown can wait for their replies to be
+/// sent
+#[derive(Debug, Default)]
+pub struct Connections {
+    open: AtomicUsize,
+    closed: Notify,
 }
 
-/// Response to clients
-#[derive(Debug, Serialize)]
-#[serde(untagged)]
-pub enum Response {
-    Ok {
-        status: &'static str,
-    },
-    Position {
-        status: &'static str,
-        position: usize,
-    },
-    Status {
-        queue_length: usize,
-        pending: usize,
-        processing: usize,
-        agents: Vec<String>,
-    },
-    Conflicts {
-        files: Vec<String>,
-    },
-    MergeResult {
-        result: String,
-        details: Option<String>,
-    },
-    Error {
-        status: &'static str,
-        error: String,
-    },
+impl Connections {
+    /// Count a connection as open until the guard is dropped
+    pub(crate) fn track(self: &Arc<Self>) -> ConnectionGuard {
+        self.open.fetch_add(1, Ordering::SeqCst);
+        ConnectionGuard(self.clone())
+    }
+
+    /// Number of open connections
+    pub fn open(&self) -> usize {
+        self.open.load(Ordering::SeqCst)
+    }
+
+    /// Wait until no connections are open
+    pub async fn closed(&self) {
+        loop {
+            let notified = self.closed.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+
+            if self.open() == 0 {
+                return;
+            }
+            notified.await;
+        }
+    }
+}
+
+/// Marks a connection as open until dropped
+pub(crate) struct ConnectionGuard(Arc<Connections>);
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        if self.0.open.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.0.closed.notify_waiters();
+        }
+    }
 }
 
 impl IpcServer {
-    /// Create a new IPC server
+    /// Create a new IPC server listening on `socket_path`, accepting
+    /// requests from the users `access` allows
     pub fn new(
         socket_path: PathBuf,
-        queue: MergeQueue,
+        repos: Arc<Repos>,
         state_manager: StateManager,
+        access: AccessPolicy,
     ) -> DaemonResult<Self> {
+        socket::prepare_dir(&socket_path, access.is_shared())?;
+        let listener = UnixListener::bind(&socket_path)?;
+        socket::restrict(&socket_path, access.is_shared())?;
+        let owner_uid = std::fs::metadata(&socket_path)?.uid();
+
         Ok(Self {
             socket_path,
-            queue,
+            listener,
+            repos,
             state_manager,
+            connections: Arc::default(),
+            access: Arc::new(Access {
+                owner_uid,
+                policy: access,
+            }),
         })
     }
 
+    /// The server's open connections
+    pub fn connections(&self) -> Arc<Connections> {
+        self.connections.clone()
+    }
+
     /// Run the IPC server
     pub async fn run(&self) -> DaemonResult<()> {
-        let listener = UnixListener::bind(&self.socket_path)?;
         info!("IPC server listening on {:?}", self.socket_path);
 
         loop {
-            match listener.accept().await {
+            match self.listener.accept().await {
                 Ok((stream, _)) => {
-                    let queue = self.queue.clone();
+                    let repos = self.repos.clone();
+                    let access = self.access.clone();
+                    let guard = self.connections.track();
                     tokio::spawn(async move {
-                        if let Err(e) = handle_connection(stream, queue).await {
+                        let _guard = guard;
+                        if let Err(e) = handle_connection(stream, &repos, &access).await {
                             error!("Connection error: {}", e);
                         }
                     });
@@ -123,123 +208,333 @@ impl IpcServer {
 }
 
 /// Handle a single client connection
-async fn handle_connection(stream: UnixStream, queue: MergeQueue) -> DaemonResult<()> {
+async fn handle_connection(stream: UnixStream, repos: &Repos, access: &Access) -> DaemonResult<()> {
+    // SO_PEERCRED: who is on the other end of the socket
+    let peer = Peer::from(stream.peer_cred()?);
     let (reader, mut writer) = stream.into_split();
     let mut reader = BufReader::new(reader);
     let mut line = String::new();
 
-    while reader.read_line(&mut line).await? > 0 {
+    loop {
+        let read = if repos.is_shutting_down() {
+            // Still answer a request sent on a connection opened during
+            // shutdown, so the client learns why it was rejected
+            reader.read_line(&mut line).await?
+        } else {
+            tokio::select! {
+                read = reader.read_line(&mut line) => read?,
+                // Close idle connections so shutdown doesn't wait on them
+                _ = repos.shutdown_requested() => break,
+            }
+        };
+        if read == 0 {
+            break;
+        }
+
         debug!("Received: {}", line.trim());
 
-        let response = match serde_json::from_str::<Request>(&line) {
-            Ok(request) => process_request(request, &queue).await,
-            Err(e) => Response::Error {
-                status: "ERROR",
-                error: format!("Invalid request: {}", e),
+        let (meta, request) = protocol::parse_request(&line);
+        let response = match request {
+            Ok(request) => match admit(peer, &meta, &request, repos, access).await {
+                Ok(()) => handler::route_request(request, meta.repo.as_deref(), repos).await,
+                Err(e) => Response::error(&e),
             },
+            Err(reason) => {
+                debug!("Rejected request: {}", reason);
+                Response::rejected(reason)
+            }
         };
 
-        let response_json = serde_json::to_string(&response)?;
+        let envelope = Envelope {
+            request_id: meta.request_id,
+            response,
+        };
+        let response_json = serde_json::to_string(&envelope)?;
         writer.write_all(response_json.as_bytes()).await?;
         writer.write_all(b"\n").await?;
         writer.flush().await?;
 
         line.clear();
+
+        if repos.is_shutting_down() {
+            break;
+        }
     }
 
     Ok(())
 }
 
-/// Process a single request
-async fn process_request(request: Request, queue: &MergeQueue) -> Response {
-    match request {
-        Request::Register { agent_id } => {
-            debug!("Registered agent: {}", agent_id);
-            Response::Ok { status: "OK" }
+/// Check that `peer` may send `request`. A repository path in `repo` that
+/// isn't registered yet is registered first, if `peer` may send
+/// REGISTER_REPO. HELLO never registers one, as it doesn't use it.
+async fn admit(
+    peer: Peer,
+    meta: &RequestMeta,
+    request: &Request,
+    repos: &Repos,
+    access: &Access,
+) -> DaemonResult<()> {
+    access.authorize(peer, meta, request)?;
+
+    let Some(path) = meta.repo.as_deref() else {
+        return Ok(());
+    };
+    if matches!(request, Request::Hello { .. }) {
+        return Ok(());
+    }
+    if !Path::new(path).is_absolute() || repos.is_registered(path).await {
+        return Ok(());
+    }
+
+    access.check(peer, "REGISTER_REPO")?;
+    info!(
+        "REGISTER_REPO from uid {} (pid {}) for {}",
+        peer.uid,
+        peer.describe_pid(),
+        path
+    );
+    repos.register(Path::new(path), None).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    const OWNER: u32 = 1000;
+    const USER: u32 = 1001;
+
+    fn init_repo(path: &Path) {
+        let repo = git2::Repository::init(path).unwrap();
+        let signature = git2::Signature::now("test", "test@example.com").unwrap();
+        let tree = repo
+            .find_tree(repo.index().unwrap().write_tree().unwrap())
+            .unwrap();
+        repo.commit(Some("HEAD"), &signature, &signature, "init", &tree, &[])
+            .unwrap();
+    }
+
+    async fn open_repos(path: &Path) -> Repos {
+        init_repo(path);
+        let state = StateManager::in_memory().await.unwrap();
+        Repos::open(path.to_path_buf(), state, Config::default())
+            .await
+            .unwrap()
+    }
+
+    fn access() -> Access {
+        Access {
+            owner_uid: OWNER,
+            policy: AccessPolicy {
+                allowed_uids: vec![USER],
+                ..AccessPolicy::default()
+            },
         }
+    }
+
+    fn status_in(repo: &Path) -> (RequestMeta, Request) {
+        let line = serde_json::json!({"type": "STATUS", "repo": repo}).to_string();
+        let (meta, request) = protocol::parse_request(&line);
+        (meta, request.unwrap())
+    }
+
+    fn peer(uid: u32) -> Peer {
+        Peer { uid, pid: None }
+    }
+
+    #[tokio::test]
+    async fn non_admin_cannot_register_repo_implicitly() {
+        let dir = tempfile::tempdir().unwrap();
+        let repos = open_repos(&dir.path().join("default")).await;
+        let other = dir.path().join("other");
+        init_repo(&other);
+
+        let (meta, request) = status_in(&other);
+        let result = admit(peer(USER), &meta, &request, &repos, &access()).await;
+
+        assert!(matches!(
+            result,
+            Err(DaemonError::PermissionDenied { ref request, uid: USER }) if request == "REGISTER_REPO"
+        ));
+        assert_eq!(repos.list().await.len(), 1);
+        assert!(matches!(
+            repos.resolve(other.to_str()).await,
+            Err(DaemonError::RepoNotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn owner_registers_repo_implicitly() {
+        let dir = tempfile::tempdir().unwrap();
+        let repos = open_repos(&dir.path().join("default")).await;
+        let other = dir.path().join("other");
+        init_repo(&other);
+
+        let (meta, request) = status_in(&other);
+        admit(peer(OWNER), &meta, &request, &repos, &access())
+            .await
+            .unwrap();
+
+        assert_eq!(repos.list().await.len(), 2);
+        assert!(repos.resolve(other.to_str()).await.is_ok());
+
+        // Registered repositories need no admin rights
+        admit(peer(USER), &meta, &request, &repos, &access())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn linked_worktree_shares_its_repository() {
+        let dir = tempfile::tempdir().unwrap();
+        let main = dir.path().join("default");
+        let repos = open_repos(&main).await;
+
+        let repo = git2::Repository::open(&main).unwrap();
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        repo.branch("agent/a1", &head, false).unwrap();
+        let worktree = dir.path().join("a1");
+        let branch = repo.find_reference("refs/heads/agent/a1").unwrap();
+        repo.worktree(
+            "a1",
+            &worktree,
+            Some(git2::WorktreeAddOptions::new().reference(Some(&branch))),
+        )
+        .unwrap();
+
+        let line = serde_json::json!({
+            "type": "ENQUEUE",
+            "repo": worktree,
+            "agent_id": "a1",
+            "session_id": "s1",
+            "branch": "agent/a1",
+            "worktree": worktree,
+            "target_branch": "main",
+        })
+        .to_string();
+        let (meta, request) = protocol::parse_request(&line);
+        let request = request.unwrap();
+        admit(peer(OWNER), &meta, &request, &repos, &access())
+            .await
+            .unwrap();
+        let response = handler::route_request(request, meta.repo.as_deref(), &repos).await;
+        assert_eq!(serde_json::to_value(&response).unwrap()["status"], "OK");
 
-        Request::Enqueue {
-            agent_id,
-            session_id,
-            branch,
-            worktree,
-            target_branch,
-        } => {
-            match queue
-                .enqueue(
-                    agent_id,
-                    session_id,
-                    branch,
-                    PathBuf::from(worktree),
-                    target_branch,
-                )
+        let registered = repos.list().await;
+        assert_eq!(registered.len(), 1);
+        assert_eq!(registered[0].repo_id, repos.default_repo().repo_id);
+        let entry = repos.default_queue().latest_entry("a1").await.unwrap();
+        assert!(entry.is_some());
+    }
+
+    /// Send `requests` on one connection as the daemon's owner and return
+    /// the responses
+    async fn exchange(repos: &Repos, requests: &[serde_json::Value]) -> Vec<serde_json::Value> {
+        let (client, server) = UnixStream::pair().unwrap();
+        let access = Access {
+            // SAFETY: geteuid has no preconditions and can't fail
+            owner_uid: unsafe { libc::geteuid() },
+            policy: AccessPolicy::default(),
+        };
+
+        let (reader, mut writer) = client.into_split();
+        for request in requests {
+            writer
+                .write_all(format!("{}\n", request).as_bytes())
                 .await
-            {
-                Ok(position) => Response::Position {
-                    status: "OK",
-                    position,
-                },
-                Err(e) => Response::Error {
-                    status: "ERROR",
-                    error: e.to_string(),
-                },
-            }
+                .unwrap();
         }
+        drop(writer);
 
-        Request::Dequeue { agent_id } => match queue.dequeue(&agent_id).await {
-            Ok(_) => Response::Ok { status: "OK" },
-            Err(e) => Response::Error {
-                status: "ERROR",
-                error: e.to_string(),
-            },
-        },
-
-        Request::Status => {
-            let status = queue.status().await;
-            Response::Status {
-                queue_length: status.length,
-                pending: status.pending,
-                processing: status.processing,
-                agents: status.agents,
+        let (served, responses) = tokio::join!(handle_connection(server, repos, &access), async {
+            let mut lines = BufReader::new(reader).lines();
+            let mut responses = Vec::new();
+            while let Some(line) = lines.next_line().await.unwrap() {
+                responses.push(serde_json::from_str(&line).unwrap());
             }
-        }
+            responses
+        });
+        served.unwrap();
+        responses
+    }
 
-        Request::Conflicts { agent_id } => match queue.get_conflicts(&agent_id).await {
-            Ok(files) => Response::Conflicts { files },
-            Err(e) => Response::Error {
-                status: "ERROR",
-                error: e.to_string(),
-            },
-        },
+    #[tokio::test]
+    async fn hello_needs_no_registered_repo() {
+        let dir = tempfile::tempdir().unwrap();
+        let repos = open_repos(&dir.path().join("default")).await;
+        let unknown = dir.path().join("unknown");
 
-        Request::Retry { agent_id } => match queue.retry(&agent_id).await {
-            Ok(position) => Response::Position {
-                status: "OK",
-                position,
-            },
-            Err(e) => Response::Error {
-                status: "ERROR",
-                error: e.to_string(),
-            },
-        },
+        let responses = exchange(
+            &repos,
+            &[
+                serde_json::json!({"type": "HELLO", "repo": unknown, "protocol_version": 1}),
+                serde_json::json!({"type": "HELLO", "protocol_version": 999}),
+            ],
+        )
+        .await;
 
-        Request::Wait { agent_id: _ } => {
-            // TODO: Implement blocking wait for merge result
-            Response::MergeResult {
-                result: "PENDING".to_string(),
-                details: Some("Waiting not yet implemented".to_string()),
-            }
-        }
+        assert_eq!(responses[0]["status"], "OK", "{}", responses[0]);
+        assert_eq!(responses[0]["protocol_version"], protocol::PROTOCOL_VERSION);
+        assert!(responses[0]["capabilities"]
+            .as_array()
+            .unwrap()
+            .contains(&"HELLO".into()));
+        assert_eq!(repos.list().await.len(), 1);
 
-        Request::SessionEnd { session_id } => {
-            debug!("Session ended: {}", session_id);
-            Response::Ok { status: "OK" }
-        }
+        assert_eq!(responses[1]["code"], "UNSUPPORTED_PROTOCOL");
+        assert_eq!(responses[1]["details"]["protocol_version"], 999);
+    }
 
-        Request::Shutdown => {
-            info!("Shutdown requested via IPC");
-            queue.shutdown().await;
-            Response::Ok { status: "OK" }
-        }
+    #[tokio::test]
+    async fn responses_echo_the_request_id() {
+        let dir = tempfile::tempdir().unwrap();
+        let repos = open_repos(&dir.path().join("default")).await;
+
+        let responses = exchange(
+            &repos,
+            &[
+                serde_json::json!({"type": "STATUS", "request_id": "abc"}),
+                serde_json::json!({"type": "STATUS", "request_id": {"n": 7}}),
+                serde_json::json!({"type": "STATUS"}),
+                serde_json::json!({"type": "NOPE", "request_id": 8}),
+            ],
+        )
+        .await;
+
+        let ids: Vec<_> = responses.iter().map(|r| r["request_id"].clone()).collect();
+        assert_eq!(
+            ids,
+            [
+                serde_json::json!("abc"),
+                serde_json::json!({"n": 7}),
+                serde_json::Value::Null,
+                serde_json::json!(8),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn unknown_requests_and_fields_are_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let repos = open_repos(&dir.path().join("default")).await;
+
+        let responses = exchange(
+            &repos,
+            &[
+                serde_json::json!({"type": "FROBNICATE"}),
+                serde_json::json!({"type": "STATUS", "verbose": true}),
+            ],
+        )
+        .await;
+
+        assert_eq!(responses[0]["code"], "UNKNOWN_REQUEST", "{}", responses[0]);
+        assert_eq!(responses[0]["details"]["reason"], "UNKNOWN_REQUEST");
+        assert_eq!(responses[0]["details"]["request_type"], "FROBNICATE");
+
+        assert_eq!(responses[1]["code"], "INVALID_REQUEST", "{}", responses[1]);
+        assert_eq!(responses[1]["details"]["reason"], "UNKNOWN_FIELD");
+        assert_eq!(responses[1]["details"]["request_type"], "STATUS");
+        assert_eq!(responses[1]["details"]["field"], "verbose");
     }
 }
@@ -0,0 +1,34 @@
+//! Merge daemon library: the merge queue and its components, plus the
+//! wire protocol and a client for talking to a running daemon.
+//!
+//! [`DaemonBuilder`] runs the whole daemon inside another program, with or
+//! without a socket. The `merge-daemon` binary is a thin wrapper around it.
+
+pub mod client;
+pub mod config;
+pub mod daemon;
+pub mod error;
+pub mod events;
+pub mod handler;
+pub mod http;
+pub mod ipc;
+pub mod merger;
+pub mod metrics;
+pub mod pidfile;
+pub mod protocol;
+pub mod queue;
+pub mod repos;
+pub mod scheduler;
+pub mod socket;
+pub mod state;
+pub mod worktree;
+
+pub use config::Config;
+pub use daemon::{Daemon, DaemonBuilder};
+pub use error::{DaemonError, DaemonResult};
+pub use handler::{handle_request, route_request};
+pub use merger::Merger;
+pub use protocol::{Request, Response};
+pub use queue::MergeQueue;
+pub use repos::{RepoInfo, Repos};
+pub use state::StateManager;
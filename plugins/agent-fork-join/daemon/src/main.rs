@@ -4,24 +4,26 @@
 //! a feature branch. It handles conflict detection, rebase coordination,
 //! and maintains persistent state across restarts.
 
-mod config;
-mod error;
-mod ipc;
-mod merger;
-mod queue;
-mod state;
-
-use anyhow::Result;
-use clap::Parser;
-use std::path::PathBuf;
+mod ctl;
+
+use anyhow::{bail, Context, Result};
+use clap::{Parser, Subcommand};
+use std::fs::OpenOptions;
+use std::os::unix::net::UnixStream;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command as Process, ExitCode, Stdio};
+use std::time::{Duration, Instant};
 use tokio::signal;
-use tracing::{info, warn};
+use tracing::info;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
-use crate::config::Config;
-use crate::ipc::IpcServer;
-use crate::queue::MergeQueue;
-use crate::state::StateManager;
+use merge_daemon::pidfile::PidFile;
+use merge_daemon::socket;
+use merge_daemon::{Config, DaemonBuilder, DaemonError};
+
+/// How long to wait for a background daemon to accept connections
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(10);
 
 /// Merge daemon for multi-agent git workflows
 #[derive(Parser, Debug)]
@@ -32,9 +34,10 @@ struct Args {
     #[arg(short, long, default_value = ".")]
     repo: PathBuf,
 
-    /// Unix socket path
-    #[arg(short, long, default_value = "/tmp/merge-daemon.sock")]
-    socket: PathBuf,
+    /// Unix socket path (default: the current user's socket for the
+    /// repository, see `locate`)
+    #[arg(short, long, global = true, env = "FORK_JOIN_DAEMON_SOCKET")]
+    socket: Option<PathBuf>,
 
     /// State database path
     #[arg(short, long, default_value = ".fork-join/state.db")]
@@ -48,15 +51,50 @@ struct Args {
     #[arg(short, long)]
     foreground: bool,
 
+    /// Log file when running in the background (default: daemon.log next
+    /// to the state database)
+    #[arg(long)]
+    log_file: Option<PathBuf>,
+
     /// Configuration file path
     #[arg(short, long)]
     config: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Control a running daemon
+    Ctl(ctl::CtlArgs),
+
+    /// Print the socket path of the daemon for the repository
+    Locate,
 }
 
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn main() -> Result<ExitCode> {
     let args = Args::parse();
 
+    if let Some(Command::Ctl(ctl)) = args.command {
+        return Ok(ctl::run(args.socket.as_deref(), ctl).await);
+    }
+
+    let socket = match &args.socket {
+        Some(socket) => socket.clone(),
+        None => socket::default_path(&args.repo)?,
+    };
+
+    if let Some(Command::Locate) = args.command {
+        println!("{}", socket.display());
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    if !args.foreground {
+        return daemonize(&args, &socket).await;
+    }
+
     // Initialize logging
     let filter = tracing_subscriber::EnvFilter::try_from_default_env()
         .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new(&args.log_level));
@@ -68,7 +106,7 @@ async fn main() -> Result<()> {
 
     info!("Starting merge daemon v{}", env!("CARGO_PKG_VERSION"));
     info!("Repository: {:?}", args.repo);
-    info!("Socket: {:?}", args.socket);
+    info!("Socket: {:?}", socket);
 
     // Load configuration
     let config = if let Some(config_path) = &args.config {
@@ -77,63 +115,97 @@ async fn main() -> Result<()> {
         Config::default()
     };
 
-    // Initialize state manager (persistent storage)
-    let state_manager = StateManager::new(&args.db).await?;
-    info!("State database initialized at {:?}", args.db);
+    let daemon = DaemonBuilder::new(args.repo)
+        .config(config)
+        .db_path(args.db)
+        .socket(socket.clone())
+        .start()
+        .await?;
 
-    // Initialize merge queue
-    let queue = MergeQueue::new(args.repo.clone(), state_manager.clone(), config.clone());
+    info!("Merge daemon started successfully");
+    info!("Listening on: {:?}", socket);
 
-    // Recover any pending merges from previous run
-    let recovered = queue.recover().await?;
-    if recovered > 0 {
-        info!("Recovered {} pending merge(s) from previous session", recovered);
+    // Wait for a shutdown signal or SHUTDOWN request
+    tokio::select! {
+        _ = shutdown_signal() => info!("Shutdown signal received, cleaning up..."),
+        _ = daemon.shutdown_requested() => info!("Shutdown requested, cleaning up..."),
     }
 
-    // Start IPC server
-    let server = IpcServer::new(args.socket.clone(), queue.clone(), state_manager.clone())?;
+    // Graceful shutdown: finish the merge in progress, then stop
+    daemon.shutdown().await?;
+
+    info!("Merge daemon stopped");
+    Ok(ExitCode::SUCCESS)
+}
 
-    // Remove stale socket file if it exists
-    if args.socket.exists() {
-        warn!("Removing stale socket file: {:?}", args.socket);
-        std::fs::remove_file(&args.socket)?;
+/// Start the daemon in the background: run this binary again with
+/// `--foreground` in a new session, logging to a file, and wait until it
+/// accepts connections
+async fn daemonize(args: &Args, socket: &Path) -> Result<ExitCode> {
+    // Fail here rather than in the log if a daemon is already running
+    let pid_path = PidFile::for_db(&args.db);
+    PidFile::check(&pid_path)?;
+    if UnixStream::connect(socket).is_ok() {
+        return Err(DaemonError::SocketInUse(socket.to_path_buf()).into());
     }
 
-    // Spawn the IPC server
-    let server_handle = tokio::spawn(async move {
-        if let Err(e) = server.run().await {
-            tracing::error!("IPC server error: {}", e);
+    let log_path = args
+        .log_file
+        .clone()
+        .unwrap_or_else(|| args.db.with_file_name("daemon.log"));
+    if let Some(parent) = log_path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let log = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&log_path)
+        .with_context(|| format!("Failed to open log file {:?}", log_path))?;
+
+    let mut command = Process::new(std::env::current_exe()?);
+    command
+        .args(std::env::args_os().skip(1))
+        .arg("--foreground")
+        .stdin(Stdio::null())
+        .stdout(log.try_clone()?)
+        .stderr(log);
+    // A new session has no controlling terminal, so the daemon gets no
+    // SIGHUP when the terminal closes or the session leader exits
+    // SAFETY: setsid is async-signal-safe and touches no parent state
+    unsafe {
+        command.pre_exec(|| {
+            if libc::setsid() == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+    let mut child = command.spawn().context("Failed to start merge daemon")?;
+
+    // Ready once it holds the PID file and listens on the socket
+    let started = Instant::now();
+    while started.elapsed() < STARTUP_TIMEOUT {
+        if let Some(status) = child.try_wait()? {
+            bail!(
+                "Merge daemon exited during startup ({}), see {:?}",
+                status,
+                log_path
+            );
         }
-    });
-
-    // Spawn the merge processor
-    let processor_handle = tokio::spawn({
-        let queue = queue.clone();
-        async move {
-            queue.process_loop().await;
+        if PidFile::read(&pid_path) == Some(child.id())
+            && UnixStream::connect(socket).is_ok()
+        {
+            println!("Merge daemon started with PID {}", child.id());
+            return Ok(ExitCode::SUCCESS);
         }
-    });
-
-    info!("Merge daemon started successfully");
-    info!("Listening on: {:?}", args.socket);
-
-    // Wait for shutdown signal
-    shutdown_signal().await;
-
-    info!("Shutdown signal received, cleaning up...");
-
-    // Graceful shutdown
-    queue.shutdown().await;
-    server_handle.abort();
-    processor_handle.abort();
-
-    // Cleanup socket file
-    if args.socket.exists() {
-        std::fs::remove_file(&args.socket)?;
+        tokio::time::sleep(Duration::from_millis(100)).await;
     }
 
-    info!("Merge daemon stopped");
-    Ok(())
+    bail!(
+        "Merge daemon did not start within {:?}, see {:?}",
+        STARTUP_TIMEOUT,
+        log_path
+    )
 }
 
 /// Wait for shutdown signals (SIGINT, SIGTERM)
@@ -1,21 +1,96 @@
 //! Git merge operations
 
-use crate::config::{Config, MergeStrategy};
+use crate::config::{Config, MergePolicy, MergeStrategy};
+use crate::error::{DaemonError, DaemonResult};
+use crate::metrics::Metrics;
 use crate::queue::{MergeResult, QueueEntry};
-use git2::{Commit, Index, MergeOptions, Repository, Signature};
+use git2::{Commit, ErrorCode, Index, MergeOptions, Oid, RebaseOptions, Repository, Signature};
+use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
-use tracing::{debug, info};
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::{debug, info, warn};
+
+/// Outcome of a dry-run merge
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MergePreview {
+    /// Whether the merge would apply without conflicts
+    pub clean: bool,
+    /// Strategy used for the preview
+    pub strategy: MergeStrategy,
+    /// Conflicting files (empty when clean)
+    pub conflict_files: Vec<String>,
+    /// Changes the branch would bring into the target
+    pub diffstat: DiffStat,
+    /// Tree the merge would produce (None on conflict)
+    pub tree_oid: Option<String>,
+    /// Why the merge would be refused by policy, if it would be
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+/// Result of merging a whole session at once
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum OctopusResult {
+    /// All branches merged in a single commit, except those of `missing`
+    Success {
+        commit_sha: String,
+        /// Agents whose branch no longer exists, left out of the commit
+        missing: Vec<String>,
+    },
+    /// Octopus merge conflicted; lists the conflicting pairs
+    Conflict { pairs: Vec<PairConflict> },
+    /// Merge refused or failed for other reasons
+    Failed { error: String },
+}
+
+/// Two branches that cannot be merged together
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PairConflict {
+    pub left: String,
+    pub right: String,
+    pub files: Vec<String>,
+}
+
+/// Result of computing a merge in memory
+enum PreviewOutcome {
+    /// Merge applies cleanly, producing this tree
+    Clean(Oid),
+    /// Merge conflicts in these files
+    Conflict(Vec<String>),
+    /// Merge is refused by the branch policy
+    Blocked(String),
+}
+
+/// Summary of a diff between two trees
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DiffStat {
+    pub files_changed: usize,
+    pub insertions: usize,
+    pub deletions: usize,
+}
 
 /// Handles git merge operations
 pub struct Merger {
     repo_path: PathBuf,
     config: Config,
+    metrics: Arc<Metrics>,
 }
 
 impl Merger {
     /// Create a new merger
     pub fn new(repo_path: PathBuf, config: Config) -> Self {
-        Self { repo_path, config }
+        Self {
+            repo_path,
+            config,
+            metrics: Arc::default(),
+        }
+    }
+
+    /// Record validation gate timings in `metrics`
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = metrics;
+        self
     }
 
     /// Perform a merge operation
@@ -38,16 +113,314 @@ impl Merger {
             target_commit.id()
         );
 
+        let policy = self.config.policy_for(&entry.target_branch);
+
+        if policy.require_linear_history {
+            let started = Instant::now();
+            let violation =
+                linear_history_violation(&repo, &target_commit, &agent_commit, &policy)?;
+            self.metrics
+                .record_gate("linear_history", violation.is_none(), started.elapsed());
+            if let Some(reason) = violation {
+                return Ok(MergeResult::Failed { error: reason });
+            }
+        }
+
         // Checkout target branch
         repo.set_head(&format!("refs/heads/{}", entry.target_branch))?;
         repo.checkout_head(Some(git2::build::CheckoutBuilder::default().force()))?;
 
-        // Perform merge based on strategy
-        match self.config.merge_strategy {
-            MergeStrategy::Merge => self.do_merge(&repo, &target_commit, &agent_commit, entry),
+        // Perform merge based on strategy. A single octopus entry is just a
+        // regular merge; batching happens in `octopus_merge`.
+        match policy.merge_strategy {
+            MergeStrategy::Merge | MergeStrategy::Octopus => {
+                self.do_merge(&repo, &target_commit, &agent_commit, entry)
+            }
             MergeStrategy::Rebase => self.do_rebase(&repo, &target_commit, &agent_commit, entry),
             MergeStrategy::Squash => self.do_squash(&repo, &target_commit, &agent_commit, entry),
+            MergeStrategy::FastForwardOnly => self.do_fast_forward(
+                &repo,
+                &target_commit,
+                &agent_commit,
+                entry,
+                policy.auto_rebase,
+            ),
+        }
+    }
+
+    /// Dry-run a merge of `branch` into `target_branch`.
+    ///
+    /// The merge is computed entirely in memory: refs, the index and the
+    /// working tree are left untouched. Only the resulting tree object is
+    /// written to the object database so its OID can be reported.
+    pub fn preview(&self, branch: &str, target_branch: &str) -> DaemonResult<MergePreview> {
+        let repo = Repository::open(&self.repo_path)?;
+
+        let target_commit = find_branch_commit(&repo, target_branch)?;
+        let agent_commit = find_branch_commit(&repo, branch)?;
+
+        debug!(
+            "Previewing {} ({}) into {} ({})",
+            branch,
+            agent_commit.id(),
+            target_branch,
+            target_commit.id()
+        );
+
+        let policy = self.config.policy_for(target_branch);
+        let strategy = policy.merge_strategy;
+
+        let violation = if policy.require_linear_history {
+            linear_history_violation(&repo, &target_commit, &agent_commit, &policy)?
+        } else {
+            None
+        };
+
+        let outcome = match (violation, strategy) {
+            (Some(reason), _) => PreviewOutcome::Blocked(reason),
+            (None, MergeStrategy::Rebase) => {
+                self.preview_rebase(&repo, &target_commit, &agent_commit)?
+            }
+            (None, MergeStrategy::FastForwardOnly) => {
+                if is_ancestor(&repo, &agent_commit, &target_commit)? {
+                    PreviewOutcome::Clean(target_commit.tree_id())
+                } else if is_ancestor(&repo, &target_commit, &agent_commit)? {
+                    PreviewOutcome::Clean(agent_commit.tree_id())
+                } else if policy.auto_rebase {
+                    self.preview_rebase(&repo, &target_commit, &agent_commit)?
+                } else {
+                    PreviewOutcome::Blocked(not_fast_forward_reason(branch, target_branch))
+                }
+            }
+            (None, MergeStrategy::Merge | MergeStrategy::Squash | MergeStrategy::Octopus) => {
+                let mut opts = MergeOptions::new();
+                opts.fail_on_conflict(false);
+
+                let mut index = repo.merge_commits(&target_commit, &agent_commit, Some(&opts))?;
+                if index.has_conflicts() {
+                    PreviewOutcome::Conflict(self.get_conflict_files(&index)?)
+                } else {
+                    PreviewOutcome::Clean(index.write_tree_to(&repo)?)
+                }
+            }
+        };
+
+        if let PreviewOutcome::Clean(tree_id) = outcome {
+            let new_tree = repo.find_tree(tree_id)?;
+            let diffstat = diff_stat(&repo, &target_commit.tree()?, &new_tree)?;
+
+            return Ok(MergePreview {
+                clean: true,
+                strategy,
+                conflict_files: vec![],
+                diffstat,
+                tree_oid: Some(tree_id.to_string()),
+                reason: None,
+            });
+        }
+
+        // No merged tree exists, so report what the branch changes
+        // relative to where it forked from the target
+        let base_id = repo.merge_base(target_commit.id(), agent_commit.id())?;
+        let base_tree = repo.find_commit(base_id)?.tree()?;
+        let diffstat = diff_stat(&repo, &base_tree, &agent_commit.tree()?)?;
+
+        let (conflict_files, reason) = match outcome {
+            PreviewOutcome::Conflict(files) => (files, None),
+            PreviewOutcome::Blocked(reason) => (vec![], Some(reason)),
+            PreviewOutcome::Clean(_) => unreachable!(),
+        };
+
+        Ok(MergePreview {
+            clean: false,
+            strategy,
+            conflict_files,
+            diffstat,
+            tree_oid: None,
+            reason,
+        })
+    }
+
+    /// Replay agent commits onto target in memory
+    fn preview_rebase(
+        &self,
+        repo: &Repository,
+        target: &Commit,
+        agent: &Commit,
+    ) -> Result<PreviewOutcome, git2::Error> {
+        let annotated_target = repo.find_annotated_commit(target.id())?;
+        let annotated_agent = repo.find_annotated_commit(agent.id())?;
+
+        let mut opts = RebaseOptions::new();
+        opts.inmemory(true);
+
+        let mut rebase = repo.rebase(
+            Some(&annotated_agent),
+            Some(&annotated_target),
+            None,
+            Some(&mut opts),
+        )?;
+
+        let sig = self.default_signature()?;
+        let mut tree_id = target.tree_id();
+
+        while let Some(op) = rebase.next() {
+            if let Err(e) = op {
+                rebase.abort()?;
+                return Err(e);
+            }
+
+            let index = rebase.inmemory_index()?;
+            if index.has_conflicts() {
+                let conflicts = self.get_conflict_files(&index)?;
+                rebase.abort()?;
+                return Ok(PreviewOutcome::Conflict(conflicts));
+            }
+
+            match rebase.commit(None, &sig, None) {
+                Ok(commit_id) => tree_id = repo.find_commit(commit_id)?.tree_id(),
+                // Patch already present upstream, nothing to commit
+                Err(e) if e.code() == ErrorCode::Applied => {}
+                Err(e) => {
+                    rebase.abort()?;
+                    return Err(e);
+                }
+            }
         }
+
+        rebase.abort()?;
+        Ok(PreviewOutcome::Clean(tree_id))
+    }
+
+    /// Merge several agent branches into one target with a single commit.
+    ///
+    /// Heads are folded into the target tree one at a time in memory. If any
+    /// step conflicts nothing is written and every pair of branches (the
+    /// target included) is checked so the caller can see which ones clash.
+    /// Branches that no longer exist are left out and reported as missing.
+    pub fn octopus_merge(
+        &self,
+        target_branch: &str,
+        session_id: &str,
+        entries: &[QueueEntry],
+    ) -> DaemonResult<OctopusResult> {
+        let repo = Repository::open(&self.repo_path)?;
+        let target_commit = find_branch_commit(&repo, target_branch)?;
+
+        let mut heads = Vec::new();
+        let mut missing = Vec::new();
+        for entry in entries {
+            let commit = match find_branch_commit(&repo, &entry.branch) {
+                Ok(commit) => commit,
+                Err(DaemonError::BranchNotFound(branch)) => {
+                    warn!(
+                        "Leaving agent {} out: branch {} not found",
+                        entry.agent_id, branch
+                    );
+                    missing.push(entry.agent_id.clone());
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            if !is_ancestor(&repo, &commit, &target_commit)? {
+                heads.push((entry, commit));
+            }
+        }
+
+        if heads.is_empty() {
+            info!("Session {} is already merged into {}", session_id, target_branch);
+            return Ok(OctopusResult::Success {
+                commit_sha: target_commit.id().to_string(),
+                missing,
+            });
+        }
+
+        let policy = self.config.policy_for(target_branch);
+        // Even a single head gets a merge commit
+        if policy.require_linear_history {
+            return Ok(OctopusResult::Failed {
+                error: "Linear history required: octopus merge would create a merge commit"
+                    .to_string(),
+            });
+        }
+
+        debug!(
+            "Octopus merging {} branch(es) into {} ({})",
+            heads.len(),
+            target_branch,
+            target_commit.id()
+        );
+
+        let mut opts = MergeOptions::new();
+        opts.fail_on_conflict(false);
+
+        let mut tree = target_commit.tree()?;
+        for (_, commit) in &heads {
+            let base_id = repo.merge_base(target_commit.id(), commit.id())?;
+            let base_tree = repo.find_commit(base_id)?.tree()?;
+
+            let mut index = repo.merge_trees(&base_tree, &tree, &commit.tree()?, Some(&opts))?;
+            if index.has_conflicts() {
+                let pairs = self.pairwise_conflicts(&repo, target_branch, &target_commit, &heads)?;
+                return Ok(OctopusResult::Conflict { pairs });
+            }
+
+            tree = repo.find_tree(index.write_tree_to(&repo)?)?;
+        }
+
+        let sig = self.default_signature()?;
+        let mut message = format!(
+            "Octopus merge session {} into {}\n\nAgents:\n",
+            session_id, target_branch
+        );
+        for (entry, _) in &heads {
+            message.push_str(&format!("- {} ({})\n", entry.agent_id, entry.branch));
+        }
+
+        let mut parents = vec![&target_commit];
+        parents.extend(heads.iter().map(|(_, commit)| commit));
+
+        let refname = format!("refs/heads/{}", target_branch);
+        let commit_id = repo.commit(Some(&refname), &sig, &sig, &message, &tree, &parents)?;
+
+        repo.set_head(&refname)?;
+        repo.checkout_head(Some(git2::build::CheckoutBuilder::default().force()))?;
+
+        Ok(OctopusResult::Success {
+            commit_sha: commit_id.to_string(),
+            missing,
+        })
+    }
+
+    /// Find every pair of branches that conflicts when merged on its own
+    fn pairwise_conflicts(
+        &self,
+        repo: &Repository,
+        target_branch: &str,
+        target: &Commit,
+        heads: &[(&QueueEntry, Commit)],
+    ) -> Result<Vec<PairConflict>, git2::Error> {
+        let mut branches = vec![(target_branch, target)];
+        branches.extend(heads.iter().map(|(entry, commit)| (entry.branch.as_str(), commit)));
+
+        let mut opts = MergeOptions::new();
+        opts.fail_on_conflict(false);
+
+        let mut pairs = Vec::new();
+        for (i, (left, left_commit)) in branches.iter().enumerate() {
+            for (right, right_commit) in &branches[i + 1..] {
+                let index = repo.merge_commits(left_commit, right_commit, Some(&opts))?;
+                if index.has_conflicts() {
+                    pairs.push(PairConflict {
+                        left: left.to_string(),
+                        right: right.to_string(),
+                        files: self.get_conflict_files(&index)?,
+                    });
+                }
+            }
+        }
+
+        Ok(pairs)
     }
 
     /// Perform a standard merge
@@ -73,9 +446,7 @@ impl Merger {
         }
 
         if analysis.is_fast_forward() {
-            // Fast-forward merge
-            let refname = format!("refs/heads/{}", entry.target_branch);
-            repo.reference(&refname, agent.id(), true, "fast-forward merge")?;
+            self.fast_forward(repo, &entry.target_branch, agent.id())?;
 
             return Ok(MergeResult::Success {
                 commit_sha: agent.id().to_string(),
@@ -126,7 +497,7 @@ impl Merger {
         repo: &Repository,
         target: &Commit,
         agent: &Commit,
-        _entry: &QueueEntry,
+        entry: &QueueEntry,
     ) -> Result<MergeResult, git2::Error> {
         // For rebase, we replay agent commits on top of target
         // This is a simplified version - full rebase would handle multiple commits
@@ -157,6 +528,9 @@ impl Merger {
                     // Commit this step
                     if let Err(e) = rebase.commit(None, &sig, None) {
                         rebase.abort()?;
+                        if is_transient(&e) {
+                            return Err(e);
+                        }
                         return Ok(MergeResult::Failed {
                             error: format!("Rebase commit failed: {}", e),
                         });
@@ -164,6 +538,9 @@ impl Merger {
                 }
                 Err(e) => {
                     rebase.abort()?;
+                    if is_transient(&e) {
+                        return Err(e);
+                    }
                     return Ok(MergeResult::Failed {
                         error: format!("Rebase step failed: {}", e),
                     });
@@ -174,14 +551,66 @@ impl Merger {
         // Finish the rebase
         rebase.finish(Some(&sig))?;
 
-        // Get the final commit
+        // Get the final commit and fast-forward the target onto it
         let head = repo.head()?.peel_to_commit()?;
+        self.fast_forward(repo, &entry.target_branch, head.id())?;
 
         Ok(MergeResult::Success {
             commit_sha: head.id().to_string(),
         })
     }
 
+    /// Fast-forward the target to the agent branch, never creating a merge
+    /// commit. When the branches have diverged, rebase first if allowed.
+    fn do_fast_forward(
+        &self,
+        repo: &Repository,
+        target: &Commit,
+        agent: &Commit,
+        entry: &QueueEntry,
+        auto_rebase: bool,
+    ) -> Result<MergeResult, git2::Error> {
+        if is_ancestor(repo, agent, target)? {
+            info!("Branch {} is already up to date", entry.branch);
+            return Ok(MergeResult::Success {
+                commit_sha: target.id().to_string(),
+            });
+        }
+
+        if is_ancestor(repo, target, agent)? {
+            self.fast_forward(repo, &entry.target_branch, agent.id())?;
+            return Ok(MergeResult::Success {
+                commit_sha: agent.id().to_string(),
+            });
+        }
+
+        if auto_rebase {
+            info!(
+                "Fast-forward of {} not possible, rebasing onto {}",
+                entry.branch, entry.target_branch
+            );
+            return self.do_rebase(repo, target, agent, entry);
+        }
+
+        Ok(MergeResult::Failed {
+            error: not_fast_forward_reason(&entry.branch, &entry.target_branch),
+        })
+    }
+
+    /// Point the target branch at `oid` and check it out
+    fn fast_forward(
+        &self,
+        repo: &Repository,
+        target_branch: &str,
+        oid: Oid,
+    ) -> Result<(), git2::Error> {
+        let refname = format!("refs/heads/{}", target_branch);
+        repo.reference(&refname, oid, true, "fast-forward merge")?;
+        repo.set_head(&refname)?;
+        repo.checkout_head(Some(git2::build::CheckoutBuilder::default().force()))?;
+        Ok(())
+    }
+
     /// Perform a squash merge
     fn do_squash(
         &self,
@@ -235,7 +664,7 @@ impl Merger {
 
             // Get the path from any of the conflict entries
             if let Some(entry) = conflict.our.or(conflict.their).or(conflict.ancestor) {
-                if let Some(path) = std::str::from_utf8(&entry.path).ok() {
+                if let Ok(path) = std::str::from_utf8(&entry.path) {
                     conflicts.push(path.to_string());
                 }
             }
@@ -249,3 +678,97 @@ impl Merger {
         Signature::now("Agent Fork-Join", "agent-fork-join@localhost")
     }
 }
+
+/// Whether a git error is likely to clear up on its own, such as a stale
+/// `index.lock` or a ref updated concurrently by another process
+pub fn is_transient(err: &git2::Error) -> bool {
+    matches!(err.code(), ErrorCode::Locked | ErrorCode::Modified)
+}
+
+/// Resolve a local branch to its tip commit
+pub fn find_branch_commit<'r>(repo: &'r Repository, name: &str) -> DaemonResult<Commit<'r>> {
+    let branch = repo
+        .find_branch(name, git2::BranchType::Local)
+        .map_err(|e| match e.code() {
+            ErrorCode::NotFound => DaemonError::BranchNotFound(name.to_string()),
+            _ => DaemonError::Git(e),
+        })?;
+
+    Ok(branch.get().peel_to_commit()?)
+}
+
+/// Whether `ancestor` is reachable from (or equal to) `descendant`
+fn is_ancestor(
+    repo: &Repository,
+    ancestor: &Commit,
+    descendant: &Commit,
+) -> Result<bool, git2::Error> {
+    Ok(ancestor.id() == descendant.id()
+        || repo.graph_descendant_of(descendant.id(), ancestor.id())?)
+}
+
+/// Explain why a fast-forward-only merge was refused
+fn not_fast_forward_reason(branch: &str, target_branch: &str) -> String {
+    format!(
+        "Fast-forward not possible: {} has diverged from {}; rebase {} onto {} and retry",
+        target_branch, branch, branch, target_branch
+    )
+}
+
+/// Check whether merging `agent` into `target` under `policy` would add a
+/// merge commit to the target's history
+fn linear_history_violation(
+    repo: &Repository,
+    target: &Commit,
+    agent: &Commit,
+    policy: &MergePolicy,
+) -> Result<Option<String>, git2::Error> {
+    match policy.merge_strategy {
+        MergeStrategy::Squash => return Ok(None),
+        MergeStrategy::Octopus => {
+            return Ok(Some(
+                "Linear history required: octopus strategy creates merge commits".to_string(),
+            ));
+        }
+        _ => {}
+    }
+
+    let mut revwalk = repo.revwalk()?;
+    revwalk.push(agent.id())?;
+    revwalk.hide(target.id())?;
+
+    for oid in revwalk {
+        let commit = repo.find_commit(oid?)?;
+        if commit.parent_count() > 1 {
+            return Ok(Some(format!(
+                "Linear history required: commit {} is a merge commit",
+                commit.id()
+            )));
+        }
+    }
+
+    if policy.merge_strategy == MergeStrategy::Merge && !is_ancestor(repo, target, agent)? {
+        return Ok(Some(
+            "Linear history required: merge strategy would create a merge commit".to_string(),
+        ));
+    }
+
+    Ok(None)
+}
+
+/// Compute a diffstat between two trees
+fn diff_stat(
+    repo: &Repository,
+    old_tree: &git2::Tree,
+    new_tree: &git2::Tree,
+) -> Result<DiffStat, git2::Error> {
+    let stats = repo
+        .diff_tree_to_tree(Some(old_tree), Some(new_tree), None)?
+        .stats()?;
+
+    Ok(DiffStat {
+        files_changed: stats.files_changed(),
+        insertions: stats.insertions(),
+        deletions: stats.deletions(),
+    })
+}
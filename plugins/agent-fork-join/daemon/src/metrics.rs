@@ -0,0 +1,311 @@
+//! Prometheus metrics for the merge queues
+//!
+//! Every [`MergeQueue`](crate::queue::MergeQueue) records into its own
+//! [`Metrics`]; [`render`] writes the metrics of all repositories in the
+//! Prometheus text format, labelled with their repo ID.
+
+use crate::config::MergeStrategy;
+use crate::queue::EntryStatus;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Histogram buckets for merge durations, in seconds
+const MERGE_DURATION_BUCKETS: &[f64] = &[0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 300.0];
+
+/// Histogram buckets for time from enqueue to first merge attempt
+const WAIT_TIME_BUCKETS: &[f64] = &[1.0, 5.0, 15.0, 30.0, 60.0, 300.0, 900.0, 1800.0, 3600.0];
+
+/// Histogram buckets for validation gates
+const GATE_DURATION_BUCKETS: &[f64] = &[0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0];
+
+/// Metrics recorded by one merge queue
+#[derive(Debug, Default)]
+pub struct Metrics {
+    registry: Mutex<Registry>,
+}
+
+/// The recorded values
+#[derive(Debug, Clone, Default)]
+pub struct Registry {
+    /// Merge attempts by result and strategy
+    merges: BTreeMap<(&'static str, String), u64>,
+    /// Retries by kind: automatic after a transient error, or manual
+    retries: BTreeMap<&'static str, u64>,
+    /// Conflicts by file path
+    conflicts: BTreeMap<String, u64>,
+    merge_duration: Histogram,
+    wait_time: Histogram,
+    /// Validation gate durations by gate and result
+    gates: BTreeMap<(&'static str, &'static str), Histogram>,
+}
+
+/// Cumulative histogram over fixed buckets
+#[derive(Debug, Clone, Default)]
+struct Histogram {
+    /// Observations per bucket, not cumulative
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, buckets: &[f64], value: f64) {
+        if self.counts.is_empty() {
+            self.counts = vec![0; buckets.len()];
+        }
+        if let Some(index) = buckets.iter().position(|bound| value <= *bound) {
+            self.counts[index] += 1;
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+}
+
+impl Metrics {
+    /// Count a finished merge attempt. `result` is the entry's new status,
+    /// or "retried" for a transient error that will be retried.
+    pub fn record_merge(&self, result: &'static str, strategy: MergeStrategy) {
+        let strategy = strategy_label(strategy);
+        *self.lock().merges.entry((result, strategy)).or_default() += 1;
+    }
+
+    /// Observe how long a merge attempt took
+    pub fn record_merge_duration(&self, duration: Duration) {
+        self.lock()
+            .merge_duration
+            .observe(MERGE_DURATION_BUCKETS, duration.as_secs_f64());
+    }
+
+    /// Observe how long an entry waited before its first merge attempt
+    pub fn record_wait_time(&self, duration: Duration) {
+        self.lock()
+            .wait_time
+            .observe(WAIT_TIME_BUCKETS, duration.as_secs_f64());
+    }
+
+    /// Count a retry, "automatic" or "manual"
+    pub fn record_retry(&self, kind: &'static str) {
+        *self.lock().retries.entry(kind).or_default() += 1;
+    }
+
+    /// Count a conflict in each of `files`
+    pub fn record_conflicts(&self, files: &[String]) {
+        let mut registry = self.lock();
+        for file in files {
+            *registry.conflicts.entry(file.clone()).or_default() += 1;
+        }
+    }
+
+    /// Observe how long a validation gate took and whether it passed
+    pub fn record_gate(&self, gate: &'static str, passed: bool, duration: Duration) {
+        let result = if passed { "passed" } else { "failed" };
+        self.lock()
+            .gates
+            .entry((gate, result))
+            .or_default()
+            .observe(GATE_DURATION_BUCKETS, duration.as_secs_f64());
+    }
+
+    /// The values recorded so far
+    pub fn snapshot(&self) -> Registry {
+        self.lock().clone()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Registry> {
+        self.registry.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// One repository's metrics at scrape time
+#[derive(Debug, Clone)]
+pub struct RepoMetrics {
+    pub repo_id: String,
+    /// Entries in memory by status
+    pub depth: Vec<(EntryStatus, usize)>,
+    pub registry: Registry,
+}
+
+/// Render metrics for every repository in the Prometheus text format
+pub fn render(repos: &[RepoMetrics]) -> String {
+    let mut out = String::new();
+
+    family(
+        &mut out,
+        "merge_daemon_queue_entries",
+        "gauge",
+        "Queue entries in memory by status",
+    );
+    for repo in repos {
+        for (status, count) in &repo.depth {
+            let status = format!("{:?}", status).to_lowercase();
+            sample(
+                &mut out,
+                "merge_daemon_queue_entries",
+                &[("repo", &repo.repo_id), ("status", &status)],
+                *count as f64,
+            );
+        }
+    }
+
+    family(
+        &mut out,
+        "merge_daemon_merges_total",
+        "counter",
+        "Merge attempts by result and strategy",
+    );
+    for repo in repos {
+        for ((result, strategy), count) in &repo.registry.merges {
+            sample(
+                &mut out,
+                "merge_daemon_merges_total",
+                &[
+                    ("repo", &repo.repo_id),
+                    ("result", result),
+                    ("strategy", strategy),
+                ],
+                *count as f64,
+            );
+        }
+    }
+
+    family(
+        &mut out,
+        "merge_daemon_merge_duration_seconds",
+        "histogram",
+        "Time spent on a merge attempt",
+    );
+    for repo in repos {
+        histogram(
+            &mut out,
+            "merge_daemon_merge_duration_seconds",
+            &[("repo", &repo.repo_id)],
+            MERGE_DURATION_BUCKETS,
+            &repo.registry.merge_duration,
+        );
+    }
+
+    family(
+        &mut out,
+        "merge_daemon_wait_seconds",
+        "histogram",
+        "Time from enqueue to the first merge attempt",
+    );
+    for repo in repos {
+        histogram(
+            &mut out,
+            "merge_daemon_wait_seconds",
+            &[("repo", &repo.repo_id)],
+            WAIT_TIME_BUCKETS,
+            &repo.registry.wait_time,
+        );
+    }
+
+    family(
+        &mut out,
+        "merge_daemon_retries_total",
+        "counter",
+        "Merges re-queued, automatically after transient errors or by RETRY",
+    );
+    for repo in repos {
+        for (kind, count) in &repo.registry.retries {
+            sample(
+                &mut out,
+                "merge_daemon_retries_total",
+                &[("repo", &repo.repo_id), ("kind", kind)],
+                *count as f64,
+            );
+        }
+    }
+
+    family(
+        &mut out,
+        "merge_daemon_conflicts_total",
+        "counter",
+        "Merge conflicts by file path",
+    );
+    for repo in repos {
+        for (path, count) in &repo.registry.conflicts {
+            sample(
+                &mut out,
+                "merge_daemon_conflicts_total",
+                &[("repo", &repo.repo_id), ("path", path)],
+                *count as f64,
+            );
+        }
+    }
+
+    family(
+        &mut out,
+        "merge_daemon_validation_duration_seconds",
+        "histogram",
+        "Time spent on validation gates before merging",
+    );
+    for repo in repos {
+        for ((gate, result), values) in &repo.registry.gates {
+            histogram(
+                &mut out,
+                "merge_daemon_validation_duration_seconds",
+                &[("repo", &repo.repo_id), ("gate", gate), ("result", result)],
+                GATE_DURATION_BUCKETS,
+                values,
+            );
+        }
+    }
+
+    out
+}
+
+fn strategy_label(strategy: MergeStrategy) -> String {
+    serde_json::to_value(strategy)
+        .ok()
+        .and_then(|value| value.as_str().map(String::from))
+        .unwrap_or_default()
+}
+
+fn family(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn sample(out: &mut String, name: &str, labels: &[(&str, &str)], value: f64) {
+    let labels: Vec<String> = labels
+        .iter()
+        .map(|(key, value)| format!("{}=\"{}\"", key, escape(value)))
+        .collect();
+    let _ = writeln!(out, "{}{{{}}} {}", name, labels.join(","), value);
+}
+
+fn histogram(
+    out: &mut String,
+    name: &str,
+    labels: &[(&str, &str)],
+    buckets: &[f64],
+    values: &Histogram,
+) {
+    let bucket_name = format!("{}_bucket", name);
+    let mut cumulative = 0;
+
+    for (index, bound) in buckets.iter().enumerate() {
+        cumulative += values.counts.get(index).copied().unwrap_or(0);
+        let le = bound.to_string();
+        let mut bucket_labels = labels.to_vec();
+        bucket_labels.push(("le", &le));
+        sample(out, &bucket_name, &bucket_labels, cumulative as f64);
+    }
+
+    let mut bucket_labels = labels.to_vec();
+    bucket_labels.push(("le", "+Inf"));
+    sample(out, &bucket_name, &bucket_labels, values.count as f64);
+    sample(out, &format!("{}_sum", name), labels, values.sum);
+    sample(out, &format!("{}_count", name), labels, values.count as f64);
+}
+
+/// Escape a label value
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
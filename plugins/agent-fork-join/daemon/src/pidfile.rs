@@ -0,0 +1,79 @@
+//! PID file that keeps a second daemon off the same state database
+
+use crate::error::{DaemonError, DaemonResult};
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// An exclusively locked PID file, held for the daemon's lifetime
+///
+/// The lock is released when the file is closed, so a daemon that crashes
+/// never leaves a stale lock behind; only the PID in it goes stale.
+#[derive(Debug)]
+pub struct PidFile {
+    path: PathBuf,
+    file: File,
+}
+
+impl PidFile {
+    /// The PID file for the state database at `db_path`
+    pub fn for_db(db_path: &Path) -> PathBuf {
+        db_path.with_file_name("daemon.pid")
+    }
+
+    /// Lock the PID file at `path` and write our PID to it. Fails with
+    /// `AlreadyRunning` if another daemon holds the lock.
+    pub fn acquire(path: impl Into<PathBuf>) -> DaemonResult<Self> {
+        let path = path.into();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+        lock(&file, &path)?;
+
+        file.set_len(0)?;
+        writeln!(file, "{}", std::process::id())?;
+        file.sync_all()?;
+
+        Ok(Self { path, file })
+    }
+
+    /// Fail with `AlreadyRunning` if a daemon holds the PID file at `path`
+    pub fn check(path: &Path) -> DaemonResult<()> {
+        match File::open(path) {
+            Ok(file) => lock(&file, path),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// The PID written to the file at `path`, if any
+    pub fn read(path: &Path) -> Option<u32> {
+        std::fs::read_to_string(path).ok()?.trim().parse().ok()
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Remove the file and release the lock
+    pub fn release(self) {
+        let _ = std::fs::remove_file(&self.path);
+        drop(self.file);
+    }
+}
+
+/// Take the lock on `file`, or report who holds it
+fn lock(file: &File, path: &Path) -> DaemonResult<()> {
+    match file.try_lock() {
+        Ok(()) => Ok(()),
+        Err(TryLockError::WouldBlock) => Err(DaemonError::AlreadyRunning(PidFile::read(path))),
+        Err(TryLockError::Error(e)) => Err(e.into()),
+    }
+}
@@ -0,0 +1,522 @@
+//! Wire protocol shared by the daemon and its clients
+//!
+//! Clients send one JSON `Request` per line and get one JSON `Response`
+//! line back. A request may carry a `request_id`, which is echoed in the
+//! response envelope, and a `repo`, a repo ID or absolute repository path
+//! selecting the queue it applies to; without one it goes to the daemon's
+//! default repository. Clients should send HELLO first to check the
+//! protocol version and which requests the daemon supports.
+
+use crate::error::DaemonError;
+use crate::merger::MergePreview;
+use crate::queue::{QueueEntry, QueueStatus, ReapReport, SessionMergeReport, WorktreeListing};
+use crate::repos::RepoInfo;
+use crate::state::MergeRecord;
+use crate::worktree::{AgentCommit, AgentWorktree};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::PathBuf;
+
+/// Version of this protocol, bumped on incompatible changes
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Oldest protocol version the daemon still accepts in HELLO
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+
+/// Request types the daemon understands, reported as capabilities by HELLO
+pub const CAPABILITIES: &[&str] = &[
+    "HELLO",
+    "REGISTER_REPO",
+    "REPOS",
+    "REGISTER",
+    "ENQUEUE",
+    "COMMIT_AND_ENQUEUE",
+    "TRACK_FILE",
+    "REPRIORITIZE",
+    "DEQUEUE",
+    "STATUS",
+    "CONFLICTS",
+    "ENTRY",
+    "RETRY",
+    "PREVIEW",
+    "WAIT",
+    "HISTORY",
+    "SESSION_END",
+    "SESSION_MERGE",
+    "REAP",
+    "WORKTREES",
+    "WORKTREE_REMOVE",
+    "WORKTREE_PRUNE",
+    "SHUTDOWN",
+];
+
+/// Request types from clients
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "SCREAMING_SNAKE_CASE", deny_unknown_fields)]
+pub enum Request {
+    /// Handshake: report protocol version, daemon version and capabilities
+    Hello {
+        /// Protocol version the client speaks
+        #[serde(default)]
+        protocol_version: Option<u32>,
+        #[serde(default)]
+        client_version: Option<String>,
+    },
+
+    /// Serve another repository, with its own queue and configuration
+    RegisterRepo {
+        /// Absolute path of the repository
+        path: String,
+        /// Overrides of the daemon's configuration for this repository
+        #[serde(default)]
+        config: Option<serde_json::Value>,
+    },
+
+    /// List the repositories the daemon serves
+    Repos,
+
+    /// Register a new agent, optionally creating its worktree and branch
+    Register {
+        agent_id: String,
+        #[serde(default)]
+        session_id: Option<String>,
+        /// Branch the agent branch starts from
+        #[serde(default)]
+        base_branch: Option<String>,
+        #[serde(default)]
+        create_worktree: bool,
+    },
+
+    /// Enqueue a branch for merging
+    Enqueue {
+        agent_id: String,
+        session_id: String,
+        branch: String,
+        worktree: String,
+        target_branch: String,
+        #[serde(default)]
+        priority: i32,
+        #[serde(default)]
+        depends_on: Vec<String>,
+    },
+
+    /// Commit an agent's worktree and enqueue it for merge
+    CommitAndEnqueue {
+        agent_id: String,
+        session_id: String,
+        worktree: String,
+        message: String,
+        target_branch: String,
+        /// Commit only files reported via TRACK_FILE
+        #[serde(default)]
+        tracked_only: bool,
+        #[serde(default)]
+        priority: i32,
+        #[serde(default)]
+        depends_on: Vec<String>,
+    },
+
+    /// Record a file written by an agent
+    TrackFile { agent_id: String, path: String },
+
+    /// Change the priority of a queued agent
+    Reprioritize { agent_id: String, priority: i32 },
+
+    /// Remove an agent from the queue
+    Dequeue { agent_id: String },
+
+    /// Get queue status
+    Status,
+
+    /// Get conflicts for an agent
+    Conflicts { agent_id: String },
+
+    /// Get an agent's latest queue entry
+    Entry { agent_id: String },
+
+    /// Retry a failed merge
+    Retry { agent_id: String },
+
+    /// Check whether a branch would merge cleanly, without merging it
+    Preview {
+        branch: String,
+        target_branch: String,
+    },
+
+    /// Wait for an agent's merge to finish or conflict
+    Wait {
+        agent_id: String,
+        /// Give up and report the current state after this many seconds
+        #[serde(default)]
+        timeout_secs: Option<u64>,
+    },
+
+    /// List completed merges, newest first
+    History {
+        #[serde(default)]
+        session_id: Option<String>,
+        #[serde(default)]
+        agent_id: Option<String>,
+        #[serde(default)]
+        limit: Option<usize>,
+    },
+
+    /// End a session
+    SessionEnd { session_id: String },
+
+    /// Merge all pending agents of a session in one octopus commit
+    SessionMerge { session_id: String },
+
+    /// Cancel and clean up stale sessions now
+    Reap {
+        #[serde(default)]
+        dry_run: bool,
+    },
+
+    /// List agent worktrees with their disk usage and state
+    Worktrees,
+
+    /// Remove an agent's worktree, and optionally its branch
+    WorktreeRemove {
+        worktree: String,
+        #[serde(default)]
+        branch: Option<String>,
+    },
+
+    /// Clean up worktrees left behind by crashed agents
+    WorktreePrune {
+        #[serde(default)]
+        dry_run: bool,
+    },
+
+    /// Shutdown the daemon
+    Shutdown,
+}
+
+impl Request {
+    /// Whether the request only reads state
+    pub fn is_read_only(&self) -> bool {
+        matches!(
+            self,
+            Request::Hello { .. }
+                | Request::Repos
+                | Request::Status
+                | Request::Conflicts { .. }
+                | Request::Entry { .. }
+                | Request::Preview { .. }
+                | Request::Wait { .. }
+                | Request::History { .. }
+                | Request::Worktrees
+        )
+    }
+}
+
+/// Response to clients
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum Response {
+    Ok {
+        status: &'static str,
+    },
+    Hello {
+        status: &'static str,
+        #[serde(flatten)]
+        hello: Hello,
+    },
+    Repo {
+        status: &'static str,
+        #[serde(flatten)]
+        repo: RepoInfo,
+    },
+    Repos {
+        status: &'static str,
+        repos: Vec<RepoInfo>,
+    },
+    Position {
+        status: &'static str,
+        position: usize,
+    },
+    Committed {
+        status: &'static str,
+        #[serde(flatten)]
+        commit: AgentCommit,
+        position: usize,
+    },
+    NoChanges {
+        status: &'static str,
+        agent_id: String,
+    },
+    Status {
+        status: &'static str,
+        #[serde(flatten)]
+        queue: QueueStatus,
+    },
+    Conflicts {
+        status: &'static str,
+        files: Vec<String>,
+    },
+    Entry {
+        status: &'static str,
+        entry: QueueEntry,
+    },
+    MergeResult {
+        status: &'static str,
+        #[serde(flatten)]
+        outcome: MergeOutcome,
+    },
+    History {
+        status: &'static str,
+        merges: Vec<MergeRecord>,
+    },
+    Preview {
+        status: &'static str,
+        #[serde(flatten)]
+        preview: MergePreview,
+    },
+    SessionMerge {
+        status: &'static str,
+        #[serde(flatten)]
+        report: SessionMergeReport,
+    },
+    Reap {
+        status: &'static str,
+        #[serde(flatten)]
+        report: ReapReport,
+    },
+    Worktree {
+        status: &'static str,
+        #[serde(flatten)]
+        worktree: AgentWorktree,
+    },
+    Worktrees {
+        status: &'static str,
+        worktrees: Vec<WorktreeListing>,
+    },
+    Pruned {
+        status: &'static str,
+        dry_run: bool,
+        pruned: Vec<PathBuf>,
+    },
+    Error {
+        status: &'static str,
+        error: String,
+        /// Machine-readable error code, e.g. QUEUE_FULL
+        code: String,
+        /// Whether the same request may succeed later
+        retryable: bool,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        details: Option<serde_json::Value>,
+    },
+}
+
+impl Response {
+    /// Error response for a failed request
+    pub fn error(e: &DaemonError) -> Self {
+        Response::Error {
+            status: "ERROR",
+            error: e.to_string(),
+            code: e.code().to_string(),
+            retryable: e.retryable(),
+            details: e.details(),
+        }
+    }
+
+    /// Error response for a request that could not be parsed. The reason
+    /// is given in `details`.
+    pub fn rejected(reason: RequestError) -> Self {
+        let code = match reason {
+            RequestError::UnknownRequest { .. } => "UNKNOWN_REQUEST",
+            RequestError::UnsupportedProtocol { .. } => "UNSUPPORTED_PROTOCOL",
+            _ => "INVALID_REQUEST",
+        };
+
+        Response::Error {
+            status: "ERROR",
+            error: reason.to_string(),
+            code: code.to_string(),
+            retryable: false,
+            details: serde_json::to_value(&reason).ok(),
+        }
+    }
+}
+
+/// Envelope fields of a request line, besides the request itself
+#[derive(Debug, Clone, Default)]
+pub struct RequestMeta {
+    /// Echoed in the response envelope
+    pub request_id: Option<serde_json::Value>,
+    /// Repo ID or absolute repository path the request applies to
+    pub repo: Option<String>,
+    /// The request's `type`, if it names a known request
+    pub request_type: Option<String>,
+}
+
+/// A response as written to the socket
+#[derive(Debug, Serialize)]
+pub struct Envelope {
+    /// The request's `request_id`, or null if it had none
+    pub request_id: Option<serde_json::Value>,
+    #[serde(flatten)]
+    pub response: Response,
+}
+
+/// Result of a WAIT request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MergeOutcome {
+    /// MERGED, CONFLICT, FAILED or CANCELLED, or PENDING/PROCESSING if the
+    /// wait timed out
+    pub result: String,
+    pub details: Option<String>,
+}
+
+/// Reply to HELLO
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Hello {
+    pub protocol_version: u32,
+    pub min_protocol_version: u32,
+    pub daemon_version: String,
+    /// Request types the daemon supports
+    pub capabilities: Vec<String>,
+}
+
+impl Hello {
+    /// This daemon's handshake reply
+    pub fn current() -> Self {
+        Self {
+            protocol_version: PROTOCOL_VERSION,
+            min_protocol_version: MIN_PROTOCOL_VERSION,
+            daemon_version: env!("CARGO_PKG_VERSION").to_string(),
+            capabilities: CAPABILITIES.iter().map(|c| c.to_string()).collect(),
+        }
+    }
+
+    /// Whether the daemon supports a request type
+    pub fn supports(&self, request_type: &str) -> bool {
+        self.capabilities.iter().any(|c| c == request_type)
+    }
+}
+
+/// Why a request line was rejected
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "reason", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum RequestError {
+    /// Not a JSON object
+    Malformed { message: String },
+    /// No `type` field
+    MissingType,
+    /// A request type this daemon doesn't know
+    UnknownRequest { request_type: String },
+    /// A field the request type doesn't have
+    UnknownField { request_type: String, field: String },
+    /// A missing or mistyped field
+    InvalidField {
+        request_type: String,
+        message: String,
+    },
+    /// HELLO with a protocol version the daemon can't speak
+    UnsupportedProtocol { protocol_version: u32 },
+}
+
+impl fmt::Display for RequestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RequestError::Malformed { message } => write!(f, "Invalid request: {}", message),
+            RequestError::MissingType => write!(f, "Invalid request: missing field `type`"),
+            RequestError::UnknownRequest { request_type } => {
+                write!(f, "Unknown request type: {}", request_type)
+            }
+            RequestError::UnknownField {
+                request_type,
+                field,
+            } => write!(f, "Unknown field `{}` in {} request", field, request_type),
+            RequestError::InvalidField {
+                request_type,
+                message,
+            } => write!(f, "Invalid {} request: {}", request_type, message),
+            RequestError::UnsupportedProtocol { protocol_version } => write!(
+                f,
+                "Unsupported protocol version {} (daemon supports {} to {})",
+                protocol_version, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION
+            ),
+        }
+    }
+}
+
+/// Parse a request line. Returns the request's envelope fields alongside
+/// the request or the reason it was rejected.
+pub fn parse_request(line: &str) -> (RequestMeta, Result<Request, RequestError>) {
+    let mut meta = RequestMeta::default();
+
+    let mut object = match serde_json::from_str::<serde_json::Value>(line) {
+        Ok(serde_json::Value::Object(object)) => object,
+        Ok(_) => {
+            let message = "expected a JSON object".to_string();
+            return (meta, Err(RequestError::Malformed { message }));
+        }
+        Err(e) => {
+            let message = e.to_string();
+            return (meta, Err(RequestError::Malformed { message }));
+        }
+    };
+
+    meta.request_id = object.remove("request_id");
+
+    let Some(request_type) = object.get("type").and_then(|t| t.as_str()) else {
+        return (meta, Err(RequestError::MissingType));
+    };
+    let request_type = request_type.to_string();
+    if !CAPABILITIES.contains(&request_type.as_str()) {
+        return (meta, Err(RequestError::UnknownRequest { request_type }));
+    }
+    meta.request_type = Some(request_type.clone());
+
+    match object.remove("repo") {
+        None | Some(serde_json::Value::Null) => {}
+        Some(serde_json::Value::String(repo)) => meta.repo = Some(repo),
+        Some(_) => {
+            let message = "`repo` must be a string".to_string();
+            return (
+                meta,
+                Err(RequestError::InvalidField {
+                    request_type,
+                    message,
+                }),
+            );
+        }
+    }
+
+    // serde ignores extra fields on requests without any fields
+    let extra_field = object.keys().find(|key| *key != "type").cloned();
+    let result = match serde_json::from_value::<Request>(serde_json::Value::Object(object)) {
+        Ok(Request::Status | Request::Worktrees | Request::Repos | Request::Shutdown)
+            if extra_field.is_some() =>
+        {
+            Err(RequestError::UnknownField {
+                request_type,
+                field: extra_field.unwrap_or_default(),
+            })
+        }
+        Ok(request) => Ok(request),
+        Err(e) => {
+            let message = e.to_string();
+            match unknown_field(&message) {
+                Some(field) => Err(RequestError::UnknownField {
+                    request_type,
+                    field,
+                }),
+                None => Err(RequestError::InvalidField {
+                    request_type,
+                    message,
+                }),
+            }
+        }
+    };
+
+    (meta, result)
+}
+
+/// The field named in a serde "unknown field" error
+fn unknown_field(message: &str) -> Option<String> {
+    let rest = message.strip_prefix("unknown field `")?;
+    rest.split('`').next().map(str::to_string)
+}
@@ -1,18 +1,29 @@
 //! FIFO merge queue implementation
 
-use crate::config::Config;
+use crate::config::{Config, MergeStrategy};
 use crate::error::{DaemonError, DaemonResult};
-use crate::merger::Merger;
-use crate::state::StateManager;
-use chrono::{DateTime, Utc};
+use crate::events::{self, DaemonEvent};
+use crate::merger::{self, MergePreview, Merger, OctopusResult, PairConflict};
+use crate::metrics::Metrics;
+use crate::scheduler::{self, Scheduler};
+use crate::state::{MergeRecord, StateManager};
+use crate::worktree::{AgentCommit, WorktreeInfo, WorktreeManager};
+use chrono::{DateTime, Duration, Utc};
+use rand::Rng;
 use serde::{Deserialize, Serialize};
-use std::collections::VecDeque;
-use std::path::PathBuf;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use tokio::sync::{Mutex, Notify};
+use tokio::sync::{broadcast, watch, Mutex, Notify};
 use tracing::{debug, error, info, warn};
 use uuid::Uuid;
 
+/// How often pooled worktrees are refreshed to their branch tips
+const POOL_REFRESH_INTERVAL: tokio::time::Duration = tokio::time::Duration::from_secs(10);
+
+/// How often WAIT requests check on the entry they wait for
+const WAIT_POLL_INTERVAL: tokio::time::Duration = tokio::time::Duration::from_millis(250);
+
 /// Entry in the merge queue
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct QueueEntry {
@@ -34,6 +45,14 @@ pub struct QueueEntry {
     /// Target branch to merge into
     pub target_branch: String,
 
+    /// Scheduling priority; higher values merge first
+    #[serde(default)]
+    pub priority: i32,
+
+    /// Agents whose entries must be merged before this one
+    #[serde(default)]
+    pub depends_on: Vec<String>,
+
     /// Number of merge attempts
     pub attempts: u32,
 
@@ -48,6 +67,27 @@ pub struct QueueEntry {
 
     /// Conflicting files (if status is Conflict)
     pub conflict_files: Vec<String>,
+
+    /// When a transient failure will be retried automatically
+    #[serde(default)]
+    pub next_retry_at: Option<DateTime<Utc>>,
+
+    /// When the entry reached a terminal status
+    #[serde(default)]
+    pub finished_at: Option<DateTime<Utc>>,
+
+    /// Last time anything happened to this entry
+    #[serde(default = "Utc::now")]
+    pub updated_at: DateTime<Utc>,
+}
+
+impl QueueEntry {
+    /// Move to a new status, stamping when the entry became terminal
+    pub fn set_status(&mut self, status: EntryStatus) {
+        self.status = status;
+        self.finished_at = status.is_terminal().then(Utc::now);
+        self.updated_at = Utc::now();
+    }
 }
 
 /// Status of a queue entry
@@ -67,6 +107,34 @@ pub enum EntryStatus {
     Cancelled,
 }
 
+impl EntryStatus {
+    /// Whether the entry is finished and only kept for history. Conflicts
+    /// are not terminal since the agent is expected to fix and retry.
+    pub fn is_terminal(self) -> bool {
+        matches!(
+            self,
+            EntryStatus::Merged | EntryStatus::Failed | EntryStatus::Cancelled
+        )
+    }
+}
+
+/// Optional scheduling parameters for a new queue entry
+#[derive(Debug, Clone, Default)]
+pub struct EnqueueOptions {
+    /// Scheduling priority; higher values merge first
+    pub priority: i32,
+    /// Agents that must be merged first
+    pub depends_on: Vec<String>,
+}
+
+/// How to build an agent commit from its worktree
+#[derive(Debug, Clone, Default)]
+pub struct CommitOptions {
+    pub message: String,
+    /// Stage only files reported via TRACK_FILE
+    pub tracked_only: bool,
+}
+
 /// Result of a merge operation
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum MergeResult {
@@ -96,17 +164,36 @@ pub struct MergeQueue {
     /// Notification when new entries are added
     notify: Arc<Notify>,
 
-    /// Shutdown flag
-    shutdown: Arc<Mutex<bool>>,
+    /// Set once shutdown has started
+    shutdown: Arc<watch::Sender<bool>>,
 
     /// Merger for git operations
     merger: Arc<Merger>,
+
+    /// Serializes git operations on the shared working tree
+    merge_lock: Arc<Mutex<()>>,
+
+    /// Chooses between ready entries of equal priority
+    scheduler: Arc<Mutex<Box<dyn Scheduler>>>,
+
+    /// Broadcasts daemon events to subscribers
+    events: broadcast::Sender<DaemonEvent>,
+
+    /// Agent worktree lifecycle
+    worktrees: Arc<WorktreeManager>,
+
+    /// Merge counts and timings for the metrics endpoint
+    metrics: Arc<Metrics>,
 }
 
 impl MergeQueue {
     /// Create a new merge queue
     pub fn new(repo_path: PathBuf, state_manager: StateManager, config: Config) -> Self {
-        let merger = Arc::new(Merger::new(repo_path.clone(), config.clone()));
+        let metrics = Arc::new(Metrics::default());
+        let merger =
+            Arc::new(Merger::new(repo_path.clone(), config.clone()).with_metrics(metrics.clone()));
+        let scheduler = Arc::new(Mutex::new(scheduler::from_config(&config)));
+        let worktrees = Arc::new(WorktreeManager::new(repo_path.clone(), config.clone()));
 
         Self {
             queue: Arc::new(Mutex::new(VecDeque::new())),
@@ -114,22 +201,78 @@ impl MergeQueue {
             state_manager,
             config,
             notify: Arc::new(Notify::new()),
-            shutdown: Arc::new(Mutex::new(false)),
+            shutdown: Arc::new(watch::channel(false).0),
             merger,
+            merge_lock: Arc::new(Mutex::new(())),
+            scheduler,
+            events: events::channel(),
+            worktrees,
+            metrics,
         }
     }
 
+    /// Share `shutdown` with other queues, so shutting down one shuts down
+    /// all of them
+    pub fn with_shutdown(mut self, shutdown: Arc<watch::Sender<bool>>) -> Self {
+        self.shutdown = shutdown;
+        self
+    }
+
+    /// The repository this queue merges into
+    pub fn repo_path(&self) -> &Path {
+        &self.repo_path
+    }
+
+    /// Merge counts and timings recorded by this queue
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    /// Number of entries in memory with each status
+    pub async fn depth_by_status(&self) -> Vec<(EntryStatus, usize)> {
+        let queue = self.queue.lock().await;
+        [
+            EntryStatus::Pending,
+            EntryStatus::Processing,
+            EntryStatus::Merged,
+            EntryStatus::Conflict,
+            EntryStatus::Failed,
+            EntryStatus::Cancelled,
+        ]
+        .into_iter()
+        .map(|status| (status, queue.iter().filter(|e| e.status == status).count()))
+        .collect()
+    }
+
     /// Recover pending entries from persistent state
     pub async fn recover(&self) -> DaemonResult<usize> {
         let entries = self.state_manager.load_pending_entries().await?;
         let count = entries.len();
 
+        // Dependencies that already finished are not pending, but their
+        // outcome is still needed to schedule the entries that wait on them
+        let mut finished = Vec::new();
+        let mut seen = HashSet::new();
+        for dep in entries.iter().flat_map(|e| e.depends_on.iter()) {
+            if entries.iter().any(|e| &e.agent_id == dep) || !seen.insert(dep.as_str()) {
+                continue;
+            }
+            if let Some(entry) = self.state_manager.load_latest_entry(dep).await? {
+                finished.push(entry);
+            }
+        }
+
         let mut queue = self.queue.lock().await;
+        queue.extend(finished);
         for entry in entries {
-            if entry.status == EntryStatus::Pending || entry.status == EntryStatus::Processing {
-                let mut recovered = entry;
-                recovered.status = EntryStatus::Pending;
-                queue.push_back(recovered);
+            match entry.status {
+                EntryStatus::Pending | EntryStatus::Processing => {
+                    let mut recovered = entry;
+                    recovered.set_status(EntryStatus::Pending);
+                    queue.push_back(recovered);
+                }
+                EntryStatus::Conflict => queue.push_back(entry),
+                _ => {}
             }
         }
 
@@ -148,11 +291,20 @@ impl MergeQueue {
         branch: String,
         worktree: PathBuf,
         target_branch: String,
+        options: EnqueueOptions,
     ) -> DaemonResult<usize> {
+        self.check_accepting()?;
+
+        let EnqueueOptions {
+            priority,
+            depends_on,
+        } = options;
+
         let mut queue = self.queue.lock().await;
 
-        // Check if queue is full
-        if queue.len() >= self.config.max_queue_size {
+        // Check if queue is full. Finished entries are archived and do not
+        // take up capacity.
+        if active_len(&queue) >= self.config.max_queue_size {
             return Err(DaemonError::QueueFull(self.config.max_queue_size));
         }
 
@@ -161,6 +313,21 @@ impl MergeQueue {
             return Err(DaemonError::AgentAlreadyQueued(agent_id));
         }
 
+        if creates_cycle(&queue, &agent_id, &depends_on) {
+            return Err(DaemonError::DependencyCycle(agent_id));
+        }
+
+        // Bring back archived dependencies so their outcome is visible.
+        // Dependencies that aren't enqueued yet, such as agents still at
+        // work, keep the entry waiting until they arrive.
+        for dep in &depends_on {
+            if !queue.iter().any(|e| &e.agent_id == dep) {
+                if let Some(archived) = self.state_manager.load_latest_entry(dep).await? {
+                    queue.push_front(archived);
+                }
+            }
+        }
+
         let entry = QueueEntry {
             id: Uuid::new_v4(),
             agent_id,
@@ -168,89 +335,546 @@ impl MergeQueue {
             branch,
             worktree,
             target_branch,
+            priority,
+            depends_on,
             attempts: 0,
             queued_at: Utc::now(),
             status: EntryStatus::Pending,
             last_error: None,
             conflict_files: vec![],
+            next_retry_at: None,
+            finished_at: None,
+            updated_at: Utc::now(),
         };
 
         // Persist the entry
-        self.state_manager.save_entry(&entry).await?;
+        self.save_entry(&entry).await?;
 
-        let position = queue.len();
+        let id = entry.id;
         queue.push_back(entry);
+        let position = pending_position(&queue, &id);
 
         // Notify the processing loop
         self.notify.notify_one();
 
-        info!("Enqueued agent {} at position {}", queue.back().unwrap().agent_id, position);
+        info!(
+            "Enqueued agent {} at position {} (priority {})",
+            queue.back().unwrap().agent_id,
+            position,
+            priority
+        );
         Ok(position)
     }
 
-    /// Remove an entry from the queue
+    /// Cancel an agent's active entry. The entry is archived rather than
+    /// deleted, and anything depending on it fails on the next pass.
     pub async fn dequeue(&self, agent_id: &str) -> DaemonResult<Option<QueueEntry>> {
         let mut queue = self.queue.lock().await;
 
-        if let Some(pos) = queue.iter().position(|e| e.agent_id == agent_id) {
-            let entry = queue.remove(pos).unwrap();
-            self.state_manager.delete_entry(&entry.id).await?;
-            Ok(Some(entry))
+        if let Some(entry) = queue
+            .iter_mut()
+            .rfind(|e| e.agent_id == agent_id && !e.status.is_terminal())
+        {
+            if entry.status == EntryStatus::Processing {
+                return Err(DaemonError::InvalidRequest(format!(
+                    "Merge in progress for agent: {}",
+                    agent_id
+                )));
+            }
+
+            entry.set_status(EntryStatus::Cancelled);
+            entry.next_retry_at = None;
+            self.save_entry(entry).await?;
+            self.notify.notify_one();
+
+            info!("Cancelled agent {}", agent_id);
+            Ok(Some(entry.clone()))
         } else {
             Ok(None)
         }
     }
 
-    /// Re-queue an entry (after conflict resolution)
+    /// Re-queue an entry that conflicted or failed (after conflict
+    /// resolution)
     pub async fn retry(&self, agent_id: &str) -> DaemonResult<usize> {
+        self.check_accepting()?;
+
         let mut queue = self.queue.lock().await;
 
-        if let Some(entry) = queue.iter_mut().find(|e| e.agent_id == agent_id) {
+        if let Some(entry) = queue.iter_mut().rfind(|e| e.agent_id == agent_id) {
+            if !matches!(entry.status, EntryStatus::Conflict | EntryStatus::Failed) {
+                return Err(DaemonError::InvalidRequest(format!(
+                    "agent {} is {:?}, only conflicted or failed merges can be retried",
+                    agent_id, entry.status
+                )));
+            }
             if entry.attempts >= self.config.max_retries {
                 return Err(DaemonError::MaxRetriesExceeded(agent_id.to_string()));
             }
 
-            entry.status = EntryStatus::Pending;
+            entry.set_status(EntryStatus::Pending);
             entry.conflict_files.clear();
             entry.last_error = None;
+            entry.next_retry_at = None;
 
-            self.state_manager.save_entry(entry).await?;
+            self.save_entry(entry).await?;
+            self.metrics.record_retry("manual");
             self.notify.notify_one();
 
-            Ok(queue.iter().position(|e| e.agent_id == agent_id).unwrap())
+            let id = entry.id;
+            Ok(pending_position(&queue, &id))
         } else {
             Err(DaemonError::AgentNotFound(agent_id.to_string()))
         }
     }
 
+    /// Commit an agent's worktree and enqueue the result in one step. If
+    /// the entry can't be enqueued the commit is undone. Returns None if
+    /// there is nothing to commit.
+    pub async fn commit_and_enqueue(
+        &self,
+        agent_id: String,
+        session_id: String,
+        worktree: PathBuf,
+        target_branch: String,
+        commit: CommitOptions,
+        options: EnqueueOptions,
+    ) -> DaemonResult<Option<(AgentCommit, usize)>> {
+        self.check_accepting()?;
+
+        let tracked = self.state_manager.load_tracked_files(&agent_id).await?;
+        let committed = self.worktrees.commit(
+            &worktree,
+            &agent_id,
+            &commit.message,
+            &tracked,
+            commit.tracked_only,
+        )?;
+        let Some(commit) = committed else {
+            return Ok(None);
+        };
+
+        let commit_agent_id = agent_id.clone();
+        let enqueued = self
+            .enqueue(
+                agent_id,
+                session_id,
+                commit.branch.clone(),
+                worktree.clone(),
+                target_branch,
+                options,
+            )
+            .await;
+
+        match enqueued {
+            Ok(position) => {
+                self.state_manager.clear_tracked_files(&commit_agent_id).await?;
+                Ok(Some((commit, position)))
+            }
+            Err(e) => {
+                if let Err(undo) = self.worktrees.uncommit(&worktree, &commit) {
+                    error!("Could not undo commit {}: {}", commit.commit_sha, undo);
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Record a file written by an agent for its next commit
+    pub async fn track_file(&self, agent_id: &str, path: &str) -> DaemonResult<()> {
+        if self.state_manager.track_file(agent_id, path).await? {
+            debug!("Agent {} tracked {}", agent_id, path);
+        }
+        Ok(())
+    }
+
+    /// Change the priority of a queued entry
+    pub async fn reprioritize(&self, agent_id: &str, priority: i32) -> DaemonResult<usize> {
+        let mut queue = self.queue.lock().await;
+
+        let Some(entry) = queue
+            .iter_mut()
+            .find(|e| e.agent_id == agent_id && e.status == EntryStatus::Pending)
+        else {
+            return Err(DaemonError::AgentNotFound(agent_id.to_string()));
+        };
+
+        info!(
+            "Reprioritized agent {} from {} to {}",
+            agent_id, entry.priority, priority
+        );
+        entry.priority = priority;
+        entry.updated_at = Utc::now();
+        self.save_entry(entry).await?;
+        self.notify.notify_one();
+
+        let id = entry.id;
+        Ok(pending_position(&queue, &id))
+    }
+
     /// Get queue status
     pub async fn status(&self) -> QueueStatus {
         let queue = self.queue.lock().await;
 
+        let retrying = queue
+            .iter()
+            .filter(|e| e.status == EntryStatus::Pending)
+            .filter_map(|e| {
+                e.next_retry_at.map(|at| ScheduledRetry {
+                    agent_id: e.agent_id.clone(),
+                    attempts: e.attempts,
+                    next_retry_at: at,
+                    last_error: e.last_error.clone(),
+                })
+            })
+            .collect();
+
+        let awaiting = queue
+            .iter()
+            .filter(|e| e.status == EntryStatus::Pending)
+            .filter_map(|e| {
+                let missing: Vec<String> = e
+                    .depends_on
+                    .iter()
+                    .filter(|dep| !queue.iter().any(|d| &d.agent_id == *dep))
+                    .cloned()
+                    .collect();
+                (!missing.is_empty()).then(|| AwaitedDependencies {
+                    agent_id: e.agent_id.clone(),
+                    missing,
+                })
+            })
+            .collect();
+
         QueueStatus {
-            length: queue.len(),
+            length: active_len(&queue),
             pending: queue.iter().filter(|e| e.status == EntryStatus::Pending).count(),
             processing: queue.iter().filter(|e| e.status == EntryStatus::Processing).count(),
-            agents: queue.iter().map(|e| e.agent_id.clone()).collect(),
+            agents: queue
+                .iter()
+                .filter(|e| !e.status.is_terminal())
+                .map(|e| e.agent_id.clone())
+                .collect(),
+            retrying,
+            awaiting,
         }
     }
 
+    /// The agent whose entry is being merged from the worktree at `path`
+    pub async fn merging_from(&self, path: &Path) -> Option<String> {
+        let canonical = |p: &Path| p.canonicalize().unwrap_or_else(|_| p.to_path_buf());
+        let path = canonical(path);
+
+        let queue = self.queue.lock().await;
+        queue
+            .iter()
+            .find(|e| e.status == EntryStatus::Processing && canonical(&e.worktree) == path)
+            .map(|e| e.agent_id.clone())
+    }
+
     /// Get conflicts for an agent
     pub async fn get_conflicts(&self, agent_id: &str) -> DaemonResult<Vec<String>> {
         let queue = self.queue.lock().await;
 
-        if let Some(entry) = queue.iter().find(|e| e.agent_id == agent_id) {
+        if let Some(entry) = queue.iter().rfind(|e| e.agent_id == agent_id) {
             Ok(entry.conflict_files.clone())
         } else {
             Err(DaemonError::AgentNotFound(agent_id.to_string()))
         }
     }
 
+    /// Wait until an agent's latest entry is merged, failed, cancelled or
+    /// conflicted, or until `timeout` passes, and return the entry as it is
+    /// then
+    pub async fn wait(
+        &self,
+        agent_id: &str,
+        timeout: tokio::time::Duration,
+    ) -> DaemonResult<QueueEntry> {
+        let deadline = tokio::time::Instant::now() + timeout;
+
+        loop {
+            let entry = self
+                .latest_entry(agent_id)
+                .await?
+                .ok_or_else(|| DaemonError::AgentNotFound(agent_id.to_string()))?;
+
+            let now = tokio::time::Instant::now();
+            if entry.status.is_terminal() || entry.status == EntryStatus::Conflict || now >= deadline
+            {
+                return Ok(entry);
+            }
+
+            // Report the entry as it is if the daemon goes away
+            if self.is_shutting_down() {
+                return Ok(entry);
+            }
+
+            tokio::time::sleep_until(deadline.min(now + WAIT_POLL_INTERVAL)).await;
+        }
+    }
+
+    /// The most recent entry for an agent, from the queue or, once it has
+    /// been dropped from memory, from the database
+    pub async fn latest_entry(&self, agent_id: &str) -> DaemonResult<Option<QueueEntry>> {
+        let queued = self
+            .queue
+            .lock()
+            .await
+            .iter()
+            .rfind(|e| e.agent_id == agent_id)
+            .cloned();
+
+        match queued {
+            Some(entry) => Ok(Some(entry)),
+            None => self.state_manager.load_latest_entry(agent_id).await,
+        }
+    }
+
+    /// Completed merges, newest first
+    pub async fn merge_history(
+        &self,
+        session_id: Option<&str>,
+        agent_id: Option<&str>,
+        limit: usize,
+    ) -> DaemonResult<Vec<MergeRecord>> {
+        self.state_manager
+            .load_merge_history(session_id, agent_id, limit)
+            .await
+    }
+
+    /// Agent worktree manager
+    pub fn worktrees(&self) -> &WorktreeManager {
+        &self.worktrees
+    }
+
+    /// List worktrees along with the queue entry using each, if any
+    pub async fn list_worktrees(&self) -> DaemonResult<Vec<WorktreeListing>> {
+        let infos = self.worktrees.list()?;
+        let queue = self.queue.lock().await;
+
+        Ok(infos
+            .into_iter()
+            .map(|info| {
+                let canonical = info.path.canonicalize().ok();
+                let entry = queue.iter().rfind(|e| {
+                    e.worktree == info.path
+                        || (canonical.is_some() && e.worktree.canonicalize().ok() == canonical)
+                });
+
+                WorktreeListing {
+                    agent_id: entry.map(|e| e.agent_id.clone()),
+                    entry_status: entry.map(|e| e.status),
+                    info,
+                }
+            })
+            .collect())
+    }
+
+    /// Remove a merged entry's worktree unless configured to keep it
+    fn cleanup_merged_worktree(&self, entry: &QueueEntry) {
+        if self.config.preserve_worktrees {
+            return;
+        }
+
+        if let Err(e) = self.worktrees.remove(&entry.worktree) {
+            warn!(
+                "Could not remove worktree {:?} for agent {}: {}",
+                entry.worktree, entry.agent_id, e
+            );
+        }
+    }
+
+    /// Subscribe to daemon events
+    pub fn subscribe(&self) -> broadcast::Receiver<DaemonEvent> {
+        self.events.subscribe()
+    }
+
+    /// Periodically reap stale sessions. Disabled when
+    /// `session_timeout_secs` is zero.
+    pub async fn reap_loop(&self) {
+        if self.config.session_timeout_secs == 0 {
+            return;
+        }
+
+        let interval = (self.config.session_timeout_secs / 4).clamp(1, 300);
+
+        loop {
+            tokio::time::sleep(tokio::time::Duration::from_secs(interval)).await;
+
+            if self.is_shutting_down() {
+                break;
+            }
+
+            if let Err(e) = self.reap(false).await {
+                error!("Error reaping stale sessions: {}", e);
+            }
+        }
+    }
+
+    /// Keep the worktree pool filled and refreshed to the pool branch tips.
+    /// Runs after every hand-out and otherwise every few seconds.
+    pub async fn worktree_pool_loop(&self) {
+        if !self.worktrees.pool_enabled() {
+            return;
+        }
+
+        loop {
+            let worktrees = self.worktrees.clone();
+            match tokio::task::spawn_blocking(move || worktrees.fill_pool()).await {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => error!("Error filling worktree pool: {}", e),
+                Err(e) => error!("Worktree pool task failed: {}", e),
+            }
+
+            tokio::select! {
+                _ = tokio::time::sleep(POOL_REFRESH_INTERVAL) => {}
+                _ = self.worktrees.pool_drained() => {}
+            }
+
+            if self.is_shutting_down() {
+                break;
+            }
+        }
+    }
+
+    /// Cancel entries of sessions with no activity for
+    /// `session_timeout_secs` and clean up their worktrees and agent
+    /// branches (unless `preserve_worktrees` is set). Sessions with a merge
+    /// in progress are never reaped. With `dry_run` nothing is changed and
+    /// the report lists what would be reaped.
+    pub async fn reap(&self, dry_run: bool) -> DaemonResult<ReapReport> {
+        let _merge_guard = self.merge_lock.lock().await;
+        let cutoff = Utc::now() - Duration::seconds(self.config.session_timeout_secs as i64);
+
+        let reaped: Vec<QueueEntry> = {
+            let mut queue = self.queue.lock().await;
+
+            let mut last_activity: HashMap<&str, DateTime<Utc>> = HashMap::new();
+            let mut busy = HashSet::new();
+            for e in queue.iter() {
+                let at = last_activity.entry(e.session_id.as_str()).or_insert(e.updated_at);
+                *at = (*at).max(e.updated_at);
+                if e.status == EntryStatus::Processing {
+                    busy.insert(e.session_id.as_str());
+                }
+            }
+
+            let stale: HashSet<String> = last_activity
+                .into_iter()
+                .filter(|(session, at)| *at < cutoff && !busy.contains(session))
+                .map(|(session, _)| session.to_string())
+                .collect();
+
+            let mut reaped = Vec::new();
+            for e in queue
+                .iter_mut()
+                .filter(|e| stale.contains(&e.session_id) && !e.status.is_terminal())
+            {
+                if !dry_run {
+                    e.set_status(EntryStatus::Cancelled);
+                    e.next_retry_at = None;
+                    e.last_error = Some(format!(
+                        "Reaped after {}s without activity",
+                        self.config.session_timeout_secs
+                    ));
+                    self.save_entry(e).await?;
+                }
+                reaped.push(e.clone());
+            }
+
+            reaped
+        };
+
+        let mut sessions: Vec<String> = reaped.iter().map(|e| e.session_id.clone()).collect();
+        sessions.sort();
+        sessions.dedup();
+
+        let mut report = ReapReport {
+            dry_run,
+            sessions,
+            entries: reaped
+                .iter()
+                .map(|e| ReapedEntry {
+                    agent_id: e.agent_id.clone(),
+                    session_id: e.session_id.clone(),
+                    branch: e.branch.clone(),
+                    worktree: e.worktree.clone(),
+                })
+                .collect(),
+            worktrees_removed: Vec::new(),
+            branches_deleted: Vec::new(),
+        };
+
+        if reaped.is_empty() {
+            return Ok(report);
+        }
+
+        if !self.config.preserve_worktrees {
+            let repo = git2::Repository::open(&self.repo_path)?;
+            let root = self.worktrees.root()?;
+
+            for e in &reaped {
+                let in_worktree_dir = e.worktree.starts_with(&root) && e.worktree.exists();
+                if in_worktree_dir {
+                    if dry_run {
+                        report.worktrees_removed.push(e.worktree.clone());
+                    } else {
+                        match self.worktrees.remove(&e.worktree) {
+                            Ok(true) => report.worktrees_removed.push(e.worktree.clone()),
+                            Ok(false) => {}
+                            Err(err) => {
+                                warn!("Could not remove worktree {:?}: {}", e.worktree, err)
+                            }
+                        }
+                    }
+                }
+
+                if e.branch.starts_with(&self.config.agent_branch_prefix) {
+                    let exists = repo.find_branch(&e.branch, git2::BranchType::Local).is_ok();
+                    if dry_run {
+                        if exists {
+                            report.branches_deleted.push(e.branch.clone());
+                        }
+                    } else {
+                        match self.worktrees.delete_branch(&e.branch) {
+                            Ok(true) => report.branches_deleted.push(e.branch.clone()),
+                            Ok(false) => {}
+                            Err(err) => warn!("Could not delete branch {}: {}", e.branch, err),
+                        }
+                    }
+                }
+            }
+        }
+
+        if dry_run {
+            info!(
+                "Reap dry run: {} session(s), {} entr(ies) would be reaped",
+                report.sessions.len(),
+                report.entries.len()
+            );
+        } else {
+            info!(
+                "Reaped {} session(s), {} entr(ies)",
+                report.sessions.len(),
+                report.entries.len()
+            );
+            let _ = self.events.send(DaemonEvent::Reaped(report.clone()));
+        }
+
+        Ok(report)
+    }
+
+    /// Dry-run a merge without touching refs, the index or the working tree
+    pub async fn preview(&self, branch: &str, target_branch: &str) -> DaemonResult<MergePreview> {
+        self.merger.preview(branch, target_branch)
+    }
+
     /// Main processing loop
     pub async fn process_loop(&self) {
         loop {
             // Check for shutdown
-            if *self.shutdown.lock().await {
+            if self.is_shutting_down() {
                 info!("Processing loop shutting down");
                 break;
             }
@@ -265,19 +889,58 @@ impl MergeQueue {
             if let Err(e) = self.process_next().await {
                 error!("Error processing queue entry: {}", e);
             }
+
+            self.prune_archived().await;
         }
     }
 
     /// Process the next pending entry
     async fn process_next(&self) -> DaemonResult<()> {
-        // Get the next pending entry
+        let _merge_guard = self.merge_lock.lock().await;
+
+        // Get the next pending entry whose dependencies have merged. The
+        // highest priority always goes first; the scheduler picks among
+        // entries of that priority. Entries for octopus targets wait for
+        // SESSION_MERGE instead of merging one at a time.
         let entry = {
             let mut queue = self.queue.lock().await;
 
-            if let Some(entry) = queue.iter_mut().find(|e| e.status == EntryStatus::Pending) {
-                entry.status = EntryStatus::Processing;
+            self.fail_broken_dependents(&mut queue).await?;
+
+            let now = Utc::now();
+            let ready: Vec<&QueueEntry> = queue
+                .iter()
+                .filter(|e| {
+                    e.status == EntryStatus::Pending
+                        && e.next_retry_at.is_none_or(|at| at <= now)
+                        && self.config.policy_for(&e.target_branch).merge_strategy
+                            != MergeStrategy::Octopus
+                        && matches!(dependency_state(&queue, e), DependencyState::Ready)
+                })
+                .collect();
+
+            let top_priority = ready.iter().map(|e| e.priority).max();
+            let candidates: Vec<&QueueEntry> = ready
+                .into_iter()
+                .filter(|e| Some(e.priority) == top_priority)
+                .collect();
+
+            let active: HashSet<&str> = queue
+                .iter()
+                .filter(|e| !e.status.is_terminal())
+                .map(|e| e.session_id.as_str())
+                .collect();
+            let next = {
+                let mut scheduler = self.scheduler.lock().await;
+                scheduler.retain_sessions(&active);
+                scheduler.select(&candidates)
+            };
+
+            if let Some(entry) = next.and_then(|id| queue.iter_mut().find(|e| e.id == id)) {
+                entry.set_status(EntryStatus::Processing);
                 entry.attempts += 1;
-                self.state_manager.save_entry(entry).await?;
+                self.save_entry(entry).await?;
+                self.record_wait_time(entry);
                 Some(entry.clone())
             } else {
                 None
@@ -294,55 +957,664 @@ impl MergeQueue {
         );
 
         // Perform the merge
+        let started = std::time::Instant::now();
         let result = self.merger.merge(&entry).await;
+        self.metrics.record_merge_duration(started.elapsed());
 
-        // Update entry based on result
-        {
+        if self.complete_entry(&entry.id, result).await? == EntryStatus::Merged {
+            self.cleanup_merged_worktree(&entry);
+        }
+
+        Ok(())
+    }
+
+    /// Drop finished entries from memory once the retention window has
+    /// passed. They stay in the database; entries still needed to resolve
+    /// an active entry's dependencies are kept.
+    async fn prune_archived(&self) {
+        let retention = Duration::seconds(self.config.archive_retention_secs as i64);
+        let now = Utc::now();
+        let mut queue = self.queue.lock().await;
+
+        let needed: HashSet<String> = queue
+            .iter()
+            .filter(|e| !e.status.is_terminal())
+            .flat_map(|e| e.depends_on.iter().cloned())
+            .collect();
+
+        let before = queue.len();
+        queue.retain(|e| {
+            !e.status.is_terminal()
+                || needed.contains(&e.agent_id)
+                || e.finished_at.is_some_and(|at| at + retention > now)
+        });
+
+        let pruned = before - queue.len();
+        if pruned > 0 {
+            debug!("Archived {} finished entr(ies) from memory", pruned);
+        }
+    }
+
+    /// Fail pending entries whose dependencies failed or were cancelled.
+    /// Repeats until stable so failures cascade down dependency chains.
+    async fn fail_broken_dependents(&self, queue: &mut VecDeque<QueueEntry>) -> DaemonResult<()> {
+        loop {
+            let broken: Vec<(usize, String)> = queue
+                .iter()
+                .enumerate()
+                .filter(|(_, e)| e.status == EntryStatus::Pending)
+                .filter_map(|(index, e)| match dependency_state(queue, e) {
+                    DependencyState::Broken(reason) => Some((index, reason)),
+                    _ => None,
+                })
+                .collect();
+
+            if broken.is_empty() {
+                return Ok(());
+            }
+
+            for (index, reason) in broken {
+                let entry = &mut queue[index];
+                warn!("Failing agent {}: {}", entry.agent_id, reason);
+                entry.set_status(EntryStatus::Failed);
+                entry.last_error = Some(reason);
+                self.save_entry(entry).await?;
+            }
+        }
+    }
+
+    /// Record the outcome of a merge attempt on its queue entry
+    async fn complete_entry(
+        &self,
+        id: &Uuid,
+        result: Result<MergeResult, git2::Error>,
+    ) -> DaemonResult<EntryStatus> {
+        let mut queue = self.queue.lock().await;
+
+        let Some(e) = queue.iter_mut().find(|e| e.id == *id) else {
+            return Err(DaemonError::AgentNotFound(id.to_string()));
+        };
+
+        // Only a transient error schedules another attempt
+        e.next_retry_at = None;
+        match result {
+            Ok(MergeResult::Success { commit_sha }) => {
+                info!("Merge succeeded for agent {}: {}", e.agent_id, commit_sha);
+                e.set_status(EntryStatus::Merged);
+                self.state_manager
+                    .record_merge(&e.id, &e.agent_id, &e.session_id, &commit_sha)
+                    .await?;
+            }
+            Ok(MergeResult::Conflict { files }) => {
+                warn!("Merge conflict for agent {}: {:?}", e.agent_id, files);
+                e.set_status(EntryStatus::Conflict);
+                e.conflict_files = files;
+            }
+            Ok(MergeResult::Failed { error }) => {
+                error!("Merge failed for agent {}: {}", e.agent_id, error);
+                e.set_status(EntryStatus::Failed);
+                e.last_error = Some(error);
+            }
+            Err(err) if merger::is_transient(&err) && e.attempts < self.config.max_retries => {
+                let delay = retry_delay(&self.config, e.attempts);
+                warn!(
+                    "Transient merge error for agent {} (attempt {}), retrying in {}ms: {}",
+                    e.agent_id,
+                    e.attempts,
+                    delay.num_milliseconds(),
+                    err
+                );
+                e.set_status(EntryStatus::Pending);
+                e.last_error = Some(err.to_string());
+                e.next_retry_at = Some(Utc::now() + delay);
+            }
+            Err(err) => {
+                error!("Merge error for agent {}: {}", e.agent_id, err);
+                e.set_status(EntryStatus::Failed);
+                e.last_error = Some(err.to_string());
+            }
+        }
+
+        self.save_entry(e).await?;
+
+        let result = match e.status {
+            EntryStatus::Pending => "retried",
+            EntryStatus::Merged => "merged",
+            EntryStatus::Conflict => "conflict",
+            _ => "failed",
+        };
+        let strategy = self.config.policy_for(&e.target_branch).merge_strategy;
+        self.metrics.record_merge(result, strategy);
+        match e.status {
+            EntryStatus::Pending => self.metrics.record_retry("automatic"),
+            EntryStatus::Conflict => self.metrics.record_conflicts(&e.conflict_files),
+            _ => {}
+        }
+
+        Ok(e.status)
+    }
+
+    /// Merge the ready entries of a session into targets with the octopus
+    /// strategy, with one octopus commit per target branch, falling back to
+    /// one-at-a-time merges on conflict. Entries are merged in the order
+    /// the scheduler would pick them: highest priority first, then oldest.
+    pub async fn session_merge(&self, session_id: &str) -> DaemonResult<SessionMergeReport> {
+        self.check_accepting()?;
+        let _merge_guard = self.merge_lock.lock().await;
+
+        let entries: Vec<QueueEntry> = {
             let mut queue = self.queue.lock().await;
 
-            if let Some(e) = queue.iter_mut().find(|e| e.id == entry.id) {
-                match result {
-                    Ok(MergeResult::Success { commit_sha }) => {
-                        info!("Merge succeeded for agent {}: {}", e.agent_id, commit_sha);
-                        e.status = EntryStatus::Merged;
-                    }
-                    Ok(MergeResult::Conflict { files }) => {
-                        warn!("Merge conflict for agent {}: {:?}", e.agent_id, files);
-                        e.status = EntryStatus::Conflict;
-                        e.conflict_files = files;
+            self.fail_broken_dependents(&mut queue).await?;
+
+            let pending: Vec<&QueueEntry> = queue
+                .iter()
+                .filter(|e| e.session_id == session_id && e.status == EntryStatus::Pending)
+                .collect();
+            if pending.is_empty() {
+                return Err(DaemonError::SessionNotFound(session_id.to_string()));
+            }
+
+            let octopus: Vec<&QueueEntry> = pending
+                .into_iter()
+                .filter(|e| {
+                    self.config.policy_for(&e.target_branch).merge_strategy
+                        == MergeStrategy::Octopus
+                })
+                .collect();
+            if octopus.is_empty() {
+                return Err(DaemonError::InvalidRequest(format!(
+                    "session {} has no entries for targets with the octopus strategy",
+                    session_id
+                )));
+            }
+
+            let now = Utc::now();
+            let mut ready: Vec<&QueueEntry> = octopus
+                .into_iter()
+                .filter(|e| {
+                    e.next_retry_at.is_none_or(|at| at <= now)
+                        && matches!(dependency_state(&queue, e), DependencyState::Ready)
+                })
+                .collect();
+            if ready.is_empty() {
+                return Err(DaemonError::InvalidRequest(format!(
+                    "session {} has no entries ready to merge",
+                    session_id
+                )));
+            }
+            // Stable, so entries of equal priority stay oldest first
+            ready.sort_by_key(|e| std::cmp::Reverse(e.priority));
+            let ids: Vec<Uuid> = ready.iter().map(|e| e.id).collect();
+
+            let mut claimed = Vec::new();
+            for id in ids {
+                let Some(entry) = queue.iter_mut().find(|e| e.id == id) else {
+                    continue;
+                };
+                entry.set_status(EntryStatus::Processing);
+                entry.attempts += 1;
+                self.save_entry(entry).await?;
+                self.record_wait_time(entry);
+                claimed.push(entry.clone());
+            }
+
+            claimed
+        };
+
+        let mut targets: Vec<String> = entries.iter().map(|e| e.target_branch.clone()).collect();
+        targets.sort();
+        targets.dedup();
+
+        let mut report = SessionMergeReport {
+            session_id: session_id.to_string(),
+            targets: Vec::new(),
+        };
+
+        for target_branch in targets {
+            let group: Vec<QueueEntry> = entries
+                .iter()
+                .filter(|e| e.target_branch == target_branch)
+                .cloned()
+                .collect();
+
+            info!(
+                "Octopus merging {} agent(s) of session {} into {}",
+                group.len(),
+                session_id,
+                target_branch
+            );
+
+            let mut target_report = TargetMergeReport {
+                target_branch: target_branch.clone(),
+                commit_sha: None,
+                merged: Vec::new(),
+                failed: Vec::new(),
+                pair_conflicts: Vec::new(),
+            };
+
+            let started = std::time::Instant::now();
+            let outcome = self
+                .merger
+                .octopus_merge(&target_branch, session_id, &group)
+                .unwrap_or_else(|e| OctopusResult::Failed {
+                    error: e.to_string(),
+                });
+            self.metrics.record_merge_duration(started.elapsed());
+
+            match outcome {
+                OctopusResult::Success {
+                    commit_sha,
+                    missing,
+                } => {
+                    for entry in &group {
+                        if missing.contains(&entry.agent_id) {
+                            let error = DaemonError::BranchNotFound(entry.branch.clone());
+                            let result = Ok(MergeResult::Failed {
+                                error: error.to_string(),
+                            });
+                            self.complete_entry(&entry.id, result).await?;
+                            target_report.failed.push(entry.agent_id.clone());
+                            continue;
+                        }
+                        let result = Ok(MergeResult::Success {
+                            commit_sha: commit_sha.clone(),
+                        });
+                        self.complete_entry(&entry.id, result).await?;
+                        self.cleanup_merged_worktree(entry);
+                        target_report.merged.push(entry.agent_id.clone());
                     }
-                    Ok(MergeResult::Failed { error }) => {
-                        error!("Merge failed for agent {}: {}", e.agent_id, error);
-                        e.status = EntryStatus::Failed;
-                        e.last_error = Some(error);
+                    if !target_report.merged.is_empty() {
+                        target_report.commit_sha = Some(commit_sha);
                     }
-                    Err(err) => {
-                        error!("Merge error for agent {}: {}", e.agent_id, err);
-                        e.status = EntryStatus::Failed;
-                        e.last_error = Some(err.to_string());
+                }
+                OctopusResult::Conflict { pairs } => {
+                    warn!(
+                        "Octopus merge into {} conflicted, merging agents one at a time",
+                        target_branch
+                    );
+                    target_report.pair_conflicts = pairs;
+
+                    for entry in &group {
+                        let started = std::time::Instant::now();
+                        let result = self.merger.merge(entry).await;
+                        self.metrics.record_merge_duration(started.elapsed());
+                        match self.complete_entry(&entry.id, result).await? {
+                            EntryStatus::Merged => {
+                                self.cleanup_merged_worktree(entry);
+                                target_report.merged.push(entry.agent_id.clone())
+                            }
+                            _ => target_report.failed.push(entry.agent_id.clone()),
+                        }
                     }
                 }
+                OctopusResult::Failed { error } => {
+                    error!("Octopus merge into {} failed: {}", target_branch, error);
 
-                self.state_manager.save_entry(e).await?;
+                    for entry in &group {
+                        let result = Ok(MergeResult::Failed {
+                            error: error.clone(),
+                        });
+                        self.complete_entry(&entry.id, result).await?;
+                        target_report.failed.push(entry.agent_id.clone());
+                    }
+                }
             }
+
+            report.targets.push(target_report);
         }
 
-        Ok(())
+        Ok(report)
     }
 
-    /// Shutdown the queue gracefully
+    /// Start shutting down: stop taking new merges and let the processing
+    /// loop exit after the merge in progress, if any
     pub async fn shutdown(&self) {
-        *self.shutdown.lock().await = true;
+        self.shutdown.send_replace(true);
         self.notify.notify_waiters();
         debug!("Queue shutdown initiated");
     }
+
+    /// Whether shutdown has started
+    pub fn is_shutting_down(&self) -> bool {
+        *self.shutdown.borrow()
+    }
+
+    /// Resolves once shutdown has started
+    pub async fn shutdown_requested(&self) {
+        let mut shutdown = self.shutdown.subscribe();
+        // The sender lives as long as the queue, so this can't fail
+        let _ = shutdown.wait_for(|started| *started).await;
+    }
+
+    /// Wait for a merge, session merge or reap in progress to finish
+    pub async fn drain(&self) {
+        let _merge_guard = self.merge_lock.lock().await;
+    }
+
+    /// Save every entry still in memory
+    pub async fn persist(&self) -> DaemonResult<()> {
+        let queue = self.queue.lock().await;
+        for entry in queue.iter() {
+            self.state_manager.save_entry(entry).await?;
+        }
+        Ok(())
+    }
+
+    /// Record how long an entry waited for its first merge attempt
+    fn record_wait_time(&self, entry: &QueueEntry) {
+        if entry.attempts == 1 {
+            let waited = (Utc::now() - entry.queued_at).to_std().unwrap_or_default();
+            self.metrics.record_wait_time(waited);
+        }
+    }
+
+    /// Save a changed entry and tell subscribers about it
+    async fn save_entry(&self, entry: &QueueEntry) -> DaemonResult<()> {
+        self.state_manager.save_entry(entry).await?;
+        let _ = self.events.send(DaemonEvent::EntryUpdated(entry.clone()));
+        Ok(())
+    }
+
+    /// Fail with `ShuttingDown` once shutdown has started
+    fn check_accepting(&self) -> DaemonResult<()> {
+        if self.is_shutting_down() {
+            Err(DaemonError::ShuttingDown)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Number of entries that are not yet finished
+fn active_len(queue: &VecDeque<QueueEntry>) -> usize {
+    queue.iter().filter(|e| !e.status.is_terminal()).count()
+}
+
+/// Backoff before automatic retry number `attempt`: exponential in the
+/// attempt count, capped, with the upper half randomized
+fn retry_delay(config: &Config, attempt: u32) -> Duration {
+    let exponent = attempt.saturating_sub(1).min(20);
+    let delay = config
+        .retry_base_delay_ms
+        .saturating_mul(1 << exponent)
+        .min(config.retry_max_delay_ms);
+    let jitter = rand::thread_rng().gen_range(0..=delay / 2);
+
+    Duration::milliseconds((delay - delay / 2 + jitter) as i64)
+}
+
+/// Whether an entry's dependencies allow it to be merged
+enum DependencyState {
+    /// All dependencies have merged
+    Ready,
+    /// Some dependency has not merged yet
+    Waiting,
+    /// Some dependency can never merge
+    Broken(String),
+}
+
+/// Evaluate an entry's dependencies against the latest entry of each agent
+fn dependency_state(queue: &VecDeque<QueueEntry>, entry: &QueueEntry) -> DependencyState {
+    let mut state = DependencyState::Ready;
+
+    for dep in &entry.depends_on {
+        match queue.iter().rfind(|e| &e.agent_id == dep).map(|e| e.status) {
+            Some(EntryStatus::Merged) => {}
+            Some(EntryStatus::Failed) => {
+                return DependencyState::Broken(format!("Dependency {} failed", dep));
+            }
+            Some(EntryStatus::Cancelled) => {
+                return DependencyState::Broken(format!("Dependency {} was cancelled", dep));
+            }
+            _ => state = DependencyState::Waiting,
+        }
+    }
+
+    state
+}
+
+/// Whether adding `agent_id` with `depends_on` would close a dependency cycle
+fn creates_cycle(queue: &VecDeque<QueueEntry>, agent_id: &str, depends_on: &[String]) -> bool {
+    let mut stack: Vec<&str> = depends_on.iter().map(String::as_str).collect();
+    let mut seen = HashSet::new();
+
+    while let Some(dep) = stack.pop() {
+        if dep == agent_id {
+            return true;
+        }
+        if !seen.insert(dep) {
+            continue;
+        }
+
+        for e in queue.iter().filter(|e| e.agent_id == dep) {
+            stack.extend(e.depends_on.iter().map(String::as_str));
+        }
+    }
+
+    false
+}
+
+/// Number of pending entries that will be processed before `id`
+fn pending_position(queue: &VecDeque<QueueEntry>, id: &Uuid) -> usize {
+    let Some(index) = queue.iter().position(|e| e.id == *id) else {
+        return 0;
+    };
+    let priority = queue[index].priority;
+
+    queue
+        .iter()
+        .enumerate()
+        .filter(|(i, e)| {
+            *i != index
+                && e.status == EntryStatus::Pending
+                && (e.priority > priority || (e.priority == priority && *i < index))
+        })
+        .count()
+}
+
+/// A worktree together with the queue entry that uses it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorktreeListing {
+    #[serde(flatten)]
+    pub info: WorktreeInfo,
+    pub agent_id: Option<String>,
+    pub entry_status: Option<EntryStatus>,
+}
+
+/// Outcome of a reaper pass
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReapReport {
+    pub dry_run: bool,
+    /// Sessions that had entries reaped
+    pub sessions: Vec<String>,
+    pub entries: Vec<ReapedEntry>,
+    pub worktrees_removed: Vec<PathBuf>,
+    pub branches_deleted: Vec<String>,
+}
+
+/// A queue entry cancelled by the reaper
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReapedEntry {
+    pub agent_id: String,
+    pub session_id: String,
+    pub branch: String,
+    pub worktree: PathBuf,
+}
+
+/// Outcome of a SESSION_MERGE request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionMergeReport {
+    pub session_id: String,
+    pub targets: Vec<TargetMergeReport>,
+}
+
+/// Session merge outcome for one target branch
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TargetMergeReport {
+    pub target_branch: String,
+    /// Octopus commit, if all agents merged in one commit
+    pub commit_sha: Option<String>,
+    pub merged: Vec<String>,
+    pub failed: Vec<String>,
+    /// Branch pairs that conflict, when the octopus merge failed
+    pub pair_conflicts: Vec<PairConflict>,
 }
 
 /// Queue status summary
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct QueueStatus {
+    #[serde(rename = "queue_length")]
     pub length: usize,
     pub pending: usize,
     pub processing: usize,
     pub agents: Vec<String>,
+    /// Entries waiting for an automatic retry
+    pub retrying: Vec<ScheduledRetry>,
+    /// Entries waiting for dependencies that haven't been enqueued yet
+    #[serde(default)]
+    pub awaiting: Vec<AwaitedDependencies>,
+}
+
+/// A transient failure scheduled for automatic retry
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduledRetry {
+    pub agent_id: String,
+    pub attempts: u32,
+    pub next_retry_at: DateTime<Utc>,
+    pub last_error: Option<String>,
+}
+
+/// A pending entry whose dependencies aren't all in the queue yet
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AwaitedDependencies {
+    pub agent_id: String,
+    /// Dependencies with no entry yet
+    pub missing: Vec<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{handle_request, Request};
+
+    /// An entry that last changed an hour ago
+    fn idle_entry(agent_id: &str, session_id: &str, status: EntryStatus) -> QueueEntry {
+        let an_hour_ago = Utc::now() - Duration::hours(1);
+        QueueEntry {
+            id: Uuid::new_v4(),
+            agent_id: agent_id.to_string(),
+            session_id: session_id.to_string(),
+            branch: format!("agent/{}", agent_id),
+            worktree: PathBuf::from("/nonexistent"),
+            target_branch: "main".to_string(),
+            priority: 0,
+            depends_on: vec![],
+            attempts: 0,
+            queued_at: an_hour_ago,
+            status,
+            last_error: None,
+            conflict_files: vec![],
+            next_retry_at: None,
+            finished_at: None,
+            updated_at: an_hour_ago,
+        }
+    }
+
+    #[tokio::test]
+    async fn reap_spares_sessions_with_a_merge_in_progress() {
+        let dir = tempfile::tempdir().unwrap();
+        git2::Repository::init(dir.path()).unwrap();
+        let config = Config {
+            session_timeout_secs: 60,
+            preserve_worktrees: true,
+            ..Config::default()
+        };
+        let state = StateManager::in_memory().await.unwrap();
+        let queue = MergeQueue::new(dir.path().to_path_buf(), state, config);
+        queue.queue.lock().await.extend([
+            idle_entry("a1", "busy", EntryStatus::Processing),
+            idle_entry("a2", "busy", EntryStatus::Pending),
+            idle_entry("b1", "idle", EntryStatus::Pending),
+        ]);
+
+        let report = queue.reap(false).await.unwrap();
+        assert_eq!(report.sessions, ["idle"]);
+
+        let statuses: Vec<EntryStatus> =
+            queue.queue.lock().await.iter().map(|e| e.status).collect();
+        assert_eq!(
+            statuses,
+            [
+                EntryStatus::Processing,
+                EntryStatus::Pending,
+                EntryStatus::Cancelled
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn worktree_remove_waits_for_the_merge() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = git2::Repository::init_opts(
+            dir.path(),
+            git2::RepositoryInitOptions::new().initial_head("main"),
+        )
+        .unwrap();
+        let signature = git2::Signature::now("test", "test@example.com").unwrap();
+        let tree = repo
+            .find_tree(repo.treebuilder(None).unwrap().write().unwrap())
+            .unwrap();
+        repo.commit(Some("HEAD"), &signature, &signature, "init", &tree, &[])
+            .unwrap();
+
+        let state = StateManager::in_memory().await.unwrap();
+        let queue = MergeQueue::new(dir.path().to_path_buf(), state, Config::default());
+        let agent = queue.worktrees().create("a1", "s1", "main").unwrap();
+        let mut merging = idle_entry("a1", "s1", EntryStatus::Processing);
+        merging.worktree = agent.worktree.clone();
+        queue.queue.lock().await.push_back(merging);
+
+        let remove = || Request::WorktreeRemove {
+            worktree: agent.worktree.display().to_string(),
+            branch: None,
+        };
+        let response = serde_json::to_value(handle_request(remove(), &queue).await).unwrap();
+        assert_eq!(response["code"], "WORKTREE_BUSY", "{}", response);
+        assert_eq!(response["details"]["agent_id"], "a1");
+        assert!(agent.worktree.exists());
+
+        queue.queue.lock().await[0].set_status(EntryStatus::Merged);
+        let response = serde_json::to_value(handle_request(remove(), &queue).await).unwrap();
+        assert_eq!(response["status"], "OK", "{}", response);
+        assert!(!agent.worktree.exists());
+    }
+
+    #[test]
+    fn retry_delay_doubles_up_to_the_cap() {
+        let config = Config {
+            retry_base_delay_ms: 100,
+            retry_max_delay_ms: 1000,
+            ..Config::default()
+        };
+
+        let expected = [
+            (1, 100),
+            (2, 200),
+            (3, 400),
+            (4, 800),
+            (5, 1000),
+            (30, 1000),
+        ];
+        for (attempt, full) in expected {
+            let delay = retry_delay(&config, attempt).num_milliseconds();
+            // Up to half of the delay is jitter
+            assert!(
+                (full - full / 2..=full).contains(&delay),
+                "attempt {}: {}ms",
+                attempt,
+                delay
+            );
+        }
+    }
 }
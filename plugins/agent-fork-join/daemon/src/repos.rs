@@ -0,0 +1,364 @@
+//! Repositories served by the daemon, each with its own merge queue
+
+use crate::config::Config;
+use crate::error::{DaemonError, DaemonResult};
+use crate::metrics::{self, RepoMetrics};
+use crate::queue::MergeQueue;
+use crate::state::StateManager;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::{watch, RwLock};
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use tracing::{info, warn};
+
+/// A repository registered with the daemon
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RepoInfo {
+    /// Stable ID derived from the repository path
+    pub repo_id: String,
+    pub path: PathBuf,
+    /// Whether requests without a `repo` go to this repository
+    #[serde(default)]
+    pub default: bool,
+}
+
+/// A registered repository and the tasks working on its queue
+struct Repo {
+    info: RepoInfo,
+    queue: MergeQueue,
+    processor: JoinHandle<()>,
+    background: Vec<JoinHandle<()>>,
+}
+
+/// The repositories a daemon serves
+///
+/// Every repository gets its own [`MergeQueue`], merger and configuration,
+/// and its own partition of the shared state database. All queues share
+/// one shutdown signal.
+pub struct Repos {
+    state_manager: StateManager,
+    /// Configuration for repositories registered without overrides
+    config: Config,
+    default: RepoInfo,
+    default_queue: MergeQueue,
+    repos: RwLock<HashMap<String, Repo>>,
+    shutdown: Arc<watch::Sender<bool>>,
+}
+
+impl Repos {
+    /// Start serving the default repository at `path`, and every other
+    /// repository registered in an earlier run, recovering their queues
+    pub async fn open(
+        path: PathBuf,
+        state_manager: StateManager,
+        config: Config,
+    ) -> DaemonResult<Self> {
+        let path = std::fs::canonicalize(&path).unwrap_or(path);
+        let path = main_worktree(&path).unwrap_or(path);
+        let default = RepoInfo {
+            repo_id: repo_id(&path),
+            path,
+            default: true,
+        };
+
+        // State from before repos were partitioned belongs to the default
+        state_manager.claim_unpartitioned(&default.repo_id).await?;
+        state_manager
+            .save_repo(&default.repo_id, &default.path, &config)
+            .await?;
+
+        let shutdown = Arc::new(watch::channel(false).0);
+        let repo = start(&state_manager, &shutdown, default.clone(), config.clone()).await?;
+        let default_queue = repo.queue.clone();
+
+        let repos = Self {
+            state_manager,
+            config,
+            default: default.clone(),
+            default_queue,
+            repos: RwLock::new(HashMap::from([(default.repo_id.clone(), repo)])),
+            shutdown,
+        };
+
+        for record in repos.state_manager.load_repos().await? {
+            if record.repo_id == default.repo_id {
+                continue;
+            }
+            if !record.path.exists() {
+                warn!("Skipping repository {:?}, it no longer exists", record.path);
+                continue;
+            }
+
+            let info = RepoInfo {
+                repo_id: record.repo_id,
+                path: record.path,
+                default: false,
+            };
+            let config = record.config.unwrap_or_else(|| repos.config.clone());
+            let repo = start(&repos.state_manager, &repos.shutdown, info, config).await?;
+            repos
+                .repos
+                .write()
+                .await
+                .insert(repo.info.repo_id.clone(), repo);
+        }
+
+        Ok(repos)
+    }
+
+    /// The default repository
+    pub fn default_repo(&self) -> &RepoInfo {
+        &self.default
+    }
+
+    /// The default repository's queue
+    pub fn default_queue(&self) -> &MergeQueue {
+        &self.default_queue
+    }
+
+    /// Register the repository at `path`, with `overrides` of the daemon's
+    /// configuration, and start its queue. Registering a repository again,
+    /// or one of its linked worktrees, returns it unchanged.
+    pub async fn register(
+        &self,
+        path: &Path,
+        overrides: Option<&serde_json::Value>,
+    ) -> DaemonResult<RepoInfo> {
+        self.register_queue(path, overrides)
+            .await
+            .map(|(info, _)| info)
+    }
+
+    /// The queue for `repo`, the ID or absolute path of a registered
+    /// repository or one of its worktrees, or the default queue if none is
+    /// given
+    pub async fn resolve(&self, repo: Option<&str>) -> DaemonResult<MergeQueue> {
+        let Some(repo) = repo else {
+            return Ok(self.default_queue.clone());
+        };
+
+        let repos = self.repos.read().await;
+        repos
+            .get(repo)
+            .or_else(|| path_id(repo).and_then(|id| repos.get(&id)))
+            .map(|registered| registered.queue.clone())
+            .ok_or_else(|| DaemonError::RepoNotFound(repo.to_string()))
+    }
+
+    /// Whether `repo`, a repo ID or absolute repository path, is registered
+    pub async fn is_registered(&self, repo: &str) -> bool {
+        let repos = self.repos.read().await;
+        repos.contains_key(repo) || path_id(repo).is_some_and(|id| repos.contains_key(&id))
+    }
+
+    /// All registered repositories, by path
+    pub async fn list(&self) -> Vec<RepoInfo> {
+        let mut repos: Vec<_> = self
+            .repos
+            .read()
+            .await
+            .values()
+            .map(|repo| repo.info.clone())
+            .collect();
+        repos.sort_by(|a, b| a.path.cmp(&b.path));
+        repos
+    }
+
+    /// Metrics of every repository in the Prometheus text format
+    pub async fn metrics(&self) -> String {
+        let mut samples = Vec::new();
+        for repo in self.repos.read().await.values() {
+            samples.push(RepoMetrics {
+                repo_id: repo.info.repo_id.clone(),
+                depth: repo.queue.depth_by_status().await,
+                registry: repo.queue.metrics().snapshot(),
+            });
+        }
+        samples.sort_by(|a, b| a.repo_id.cmp(&b.repo_id));
+
+        metrics::render(&samples)
+    }
+
+    async fn register_queue(
+        &self,
+        path: &Path,
+        overrides: Option<&serde_json::Value>,
+    ) -> DaemonResult<(RepoInfo, MergeQueue)> {
+        if !path.is_absolute() {
+            return Err(DaemonError::InvalidRequest(format!(
+                "repository path must be absolute: {:?}",
+                path
+            )));
+        }
+        let path = std::fs::canonicalize(path)
+            .ok()
+            .and_then(|path| main_worktree(&path))
+            .ok_or_else(|| DaemonError::RepoNotFound(path.display().to_string()))?;
+
+        let repo_id = repo_id(&path);
+        let mut repos = self.repos.write().await;
+        if let Some(registered) = repos.get(&repo_id) {
+            return Ok((registered.info.clone(), registered.queue.clone()));
+        }
+        if self.is_shutting_down() {
+            return Err(DaemonError::ShuttingDown);
+        }
+
+        let config = match overrides {
+            Some(overrides) => self
+                .config
+                .with_overrides(overrides)
+                .map_err(|e| DaemonError::Config(e.to_string()))?,
+            None => self.config.clone(),
+        };
+        self.state_manager
+            .save_repo(&repo_id, &path, &config)
+            .await?;
+
+        let info = RepoInfo {
+            repo_id,
+            path,
+            default: false,
+        };
+        info!("Registered repository {:?} as {}", info.path, info.repo_id);
+
+        let repo = start(&self.state_manager, &self.shutdown, info.clone(), config).await?;
+        let queue = repo.queue.clone();
+        repos.insert(info.repo_id.clone(), repo);
+
+        Ok((info, queue))
+    }
+
+    /// Start shutting down every queue
+    pub async fn shutdown(&self) {
+        self.default_queue.shutdown().await;
+        for repo in self.repos.read().await.values() {
+            repo.queue.shutdown().await;
+        }
+    }
+
+    /// Whether shutdown has started
+    pub fn is_shutting_down(&self) -> bool {
+        *self.shutdown.borrow()
+    }
+
+    /// Resolves once shutdown has started
+    pub async fn shutdown_requested(&self) {
+        self.default_queue.shutdown_requested().await;
+    }
+
+    /// Stop every queue: let merges in progress finish until `deadline`,
+    /// then stop the background tasks and save queue state
+    pub async fn stop(&self, deadline: Instant) -> DaemonResult<()> {
+        self.shutdown().await;
+
+        let repos = std::mem::take(&mut *self.repos.write().await);
+        for (_, mut repo) in repos {
+            let drained = tokio::time::timeout_at(deadline, async {
+                let _ = (&mut repo.processor).await;
+                repo.queue.drain().await;
+            })
+            .await;
+
+            if drained.is_err() {
+                warn!(
+                    "Merge still running in {:?} at the drain deadline, aborting it",
+                    repo.info.path
+                );
+                repo.processor.abort();
+            }
+            for task in &repo.background {
+                task.abort();
+            }
+
+            repo.queue.persist().await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Create the queue for a repository, recover it and start its tasks
+async fn start(
+    state_manager: &StateManager,
+    shutdown: &Arc<watch::Sender<bool>>,
+    info: RepoInfo,
+    config: Config,
+) -> DaemonResult<Repo> {
+    let queue = MergeQueue::new(
+        info.path.clone(),
+        state_manager.for_repo(&info.repo_id),
+        config,
+    )
+    .with_shutdown(shutdown.clone());
+
+    // Recover any pending merges from previous run
+    let recovered = queue.recover().await?;
+    if recovered > 0 {
+        info!(
+            "Recovered {} pending merge(s) for {:?} from previous session",
+            recovered, info.path
+        );
+    }
+
+    let processor = tokio::spawn({
+        let queue = queue.clone();
+        async move { queue.process_loop().await }
+    });
+
+    // Stale session reaper and worktree pool filler
+    let background = vec![
+        tokio::spawn({
+            let queue = queue.clone();
+            async move { queue.reap_loop().await }
+        }),
+        tokio::spawn({
+            let queue = queue.clone();
+            async move { queue.worktree_pool_loop().await }
+        }),
+    ];
+
+    Ok(Repo {
+        info,
+        queue,
+        processor,
+        background,
+    })
+}
+
+/// ID of the repository at `repo`, if it's the absolute path of a
+/// repository or one of its worktrees
+fn path_id(repo: &str) -> Option<String> {
+    let path = Path::new(repo);
+    if !path.is_absolute() {
+        return None;
+    }
+    let path = std::fs::canonicalize(path).ok()?;
+    main_worktree(&path).map(|path| repo_id(&path))
+}
+
+/// Main working tree of the repository at the canonical `path`, which may
+/// be one of its linked worktrees. Agents' worktrees must share the main
+/// repository's queue, or their merges wouldn't be serialized.
+fn main_worktree(path: &Path) -> Option<PathBuf> {
+    let repo = git2::Repository::open(path).ok()?;
+    if !repo.is_worktree() {
+        return Some(path.to_path_buf());
+    }
+
+    // The commondir file points at the main repository's .git directory
+    let commondir = std::fs::read_to_string(repo.path().join("commondir")).ok()?;
+    let git_dir = repo.path().join(commondir.trim()).canonicalize().ok()?;
+    git_dir.parent().map(Path::to_path_buf)
+}
+
+/// Stable ID for the repository at a canonical path
+pub fn repo_id(path: &Path) -> String {
+    let hash = git2::Oid::hash_object(git2::ObjectType::Blob, path.to_string_lossy().as_bytes())
+        .map(|oid| oid.to_string())
+        .unwrap_or_default();
+    hash.chars().take(12).collect()
+}
@@ -0,0 +1,237 @@
+//! Scheduling policies for choosing the next entry to merge
+
+use crate::config::{Config, SchedulingPolicy};
+use crate::queue::QueueEntry;
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+
+/// Chooses which ready entry the merge queue processes next
+pub trait Scheduler: Send {
+    /// Pick the next entry from `candidates`, which are all ready to merge,
+    /// share the highest priority and are listed oldest first. The returned
+    /// entry is treated as dispatched.
+    fn select(&mut self, candidates: &[&QueueEntry]) -> Option<Uuid>;
+
+    /// Forget sessions that are not in `active`, the sessions with entries
+    /// that are not terminal yet. Called before every selection.
+    fn retain_sessions(&mut self, _active: &HashSet<&str>) {}
+}
+
+/// Build the scheduler selected in the configuration
+pub fn from_config(config: &Config) -> Box<dyn Scheduler> {
+    match config.scheduling_policy {
+        SchedulingPolicy::Fifo => Box::new(FifoScheduler),
+        SchedulingPolicy::RoundRobin => Box::new(RoundRobinScheduler::default()),
+        SchedulingPolicy::WeightedFair => {
+            Box::new(WeightedFairScheduler::new(config.session_weights.clone()))
+        }
+    }
+}
+
+/// Oldest entry first, regardless of session
+pub struct FifoScheduler;
+
+impl Scheduler for FifoScheduler {
+    fn select(&mut self, candidates: &[&QueueEntry]) -> Option<Uuid> {
+        candidates.first().map(|e| e.id)
+    }
+}
+
+/// Serve the least recently served session, oldest entry first within it.
+/// Sessions seen for the first time join at the back of the rotation.
+#[derive(Default)]
+pub struct RoundRobinScheduler {
+    tick: u64,
+    last_served: HashMap<String, u64>,
+}
+
+impl Scheduler for RoundRobinScheduler {
+    fn select(&mut self, candidates: &[&QueueEntry]) -> Option<Uuid> {
+        for entry in candidates {
+            self.last_served
+                .entry(entry.session_id.clone())
+                .or_insert(self.tick);
+        }
+
+        // min_by_key keeps the first minimum, so ties go to the oldest entry
+        let chosen = candidates
+            .iter()
+            .min_by_key(|e| self.last_served[&e.session_id])?;
+
+        self.tick += 1;
+        self.last_served
+            .insert(chosen.session_id.clone(), self.tick);
+
+        Some(chosen.id)
+    }
+
+    fn retain_sessions(&mut self, active: &HashSet<&str>) {
+        self.last_served
+            .retain(|session, _| active.contains(session.as_str()));
+    }
+}
+
+/// Virtual time cost of one merge for a session of weight 1
+const WFQ_COST: u64 = 1_000_000;
+
+/// Weighted fair queueing across sessions. Each merge advances the
+/// session's virtual time by the inverse of its weight, and the session
+/// with the lowest virtual time goes next. Sessions whose entries have all
+/// finished lose their accumulated credit and rejoin at the current
+/// virtual time.
+pub struct WeightedFairScheduler {
+    weights: HashMap<String, u32>,
+    virtual_time: HashMap<String, u64>,
+    now: u64,
+}
+
+impl WeightedFairScheduler {
+    /// Create a scheduler with per-session weights (missing sessions get 1)
+    pub fn new(weights: HashMap<String, u32>) -> Self {
+        Self {
+            weights,
+            virtual_time: HashMap::new(),
+            now: 0,
+        }
+    }
+
+    fn weight(&self, session_id: &str) -> u64 {
+        u64::from(self.weights.get(session_id).copied().unwrap_or(1).max(1))
+    }
+}
+
+impl Scheduler for WeightedFairScheduler {
+    fn select(&mut self, candidates: &[&QueueEntry]) -> Option<Uuid> {
+        for entry in candidates {
+            self.virtual_time
+                .entry(entry.session_id.clone())
+                .or_insert(self.now);
+        }
+
+        let chosen = candidates
+            .iter()
+            .min_by_key(|e| self.virtual_time[&e.session_id])?;
+
+        let start = self.virtual_time[&chosen.session_id];
+        self.now = self.now.max(start);
+        self.virtual_time.insert(
+            chosen.session_id.clone(),
+            start + WFQ_COST / self.weight(&chosen.session_id),
+        );
+
+        Some(chosen.id)
+    }
+
+    fn retain_sessions(&mut self, active: &HashSet<&str>) {
+        self.virtual_time
+            .retain(|session, _| active.contains(session.as_str()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::queue::EntryStatus;
+    use chrono::Utc;
+
+    fn entry(session_id: &str) -> QueueEntry {
+        QueueEntry {
+            id: Uuid::new_v4(),
+            agent_id: Uuid::new_v4().to_string(),
+            session_id: session_id.to_string(),
+            branch: "agent/test".to_string(),
+            worktree: "/tmp/worktree".into(),
+            target_branch: "main".to_string(),
+            priority: 0,
+            depends_on: vec![],
+            attempts: 0,
+            queued_at: Utc::now(),
+            status: EntryStatus::Pending,
+            last_error: None,
+            conflict_files: vec![],
+            next_retry_at: None,
+            finished_at: None,
+            updated_at: Utc::now(),
+        }
+    }
+
+    /// Queue `depth` entries per session, oldest sessions' entries first,
+    /// and merge them all. Returns the sessions in merge order.
+    fn drain(scheduler: &mut dyn Scheduler, depths: &[(&str, usize)]) -> Vec<String> {
+        let mut queue: Vec<QueueEntry> = depths
+            .iter()
+            .flat_map(|(session, depth)| (0..*depth).map(|_| entry(session)))
+            .collect();
+        let mut order = Vec::new();
+
+        while !queue.is_empty() {
+            let active: HashSet<&str> = queue.iter().map(|e| e.session_id.as_str()).collect();
+            scheduler.retain_sessions(&active);
+            let candidates: Vec<&QueueEntry> = queue.iter().collect();
+            let id = scheduler.select(&candidates).unwrap();
+
+            let index = queue.iter().position(|e| e.id == id).unwrap();
+            order.push(queue.remove(index).session_id);
+        }
+        order
+    }
+
+    fn served(order: &[String], session: &str) -> usize {
+        order.iter().filter(|s| *s == session).count()
+    }
+
+    #[test]
+    fn round_robin_serves_every_session_in_turn() {
+        let depths = [("a", 6), ("b", 3), ("c", 1)];
+        let order = drain(&mut RoundRobinScheduler::default(), &depths);
+
+        assert_eq!(&order[..3], ["a", "b", "c"]);
+        assert_eq!(&order[3..7], ["a", "b", "a", "b"]);
+        assert!(order[7..].iter().all(|s| s == "a"));
+    }
+
+    #[test]
+    fn weighted_fair_serves_every_session_by_weight() {
+        let weights = HashMap::from([("a".to_string(), 3)]);
+        let depths = [("a", 12), ("b", 4), ("c", 2)];
+        let order = drain(&mut WeightedFairScheduler::new(weights), &depths);
+
+        // No session waits for a deeper one to drain
+        let first = &order[..5];
+        for session in ["a", "b", "c"] {
+            assert!(
+                served(first, session) > 0,
+                "{} starved in {:?}",
+                session,
+                first
+            );
+        }
+        // a merges three times as often as b while both have entries
+        let until_b_done = order.iter().rposition(|s| s == "b").unwrap() + 1;
+        assert!(served(&order[..until_b_done], "a") >= 9);
+    }
+
+    #[test]
+    fn sessions_with_entries_in_flight_keep_their_turn() {
+        let (a1, b1) = (entry("a"), entry("b"));
+        let (b2, a2) = (entry("b"), entry("a"));
+        let active = HashSet::from(["a", "b"]);
+
+        let mut round_robin = RoundRobinScheduler::default();
+        round_robin.retain_sessions(&active);
+        assert_eq!(round_robin.select(&[&a1, &b1]), Some(a1.id));
+        // a's next entry is backing off, but a still has work
+        round_robin.retain_sessions(&active);
+        assert_eq!(round_robin.select(&[&b1]), Some(b1.id));
+        round_robin.retain_sessions(&active);
+        assert_eq!(round_robin.select(&[&b2, &a2]), Some(a2.id));
+
+        let mut weighted = WeightedFairScheduler::new(HashMap::new());
+        weighted.retain_sessions(&active);
+        assert_eq!(weighted.select(&[&a1, &b1]), Some(a1.id));
+        weighted.retain_sessions(&active);
+        assert_eq!(weighted.select(&[&b1]), Some(b1.id));
+        weighted.retain_sessions(&active);
+        assert_eq!(weighted.select(&[&b2, &a2]), Some(b2.id));
+    }
+}
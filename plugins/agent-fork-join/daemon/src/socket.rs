@@ -0,0 +1,199 @@
+//! Where the daemon socket lives: one socket per user and repository
+
+use crate::error::{DaemonError, DaemonResult};
+use crate::repos;
+use std::fs::{DirBuilder, Permissions};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+
+/// Default socket for the repository containing `path`
+///
+/// The socket is named after a hash of the repository's canonical git
+/// dir, so every worktree of a repository finds the same daemon.
+pub fn default_path(path: &Path) -> DaemonResult<PathBuf> {
+    let repo = git2::Repository::discover(path)
+        .map_err(|_| DaemonError::RepoNotFound(path.display().to_string()))?;
+    let mut git_dir = repo.path().to_path_buf();
+    // Worktrees point at the main repository's git dir
+    if repo.is_worktree() {
+        if let Ok(common) = std::fs::read_to_string(git_dir.join("commondir")) {
+            git_dir = git_dir.join(common.trim());
+        }
+    }
+    let git_dir = std::fs::canonicalize(git_dir)?;
+
+    Ok(runtime_dir().join(format!("{}.sock", repos::repo_id(&git_dir))))
+}
+
+/// Directory for the current user's sockets: `$XDG_RUNTIME_DIR/merge-daemon`,
+/// or a per-user directory in the system temp dir if it isn't set
+pub fn runtime_dir() -> PathBuf {
+    match std::env::var_os("XDG_RUNTIME_DIR").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir).join("merge-daemon"),
+        None => {
+            let user = std::env::var("USER").unwrap_or_else(|_| "default".to_string());
+            std::env::temp_dir().join(format!("merge-daemon-{}", user))
+        }
+    }
+}
+
+/// Create the directory for the socket at `path` if it doesn't exist yet,
+/// and check that no other user can change it
+///
+/// The default runtime directory is the daemon's own: it must be a real
+/// directory owned by the current user with mode 0700. A shared socket
+/// can't live there, since other users can't traverse it. Any other
+/// directory, such as one given with `--socket`, must be owned by the
+/// current user and not writable by group or others unless it is sticky.
+pub fn prepare_dir(path: &Path, shared: bool) -> DaemonResult<()> {
+    let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) else {
+        return Ok(());
+    };
+    prepare(dir, shared, dir == runtime_dir())
+}
+
+fn prepare(dir: &Path, shared: bool, default: bool) -> DaemonResult<()> {
+    let mode = if shared { 0o711 } else { 0o700 };
+    let unsafe_dir = |reason: String| DaemonError::UnsafeSocketDir {
+        dir: dir.to_path_buf(),
+        reason,
+    };
+
+    if shared && default {
+        return Err(unsafe_dir(
+            "other users can't reach the default runtime directory, \
+             give a --socket in a directory they can reach"
+                .to_string(),
+        ));
+    }
+
+    if std::fs::symlink_metadata(dir).is_err() {
+        DirBuilder::new().recursive(true).mode(mode).create(dir)?;
+        // Not masked by the umask
+        std::fs::set_permissions(dir, Permissions::from_mode(mode))?;
+    }
+
+    // lstat the runtime directory, so a symlink planted in its place is
+    // refused; a directory the user chose may be reached through one
+    let metadata = if default {
+        std::fs::symlink_metadata(dir)?
+    } else {
+        std::fs::metadata(dir)?
+    };
+    if !metadata.file_type().is_dir() {
+        return Err(unsafe_dir("not a directory".to_string()));
+    }
+    // SAFETY: geteuid has no preconditions and can't fail
+    let uid = unsafe { libc::geteuid() };
+    if metadata.uid() != uid {
+        return Err(unsafe_dir(format!(
+            "owned by uid {}, not {}",
+            metadata.uid(),
+            uid
+        )));
+    }
+
+    let dir_mode = metadata.mode() & 0o7777;
+    if default && dir_mode != mode {
+        return Err(unsafe_dir(format!(
+            "mode is {:o}, expected {:o}",
+            dir_mode, mode
+        )));
+    }
+    if dir_mode & 0o022 != 0 && dir_mode & 0o1000 == 0 {
+        return Err(unsafe_dir(format!(
+            "mode is {:o}, writable by group or others",
+            dir_mode
+        )));
+    }
+
+    Ok(())
+}
+
+/// Make the socket at `path` accessible only to the current user, or to
+/// every user if `shared`; the daemon then checks each peer's uid itself
+pub fn restrict(path: &Path, shared: bool) -> DaemonResult<()> {
+    let mode = if shared { 0o666 } else { 0o600 };
+    std::fs::set_permissions(path, Permissions::from_mode(mode))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn creates_a_private_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir.path().join("sockets").join("repo.sock");
+
+        prepare_dir(&socket, false).unwrap();
+
+        let metadata = std::fs::metadata(dir.path().join("sockets")).unwrap();
+        assert_eq!(metadata.mode() & 0o777, 0o700);
+    }
+
+    #[test]
+    fn shared_dir_is_traversable() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir.path().join("sockets").join("repo.sock");
+
+        prepare_dir(&socket, true).unwrap();
+
+        let metadata = std::fs::metadata(dir.path().join("sockets")).unwrap();
+        assert_eq!(metadata.mode() & 0o777, 0o711);
+    }
+
+    #[test]
+    fn runtime_dir_refuses_a_symlink() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("target");
+        DirBuilder::new().mode(0o700).create(&target).unwrap();
+        let sockets = dir.path().join("sockets");
+        std::os::unix::fs::symlink(&target, &sockets).unwrap();
+
+        let result = prepare(&sockets, false, true);
+        assert!(matches!(result, Err(DaemonError::UnsafeSocketDir { .. })));
+
+        // A directory the user chose may be a symlink
+        prepare(&sockets, false, false).unwrap();
+    }
+
+    #[test]
+    fn runtime_dir_must_be_private() {
+        let dir = tempfile::tempdir().unwrap();
+        let sockets = dir.path().join("sockets");
+        DirBuilder::new().mode(0o755).create(&sockets).unwrap();
+        std::fs::set_permissions(&sockets, Permissions::from_mode(0o755)).unwrap();
+
+        let result = prepare(&sockets, false, true);
+
+        assert!(matches!(result, Err(DaemonError::UnsafeSocketDir { .. })));
+    }
+
+    #[test]
+    fn accepts_project_dirs_others_can_read() {
+        let dir = tempfile::tempdir().unwrap();
+        for mode in [0o755, 0o750, 0o1777] {
+            let sockets = dir.path().join(format!("{:o}", mode));
+            std::fs::create_dir(&sockets).unwrap();
+            std::fs::set_permissions(&sockets, Permissions::from_mode(mode)).unwrap();
+
+            prepare_dir(&sockets.join("repo.sock"), false).unwrap();
+        }
+    }
+
+    #[test]
+    fn refuses_a_dir_others_can_write() {
+        let dir = tempfile::tempdir().unwrap();
+        for mode in [0o777, 0o770] {
+            let sockets = dir.path().join(format!("{:o}", mode));
+            std::fs::create_dir(&sockets).unwrap();
+            std::fs::set_permissions(&sockets, Permissions::from_mode(mode)).unwrap();
+
+            let result = prepare_dir(&sockets.join("repo.sock"), false);
+
+            assert!(matches!(result, Err(DaemonError::UnsafeSocketDir { .. })));
+        }
+    }
+}
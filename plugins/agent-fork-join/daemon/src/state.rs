@@ -1,18 +1,26 @@
 //! Persistent state management using SQLite
 
+use crate::config::Config;
 use crate::error::DaemonResult;
 use crate::queue::QueueEntry;
 use rusqlite::{params, Connection};
-use std::path::Path;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use tokio::sync::Mutex;
 use tracing::debug;
 use uuid::Uuid;
 
 /// Manages persistent state in SQLite
+///
+/// Queue entries, merge history, sessions and tracked files are
+/// partitioned by repo;
+/// a state manager only sees the partition of its repo, set with
+/// [`StateManager::for_repo`].
 #[derive(Clone)]
 pub struct StateManager {
     conn: Arc<Mutex<Connection>>,
+    repo_id: String,
 }
 
 impl StateManager {
@@ -23,8 +31,17 @@ impl StateManager {
             std::fs::create_dir_all(parent)?;
         }
 
-        let conn = Connection::open(db_path)?;
+        Self::init(Connection::open(db_path)?)
+    }
+
+    /// Create a state manager backed by an in-memory database, for
+    /// embedded queues that don't need to survive a restart
+    pub async fn in_memory() -> DaemonResult<Self> {
+        Self::init(Connection::open_in_memory()?)
+    }
 
+    /// Create the schema on a fresh connection
+    fn init(conn: Connection) -> DaemonResult<Self> {
         // Initialize schema
         conn.execute_batch(
             r#"
@@ -35,6 +52,10 @@ impl StateManager {
                 branch TEXT NOT NULL,
                 worktree TEXT NOT NULL,
                 target_branch TEXT NOT NULL,
+                priority INTEGER NOT NULL DEFAULT 0,
+                depends_on TEXT NOT NULL DEFAULT '[]',
+                next_retry_at TEXT,
+                finished_at TEXT,
                 attempts INTEGER NOT NULL DEFAULT 0,
                 queued_at TEXT NOT NULL,
                 status TEXT NOT NULL,
@@ -47,16 +68,6 @@ impl StateManager {
             CREATE INDEX IF NOT EXISTS idx_queue_session ON queue_entries(session_id);
             CREATE INDEX IF NOT EXISTS idx_queue_status ON queue_entries(status);
 
-            CREATE TABLE IF NOT EXISTS sessions (
-                id TEXT PRIMARY KEY,
-                feature_branch TEXT NOT NULL,
-                base_branch TEXT NOT NULL,
-                original_prompt TEXT,
-                created_at TEXT NOT NULL,
-                state TEXT NOT NULL,
-                updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
-            );
-
             CREATE TABLE IF NOT EXISTS merge_history (
                 id INTEGER PRIMARY KEY AUTOINCREMENT,
                 entry_id TEXT NOT NULL,
@@ -66,25 +77,124 @@ impl StateManager {
                 merged_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
                 FOREIGN KEY (entry_id) REFERENCES queue_entries(id)
             );
+
+            CREATE TABLE IF NOT EXISTS repos (
+                id TEXT PRIMARY KEY,
+                path TEXT NOT NULL UNIQUE,
+                config TEXT NOT NULL,
+                registered_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+            );
             "#,
         )?;
 
+        // Tables partitioned by repo, rebuilt below if older
+        for (table, columns) in PARTITIONED_TABLES {
+            conn.execute_batch(&format!(
+                "CREATE TABLE IF NOT EXISTS {} ({});",
+                table, columns
+            ))?;
+        }
+
+        // Columns added after the initial schema
+        add_column_if_missing(&conn, "queue_entries", "priority", "INTEGER NOT NULL DEFAULT 0")?;
+        add_column_if_missing(&conn, "queue_entries", "depends_on", "TEXT NOT NULL DEFAULT '[]'")?;
+        add_column_if_missing(&conn, "queue_entries", "next_retry_at", "TEXT")?;
+        add_column_if_missing(&conn, "queue_entries", "finished_at", "TEXT")?;
+        for table in ["queue_entries", "merge_history", "tracked_files", "sessions"] {
+            add_column_if_missing(&conn, table, "repo_id", "TEXT NOT NULL DEFAULT ''")?;
+        }
+        conn.execute_batch(
+            "CREATE INDEX IF NOT EXISTS idx_queue_repo ON queue_entries(repo_id);",
+        )?;
+        for (table, columns) in PARTITIONED_TABLES {
+            partition_by_repo(&conn, table, columns)?;
+        }
+
         Ok(Self {
             conn: Arc::new(Mutex::new(conn)),
+            repo_id: String::new(),
         })
     }
 
+    /// A state manager on the same database, scoped to `repo_id`
+    pub fn for_repo(&self, repo_id: &str) -> Self {
+        Self {
+            conn: self.conn.clone(),
+            repo_id: repo_id.to_string(),
+        }
+    }
+
+    /// Move state written before repos were partitioned into `repo_id`
+    pub async fn claim_unpartitioned(&self, repo_id: &str) -> DaemonResult<()> {
+        let conn = self.conn.lock().await;
+
+        let mut claimed = 0;
+        for table in ["queue_entries", "merge_history", "tracked_files", "sessions"] {
+            claimed += conn.execute(
+                &format!("UPDATE OR IGNORE {} SET repo_id = ?1 WHERE repo_id = ''", table),
+                params![repo_id],
+            )?;
+        }
+        // Files and sessions the repo already has itself
+        conn.execute("DELETE FROM tracked_files WHERE repo_id = ''", [])?;
+        conn.execute("DELETE FROM sessions WHERE repo_id = ''", [])?;
+        if claimed > 0 {
+            debug!("Moved {} row(s) into repo {}", claimed, repo_id);
+        }
+
+        Ok(())
+    }
+
+    /// Save a repo and its configuration, replacing any earlier record
+    pub async fn save_repo(&self, repo_id: &str, path: &Path, config: &Config) -> DaemonResult<()> {
+        let conn = self.conn.lock().await;
+
+        conn.execute(
+            r#"
+            INSERT INTO repos (id, path, config) VALUES (?1, ?2, ?3)
+            ON CONFLICT(id) DO UPDATE SET path = excluded.path, config = excluded.config
+            "#,
+            params![repo_id, path.to_string_lossy(), serde_json::to_string(config)?],
+        )?;
+
+        Ok(())
+    }
+
+    /// Load all registered repos, oldest first
+    pub async fn load_repos(&self) -> DaemonResult<Vec<RepoRecord>> {
+        let conn = self.conn.lock().await;
+
+        let mut stmt = conn.prepare(
+            "SELECT id, path, config FROM repos ORDER BY registered_at ASC, rowid ASC",
+        )?;
+
+        let repos = stmt
+            .query_map([], |row| {
+                let config: String = row.get(2)?;
+                Ok(RepoRecord {
+                    repo_id: row.get(0)?,
+                    path: PathBuf::from(row.get::<_, String>(1)?),
+                    config: serde_json::from_str(&config).ok(),
+                })
+            })?
+            .filter_map(|r| r.ok())
+            .collect();
+
+        Ok(repos)
+    }
+
     /// Save a queue entry
     pub async fn save_entry(&self, entry: &QueueEntry) -> DaemonResult<()> {
         let conn = self.conn.lock().await;
 
         let conflict_files = serde_json::to_string(&entry.conflict_files)?;
+        let depends_on = serde_json::to_string(&entry.depends_on)?;
 
         conn.execute(
             r#"
             INSERT OR REPLACE INTO queue_entries
-            (id, agent_id, session_id, branch, worktree, target_branch, attempts, queued_at, status, last_error, conflict_files, updated_at)
-            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, CURRENT_TIMESTAMP)
+            (id, agent_id, session_id, branch, worktree, target_branch, attempts, queued_at, status, last_error, conflict_files, priority, depends_on, next_retry_at, finished_at, updated_at, repo_id)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17)
             "#,
             params![
                 entry.id.to_string(),
@@ -98,6 +208,12 @@ impl StateManager {
                 serde_json::to_string(&entry.status)?,
                 entry.last_error,
                 conflict_files,
+                entry.priority,
+                depends_on,
+                entry.next_retry_at.map(|at| at.to_rfc3339()),
+                entry.finished_at.map(|at| at.to_rfc3339()),
+                entry.updated_at.to_rfc3339(),
+                self.repo_id,
             ],
         )?;
 
@@ -106,59 +222,64 @@ impl StateManager {
     }
 
     /// Delete a queue entry
+    #[allow(dead_code)]
     pub async fn delete_entry(&self, id: &Uuid) -> DaemonResult<()> {
         let conn = self.conn.lock().await;
 
         conn.execute(
-            "DELETE FROM queue_entries WHERE id = ?1",
-            params![id.to_string()],
+            "DELETE FROM queue_entries WHERE id = ?1 AND repo_id = ?2",
+            params![id.to_string(), self.repo_id],
         )?;
 
         debug!("Deleted entry {}", id);
         Ok(())
     }
 
-    /// Load all pending entries (for recovery)
+    /// Load all active entries (for recovery)
     pub async fn load_pending_entries(&self) -> DaemonResult<Vec<QueueEntry>> {
         let conn = self.conn.lock().await;
 
-        let mut stmt = conn.prepare(
+        let mut stmt = conn.prepare(&format!(
             r#"
-            SELECT id, agent_id, session_id, branch, worktree, target_branch, attempts, queued_at, status, last_error, conflict_files
+            SELECT {}
             FROM queue_entries
-            WHERE status IN ('"Pending"', '"Processing"')
+            WHERE status IN ('"Pending"', '"Processing"', '"Conflict"') AND repo_id = ?1
             ORDER BY queued_at ASC
             "#,
-        )?;
+            ENTRY_COLUMNS
+        ))?;
 
         let entries = stmt
-            .query_map([], |row| {
-                let id: String = row.get(0)?;
-                let conflict_files: String = row.get(10)?;
-                let status: String = row.get(8)?;
-
-                Ok(QueueEntry {
-                    id: Uuid::parse_str(&id).unwrap_or_else(|_| Uuid::new_v4()),
-                    agent_id: row.get(1)?,
-                    session_id: row.get(2)?,
-                    branch: row.get(3)?,
-                    worktree: std::path::PathBuf::from(row.get::<_, String>(4)?),
-                    target_branch: row.get(5)?,
-                    attempts: row.get(6)?,
-                    queued_at: chrono::DateTime::parse_from_rfc3339(&row.get::<_, String>(7)?)
-                        .map(|dt| dt.with_timezone(&chrono::Utc))
-                        .unwrap_or_else(|_| chrono::Utc::now()),
-                    status: serde_json::from_str(&status).unwrap_or(crate::queue::EntryStatus::Pending),
-                    last_error: row.get(9)?,
-                    conflict_files: serde_json::from_str(&conflict_files).unwrap_or_default(),
-                })
-            })?
+            .query_map(params![self.repo_id], entry_from_row)?
             .filter_map(|r| r.ok())
             .collect();
 
         Ok(entries)
     }
 
+    /// Load the most recent entry for an agent, whatever its status
+    pub async fn load_latest_entry(&self, agent_id: &str) -> DaemonResult<Option<QueueEntry>> {
+        let conn = self.conn.lock().await;
+
+        let mut stmt = conn.prepare(&format!(
+            r#"
+            SELECT {}
+            FROM queue_entries
+            WHERE agent_id = ?1 AND repo_id = ?2
+            ORDER BY queued_at DESC
+            LIMIT 1
+            "#,
+            ENTRY_COLUMNS
+        ))?;
+
+        let entry = stmt
+            .query_map(params![agent_id, self.repo_id], entry_from_row)?
+            .filter_map(|r| r.ok())
+            .next();
+
+        Ok(entry)
+    }
+
     /// Record a successful merge in history
     pub async fn record_merge(
         &self,
@@ -171,34 +292,89 @@ impl StateManager {
 
         conn.execute(
             r#"
-            INSERT INTO merge_history (entry_id, agent_id, session_id, commit_sha)
-            VALUES (?1, ?2, ?3, ?4)
+            INSERT INTO merge_history (entry_id, agent_id, session_id, commit_sha, repo_id)
+            VALUES (?1, ?2, ?3, ?4, ?5)
             "#,
-            params![entry_id.to_string(), agent_id, session_id, commit_sha],
+            params![entry_id.to_string(), agent_id, session_id, commit_sha, self.repo_id],
         )?;
 
         Ok(())
     }
 
-    /// Get merge history for a session
-    pub async fn get_session_merges(&self, session_id: &str) -> DaemonResult<Vec<MergeRecord>> {
+    /// Record a file written by an agent. Returns false if it was already
+    /// tracked.
+    pub async fn track_file(&self, agent_id: &str, path: &str) -> DaemonResult<bool> {
+        let conn = self.conn.lock().await;
+
+        let inserted = conn.execute(
+            "INSERT OR IGNORE INTO tracked_files (agent_id, path, repo_id) VALUES (?1, ?2, ?3)",
+            params![agent_id, path, self.repo_id],
+        )?;
+
+        Ok(inserted > 0)
+    }
+
+    /// Load the files an agent has written, in the order they were tracked
+    pub async fn load_tracked_files(&self, agent_id: &str) -> DaemonResult<Vec<String>> {
         let conn = self.conn.lock().await;
 
         let mut stmt = conn.prepare(
             r#"
-            SELECT agent_id, commit_sha, merged_at
+            SELECT path
+            FROM tracked_files
+            WHERE agent_id = ?1 AND repo_id = ?2
+            ORDER BY tracked_at ASC, rowid ASC
+            "#,
+        )?;
+
+        let paths = stmt
+            .query_map(params![agent_id, self.repo_id], |row| row.get(0))?
+            .filter_map(|r| r.ok())
+            .collect();
+
+        Ok(paths)
+    }
+
+    /// Forget the files an agent has written
+    pub async fn clear_tracked_files(&self, agent_id: &str) -> DaemonResult<()> {
+        let conn = self.conn.lock().await;
+
+        conn.execute(
+            "DELETE FROM tracked_files WHERE agent_id = ?1 AND repo_id = ?2",
+            params![agent_id, self.repo_id],
+        )?;
+
+        Ok(())
+    }
+
+    /// Load completed merges, newest first, optionally filtered by
+    /// session and agent
+    pub async fn load_merge_history(
+        &self,
+        session_id: Option<&str>,
+        agent_id: Option<&str>,
+        limit: usize,
+    ) -> DaemonResult<Vec<MergeRecord>> {
+        let conn = self.conn.lock().await;
+
+        let mut stmt = conn.prepare(
+            r#"
+            SELECT agent_id, session_id, commit_sha, merged_at
             FROM merge_history
-            WHERE session_id = ?1
-            ORDER BY merged_at ASC
+            WHERE (?1 IS NULL OR session_id = ?1) AND (?2 IS NULL OR agent_id = ?2)
+                AND repo_id = ?4
+            ORDER BY id DESC
+            LIMIT ?3
             "#,
         )?;
 
         let records = stmt
-            .query_map(params![session_id], |row| {
+            .query_map(params![session_id, agent_id, limit as i64, self.repo_id], |row| {
                 Ok(MergeRecord {
                     agent_id: row.get(0)?,
-                    commit_sha: row.get(1)?,
-                    merged_at: row.get(2)?,
+                    session_id: row.get(1)?,
+                    commit_sha: row.get(2)?,
+                    merged_at: row.get(3)?,
                 })
             })?
             .filter_map(|r| r.ok())
@@ -209,9 +385,251 @@ impl StateManager {
 }
 
 /// Record of a completed merge
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MergeRecord {
     pub agent_id: String,
-    pub commit_sha: String,
+    pub session_id: String,
+    pub commit_sha: Option<String>,
     pub merged_at: String,
 }
+
+/// A repo registered with the daemon
+#[derive(Debug, Clone)]
+pub struct RepoRecord {
+    pub repo_id: String,
+    pub path: PathBuf,
+    /// None if the saved configuration no longer parses
+    pub config: Option<Config>,
+}
+
+/// Tables with `repo_id` in their primary key, and their columns
+const PARTITIONED_TABLES: [(&str, &str); 2] = [
+    (
+        "sessions",
+        "id TEXT NOT NULL,
+        feature_branch TEXT NOT NULL,
+        base_branch TEXT NOT NULL,
+        original_prompt TEXT,
+        created_at TEXT NOT NULL,
+        state TEXT NOT NULL,
+        updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+        repo_id TEXT NOT NULL DEFAULT '',
+        PRIMARY KEY (repo_id, id)",
+    ),
+    (
+        "tracked_files",
+        "agent_id TEXT NOT NULL,
+        path TEXT NOT NULL,
+        tracked_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+        repo_id TEXT NOT NULL DEFAULT '',
+        PRIMARY KEY (repo_id, agent_id, path)",
+    ),
+];
+
+/// Columns selected when loading queue entries, in `entry_from_row` order
+const ENTRY_COLUMNS: &str = "id, agent_id, session_id, branch, worktree, target_branch, attempts, \
+     queued_at, status, last_error, conflict_files, priority, depends_on, next_retry_at, \
+     finished_at, updated_at";
+
+/// Build a queue entry from a row selected with `ENTRY_COLUMNS`
+fn entry_from_row(row: &rusqlite::Row) -> rusqlite::Result<QueueEntry> {
+    let id: String = row.get(0)?;
+    let conflict_files: String = row.get(10)?;
+    let status: String = row.get(8)?;
+    let depends_on: String = row.get(12)?;
+    let queued_at = chrono::DateTime::parse_from_rfc3339(&row.get::<_, String>(7)?)
+        .map(|dt| dt.with_timezone(&chrono::Utc))
+        .unwrap_or_else(|_| chrono::Utc::now());
+
+    Ok(QueueEntry {
+        id: Uuid::parse_str(&id).unwrap_or_else(|_| Uuid::new_v4()),
+        agent_id: row.get(1)?,
+        session_id: row.get(2)?,
+        branch: row.get(3)?,
+        worktree: std::path::PathBuf::from(row.get::<_, String>(4)?),
+        target_branch: row.get(5)?,
+        priority: row.get(11)?,
+        depends_on: serde_json::from_str(&depends_on).unwrap_or_default(),
+        attempts: row.get(6)?,
+        queued_at,
+        status: serde_json::from_str(&status).unwrap_or(crate::queue::EntryStatus::Pending),
+        last_error: row.get(9)?,
+        conflict_files: serde_json::from_str(&conflict_files).unwrap_or_default(),
+        next_retry_at: parse_optional_time(row.get(13)?),
+        finished_at: parse_optional_time(row.get(14)?),
+        // Rows written before updated_at was tracked use SQLite's format
+        updated_at: parse_optional_time(row.get(15)?)
+            .or_else(|| {
+                chrono::NaiveDateTime::parse_from_str(
+                    &row.get::<_, String>(15).ok()?,
+                    "%Y-%m-%d %H:%M:%S",
+                )
+                .ok()
+                .map(|at| at.and_utc())
+            })
+            .unwrap_or(queued_at),
+    })
+}
+
+/// Parse an optional RFC 3339 timestamp column
+fn parse_optional_time(value: Option<String>) -> Option<chrono::DateTime<chrono::Utc>> {
+    value
+        .and_then(|at| chrono::DateTime::parse_from_rfc3339(&at).ok())
+        .map(|at| at.with_timezone(&chrono::Utc))
+}
+
+/// Rebuild `table` from before repos were partitioned with `repo_id` in
+/// its primary key, so repos can reuse the same keys. `columns` is the new
+/// table's definition; the old table must already have a `repo_id` column.
+fn partition_by_repo(conn: &Connection, table: &str, columns: &str) -> DaemonResult<()> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+    let existing: Vec<(String, i64)> = stmt
+        .query_map([], |row| Ok((row.get(1)?, row.get(5)?)))?
+        .filter_map(|r| r.ok())
+        .collect();
+    let partitioned = existing.iter().any(|(name, pk)| name == "repo_id" && *pk > 0);
+    if partitioned {
+        return Ok(());
+    }
+
+    debug!("Adding repo_id to the primary key of {}", table);
+    let names: Vec<&str> = existing.iter().map(|(name, _)| name.as_str()).collect();
+    let names = names.join(", ");
+    conn.execute_batch(&format!(
+        r#"
+        BEGIN;
+        CREATE TABLE {table}_partitioned ({columns});
+        INSERT INTO {table}_partitioned ({names})
+            SELECT {names} FROM {table} ORDER BY rowid;
+        DROP TABLE {table};
+        ALTER TABLE {table}_partitioned RENAME TO {table};
+        COMMIT;
+        "#,
+    ))?;
+
+    Ok(())
+}
+
+/// Add a column to an existing table if an older schema lacks it
+fn add_column_if_missing(
+    conn: &Connection,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> DaemonResult<()> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+    let exists = stmt
+        .query_map([], |row| row.get::<_, String>(1))?
+        .filter_map(|r| r.ok())
+        .any(|name| name == column);
+
+    if !exists {
+        debug!("Adding column {}.{}", table, column);
+        conn.execute(
+            &format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition),
+            [],
+        )?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn repos_track_the_same_agent_and_path() {
+        let state = StateManager::in_memory().await.unwrap();
+        let (a, b) = (state.for_repo("a"), state.for_repo("b"));
+
+        assert!(a.track_file("agent-1", "src/lib.rs").await.unwrap());
+        assert!(b.track_file("agent-1", "src/lib.rs").await.unwrap());
+        assert!(!b.track_file("agent-1", "src/lib.rs").await.unwrap());
+
+        a.clear_tracked_files("agent-1").await.unwrap();
+        assert!(a.load_tracked_files("agent-1").await.unwrap().is_empty());
+        assert_eq!(
+            b.load_tracked_files("agent-1").await.unwrap(),
+            vec!["src/lib.rs".to_string()]
+        );
+    }
+
+    #[tokio::test]
+    async fn migrates_tracked_files_from_before_partitioning() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("state.db");
+        {
+            let conn = Connection::open(&db_path).unwrap();
+            conn.execute_batch(
+                r#"
+                CREATE TABLE tracked_files (
+                    agent_id TEXT NOT NULL,
+                    path TEXT NOT NULL,
+                    tracked_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                    PRIMARY KEY (agent_id, path)
+                );
+                INSERT INTO tracked_files (agent_id, path) VALUES ('agent-1', 'src/lib.rs');
+                "#,
+            )
+            .unwrap();
+        }
+
+        let state = StateManager::new(&db_path).await.unwrap();
+        state.claim_unpartitioned("a").await.unwrap();
+        let (a, b) = (state.for_repo("a"), state.for_repo("b"));
+
+        assert_eq!(
+            a.load_tracked_files("agent-1").await.unwrap(),
+            vec!["src/lib.rs".to_string()]
+        );
+        assert!(b.track_file("agent-1", "src/lib.rs").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn migrates_sessions_from_before_partitioning() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("state.db");
+        {
+            let conn = Connection::open(&db_path).unwrap();
+            conn.execute_batch(
+                r#"
+                CREATE TABLE sessions (
+                    id TEXT PRIMARY KEY,
+                    feature_branch TEXT NOT NULL,
+                    base_branch TEXT NOT NULL,
+                    original_prompt TEXT,
+                    created_at TEXT NOT NULL,
+                    state TEXT NOT NULL,
+                    updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+                );
+                INSERT INTO sessions (id, feature_branch, base_branch, created_at, state)
+                    VALUES ('s1', 'feature/x', 'main', '2024-01-01T00:00:00Z', 'Active');
+                "#,
+            )
+            .unwrap();
+        }
+
+        let state = StateManager::new(&db_path).await.unwrap();
+        state.claim_unpartitioned("a").await.unwrap();
+
+        let conn = state.conn.lock().await;
+        let insert = |repo_id: &str| {
+            conn.execute(
+                "INSERT INTO sessions (id, feature_branch, base_branch, created_at, state, repo_id)
+                    VALUES ('s1', 'feature/y', 'main', '2024-01-02T00:00:00Z', 'Active', ?1)",
+                params![repo_id],
+            )
+        };
+        assert!(insert("a").is_err());
+        insert("b").unwrap();
+        let repos: Vec<String> = conn
+            .prepare("SELECT repo_id FROM sessions WHERE id = 's1' ORDER BY repo_id")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .map(|r| r.unwrap())
+            .collect();
+        assert_eq!(repos, ["a", "b"]);
+    }
+}
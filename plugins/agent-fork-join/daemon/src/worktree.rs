@@ -0,0 +1,742 @@
+//! Agent worktree lifecycle: creation, listing, removal and pruning
+
+use crate::config::Config;
+use crate::error::{DaemonError, DaemonResult};
+use crate::merger::find_branch_commit;
+use git2::build::CheckoutBuilder;
+use git2::{
+    BranchType, ErrorCode, IndexAddOption, Oid, Repository, ResetType, Signature, StatusOptions,
+    WorktreeAddOptions, WorktreePruneOptions,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tokio::sync::Notify;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+/// Name prefix of pooled worktrees
+const POOL_PREFIX: &str = "pool-";
+
+/// Plugin state directory, never committed from agent worktrees
+const STATE_DIR: &str = ".fork-join";
+
+/// A worktree created for an agent
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentWorktree {
+    pub agent_id: String,
+    pub branch: String,
+    pub worktree: PathBuf,
+}
+
+/// A commit created from an agent's worktree
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentCommit {
+    pub branch: String,
+    pub commit_sha: String,
+    /// Paths changed by the commit
+    pub files: Vec<String>,
+    /// Changed paths the agent never tracked (e.g. formatter fallout)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub untouched: Vec<String>,
+}
+
+/// State of a worktree on disk
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum WorktreeState {
+    /// Registered with git and present on disk
+    Active,
+    /// Registered with git and locked against pruning
+    Locked,
+    /// Pre-created and waiting in the pool to be handed out
+    Pooled,
+    /// Registered with git but the directory is gone
+    Missing,
+    /// Directory under `worktree_dir` that git does not know about
+    Orphaned,
+}
+
+/// A worktree as reported by WORKTREES
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorktreeInfo {
+    /// Git's name for the worktree (None if orphaned)
+    pub name: Option<String>,
+    pub path: PathBuf,
+    /// Checked-out branch, if any
+    pub branch: Option<String>,
+    pub state: WorktreeState,
+    /// Total size of files under the worktree
+    pub disk_usage_bytes: u64,
+}
+
+/// Pre-created detached worktrees, keyed by the branch they track
+#[derive(Default)]
+struct WorktreePool {
+    ready: HashMap<String, Vec<PathBuf>>,
+    /// Whether pool worktrees left by a previous run have been adopted
+    adopted: bool,
+}
+
+/// Manages agent worktrees under `worktree_dir`
+pub struct WorktreeManager {
+    repo_path: PathBuf,
+    config: Config,
+    pool: Mutex<WorktreePool>,
+    /// Signalled when a pooled worktree is handed out
+    pool_drained: Notify,
+}
+
+impl WorktreeManager {
+    /// Create a new worktree manager
+    pub fn new(repo_path: PathBuf, config: Config) -> Self {
+        Self {
+            repo_path,
+            config,
+            pool: Mutex::new(WorktreePool::default()),
+            pool_drained: Notify::new(),
+        }
+    }
+
+    /// Whether a worktree pool is configured
+    pub fn pool_enabled(&self) -> bool {
+        self.config.worktree_pool_size > 0 && !self.config.worktree_pool_branches.is_empty()
+    }
+
+    /// Wait until a pooled worktree has been handed out
+    pub async fn pool_drained(&self) {
+        self.pool_drained.notified().await;
+    }
+
+    /// Directory that holds agent worktrees
+    pub fn root(&self) -> DaemonResult<PathBuf> {
+        let repo = Repository::open(&self.repo_path)?;
+        worktree_root(&repo, &self.config)
+    }
+
+    /// Create an agent branch from `base_branch` and check it out in a new
+    /// worktree at `<worktree_dir>/<agent_id>`
+    pub fn create(
+        &self,
+        agent_id: &str,
+        session_id: &str,
+        base_branch: &str,
+    ) -> DaemonResult<AgentWorktree> {
+        let repo = Repository::open(&self.repo_path)?;
+        let base = find_branch_commit(&repo, base_branch)?;
+
+        let branch_name = format!(
+            "{}{}/{}",
+            self.config.agent_branch_prefix, session_id, agent_id
+        );
+
+        if let Some(path) = self.take_pooled(base_branch) {
+            match claim_pooled(&repo, &path, &branch_name, base.id()) {
+                Ok(()) => {
+                    info!(
+                        "Handed out pooled worktree {:?} on {} to agent {}",
+                        path, branch_name, agent_id
+                    );
+                    self.pool_drained.notify_one();
+                    return Ok(AgentWorktree {
+                        agent_id: agent_id.to_string(),
+                        branch: branch_name,
+                        worktree: path,
+                    });
+                }
+                Err(e) => {
+                    warn!("Discarding pooled worktree {:?}: {}", path, e);
+                    if let Err(e) = self.remove(&path) {
+                        warn!("Could not remove pooled worktree {:?}: {}", path, e);
+                    }
+                }
+            }
+        }
+
+        let name = worktree_name(agent_id);
+        let root = worktree_root(&repo, &self.config)?;
+        let path = root.join(&name);
+        if path.exists() {
+            return Err(DaemonError::Worktree(format!(
+                "Worktree already exists: {:?}",
+                path
+            )));
+        }
+        std::fs::create_dir_all(&root)?;
+
+        let branch = repo.branch(&branch_name, &base, false)?;
+
+        let mut opts = WorktreeAddOptions::new();
+        opts.reference(Some(branch.get()));
+
+        if let Err(e) = repo.worktree(&name, &path, Some(&opts)) {
+            // Don't leave a dangling branch behind
+            if let Err(cleanup) = repo
+                .find_branch(&branch_name, BranchType::Local)
+                .and_then(|mut b| b.delete())
+            {
+                warn!("Could not delete branch {}: {}", branch_name, cleanup);
+            }
+            return Err(e.into());
+        }
+
+        info!(
+            "Created worktree {:?} on {} for agent {}",
+            path, branch_name, agent_id
+        );
+
+        Ok(AgentWorktree {
+            agent_id: agent_id.to_string(),
+            branch: branch_name,
+            worktree: path,
+        })
+    }
+
+    /// Remove an agent worktree and its git metadata.
+    ///
+    /// Only paths under `worktree_dir` are touched. Returns whether anything
+    /// was removed.
+    pub fn remove(&self, path: &Path) -> DaemonResult<bool> {
+        let repo = Repository::open(&self.repo_path)?;
+        let Some(path) = self.managed_path(&repo, path)? else {
+            return Ok(false);
+        };
+
+        for name in repo.worktrees()?.iter().flatten() {
+            let worktree = repo.find_worktree(name)?;
+            if worktree.path().canonicalize().ok().as_deref() != Some(path.as_path()) {
+                continue;
+            }
+
+            debug!("Pruning worktree {} at {:?}", name, path);
+            worktree.prune(Some(
+                WorktreePruneOptions::new()
+                    .valid(true)
+                    .locked(false)
+                    .working_tree(true),
+            ))?;
+            info!("Removed worktree {:?}", path);
+            return Ok(true);
+        }
+
+        // Not registered with git (e.g. metadata already pruned), just a
+        // leftover directory
+        std::fs::remove_dir_all(&path)?;
+        info!("Removed orphaned worktree directory {:?}", path);
+        Ok(true)
+    }
+
+    /// Stage changes in an agent worktree and commit them on its agent
+    /// branch. Ignored files and the plugin state directory are left out.
+    /// With `tracked_only` only the `tracked` files are staged, and it is an
+    /// error if there are none. Changed files missing from a non-empty
+    /// `tracked` list are reported as untouched. Returns None if there is
+    /// nothing to commit.
+    pub fn commit(
+        &self,
+        path: &Path,
+        agent_id: &str,
+        message: &str,
+        tracked: &[String],
+        tracked_only: bool,
+    ) -> DaemonResult<Option<AgentCommit>> {
+        let repo = Repository::open(path)?;
+
+        let head = repo.head()?;
+        let branch = match head.shorthand() {
+            Some(name)
+                if head.is_branch() && name.starts_with(&self.config.agent_branch_prefix) =>
+            {
+                name.to_string()
+            }
+            _ => {
+                return Err(DaemonError::Worktree(format!(
+                    "{:?} is not on an agent branch",
+                    path
+                )))
+            }
+        };
+        let parent = head.peel_to_commit()?;
+
+        let mut skip_state_dir = |p: &Path, _: &[u8]| -> i32 {
+            if p.starts_with(STATE_DIR) {
+                1
+            } else {
+                0
+            }
+        };
+
+        let tracked: HashSet<String> = tracked
+            .iter()
+            .filter_map(|p| worktree_relative(path, p))
+            .collect();
+
+        let mut index = repo.index()?;
+        let untouched = if tracked.is_empty() {
+            vec![]
+        } else {
+            let untouched: Vec<String> = changed_paths(&repo)?
+                .into_iter()
+                .filter(|p| !tracked.contains(p))
+                .collect();
+            if !untouched.is_empty() {
+                warn!(
+                    "Agent {} changed files it never wrote: {}",
+                    agent_id,
+                    untouched.join(", ")
+                );
+            }
+            untouched
+        };
+
+        if tracked_only {
+            if tracked.is_empty() {
+                return Err(DaemonError::InvalidRequest(format!(
+                    "agent {} tracked no files in {:?}",
+                    agent_id, path
+                )));
+            }
+            for p in &tracked {
+                if Path::new(p).starts_with(STATE_DIR) || repo.is_path_ignored(Path::new(p))? {
+                    continue;
+                }
+                if path.join(p).exists() {
+                    index.add_path(Path::new(p))?;
+                } else if index.get_path(Path::new(p), 0).is_some() {
+                    index.remove_path(Path::new(p))?;
+                }
+            }
+        } else {
+            index.add_all(["*"], IndexAddOption::DEFAULT, Some(&mut skip_state_dir))?;
+            index.update_all(["*"], Some(&mut skip_state_dir))?;
+        }
+        index.write()?;
+
+        let tree_id = index.write_tree()?;
+        if tree_id == parent.tree_id() {
+            debug!("No changes to commit in {:?}", path);
+            return Ok(None);
+        }
+        let tree = repo.find_tree(tree_id)?;
+
+        let committer = repo.signature()?;
+        let author = Signature::now(
+            &format!("Agent {}", agent_id),
+            committer.email().unwrap_or_default(),
+        )?;
+
+        let oid = repo.commit(
+            Some("HEAD"),
+            &author,
+            &committer,
+            message,
+            &tree,
+            &[&parent],
+        )?;
+
+        let diff = repo.diff_tree_to_tree(Some(&parent.tree()?), Some(&tree), None)?;
+        let files = diff
+            .deltas()
+            .filter_map(|d| d.new_file().path().or_else(|| d.old_file().path()))
+            .map(|p| p.to_string_lossy().into_owned())
+            .collect();
+
+        info!("Committed {} on {} for agent {}", oid, branch, agent_id);
+
+        Ok(Some(AgentCommit {
+            branch,
+            commit_sha: oid.to_string(),
+            files,
+            untouched,
+        }))
+    }
+
+    /// Undo an agent commit made by [`WorktreeManager::commit`], keeping its
+    /// changes staged
+    pub fn uncommit(&self, path: &Path, commit: &AgentCommit) -> DaemonResult<()> {
+        let repo = Repository::open(path)?;
+        let head = repo.head()?.peel_to_commit()?;
+        if head.id().to_string() != commit.commit_sha {
+            return Err(DaemonError::Worktree(format!(
+                "{} moved past {}",
+                commit.branch, commit.commit_sha
+            )));
+        }
+
+        repo.reset(head.parent(0)?.as_object(), ResetType::Soft, None)?;
+        Ok(())
+    }
+
+    /// Delete an agent branch. Only branches under `agent_branch_prefix`
+    /// are touched. Returns whether the branch existed.
+    pub fn delete_branch(&self, branch: &str) -> DaemonResult<bool> {
+        if !branch.starts_with(&self.config.agent_branch_prefix) {
+            return Err(DaemonError::Worktree(format!(
+                "Refusing to delete {}: not an agent branch",
+                branch
+            )));
+        }
+
+        let repo = Repository::open(&self.repo_path)?;
+        let deleted = match repo.find_branch(branch, BranchType::Local) {
+            Ok(mut b) => {
+                b.delete()?;
+                info!("Deleted agent branch {}", branch);
+                true
+            }
+            Err(e) if e.code() == ErrorCode::NotFound => false,
+            Err(e) => return Err(e.into()),
+        };
+        Ok(deleted)
+    }
+
+    /// List worktrees registered with git plus orphaned directories under
+    /// `worktree_dir`
+    pub fn list(&self) -> DaemonResult<Vec<WorktreeInfo>> {
+        let repo = Repository::open(&self.repo_path)?;
+        let mut infos = Vec::new();
+        let mut known = Vec::new();
+
+        for name in repo.worktrees()?.iter().flatten() {
+            let worktree = repo.find_worktree(name)?;
+            let path = worktree.path().to_path_buf();
+
+            let state = if self.is_pooled(&path) {
+                WorktreeState::Pooled
+            } else if worktree.validate().is_err() {
+                WorktreeState::Missing
+            } else if !matches!(worktree.is_locked(), Ok(git2::WorktreeLockStatus::Unlocked)) {
+                WorktreeState::Locked
+            } else {
+                WorktreeState::Active
+            };
+
+            let branch = Repository::open_from_worktree(&worktree)
+                .ok()
+                .and_then(|wt_repo| {
+                    if wt_repo.head_detached().ok()? {
+                        return None;
+                    }
+                    let head = wt_repo.head().ok()?;
+                    head.shorthand().map(str::to_string)
+                });
+
+            known.push(path.canonicalize().unwrap_or_else(|_| path.clone()));
+            infos.push(WorktreeInfo {
+                name: Some(name.to_string()),
+                disk_usage_bytes: disk_usage(&path),
+                path,
+                branch,
+                state,
+            });
+        }
+
+        let root = worktree_root(&repo, &self.config)?;
+        if let Ok(dir) = std::fs::read_dir(&root) {
+            for dir_entry in dir.flatten() {
+                let path = dir_entry.path();
+                let canonical = path.canonicalize().unwrap_or_else(|_| path.clone());
+                if !path.is_dir() || known.contains(&canonical) {
+                    continue;
+                }
+
+                infos.push(WorktreeInfo {
+                    name: None,
+                    disk_usage_bytes: disk_usage(&path),
+                    path,
+                    branch: None,
+                    state: WorktreeState::Orphaned,
+                });
+            }
+        }
+
+        Ok(infos)
+    }
+
+    /// Clean up after crashed agents: drop git metadata for worktrees whose
+    /// directory is gone and delete orphaned directories under
+    /// `worktree_dir`. Returns the affected paths.
+    pub fn prune(&self, dry_run: bool) -> DaemonResult<Vec<PathBuf>> {
+        let repo = Repository::open(&self.repo_path)?;
+        let mut pruned = Vec::new();
+
+        for info in self.list()? {
+            match info.state {
+                WorktreeState::Missing => {
+                    if !dry_run {
+                        if let Some(name) = &info.name {
+                            repo.find_worktree(name)?.prune(None)?;
+                            info!("Pruned metadata for missing worktree {}", name);
+                        }
+                    }
+                    pruned.push(info.path);
+                }
+                WorktreeState::Orphaned => {
+                    if !dry_run {
+                        std::fs::remove_dir_all(&info.path)?;
+                        info!("Removed orphaned worktree directory {:?}", info.path);
+                    }
+                    pruned.push(info.path);
+                }
+                WorktreeState::Active | WorktreeState::Locked | WorktreeState::Pooled => {}
+            }
+        }
+
+        Ok(pruned)
+    }
+
+    /// Bring the pool up to `worktree_pool_size` detached worktrees per
+    /// pool branch, refreshing existing ones to the branch tip. This does
+    /// blocking git work and can take a while on large repositories.
+    pub fn fill_pool(&self) -> DaemonResult<()> {
+        let repo = Repository::open(&self.repo_path)?;
+        let root = worktree_root(&repo, &self.config)?;
+        std::fs::create_dir_all(&root)?;
+
+        // Detached pool worktrees from a previous run can be reused
+        let mut spare = Vec::new();
+        if !std::mem::replace(&mut self.pool.lock().unwrap().adopted, true) {
+            for name in repo.worktrees()?.iter().flatten() {
+                if !name.starts_with(POOL_PREFIX) {
+                    continue;
+                }
+                let worktree = repo.find_worktree(name)?;
+                let detached = Repository::open_from_worktree(&worktree)
+                    .and_then(|r| r.head_detached())
+                    .unwrap_or(false);
+                if detached {
+                    spare.push(worktree.path().to_path_buf());
+                }
+            }
+        }
+
+        for branch in &self.config.worktree_pool_branches {
+            let tip = match find_branch_commit(&repo, branch) {
+                Ok(commit) => commit.id(),
+                Err(e) => {
+                    warn!("Not pooling worktrees for {}: {}", branch, e);
+                    continue;
+                }
+            };
+
+            // Take the branch's worktrees out of the pool while they are
+            // refreshed so they can't be handed out mid-checkout
+            let mut ready = self
+                .pool
+                .lock()
+                .unwrap()
+                .ready
+                .remove(branch)
+                .unwrap_or_default();
+
+            ready.retain(|path| match refresh_pooled(path, tip) {
+                Ok(()) => true,
+                Err(e) => {
+                    warn!("Dropping pooled worktree {:?}: {}", path, e);
+                    false
+                }
+            });
+
+            while ready.len() < self.config.worktree_pool_size {
+                let path = match spare.pop() {
+                    Some(path) => match refresh_pooled(&path, tip) {
+                        Ok(()) => path,
+                        Err(e) => {
+                            warn!("Dropping pooled worktree {:?}: {}", path, e);
+                            continue;
+                        }
+                    },
+                    None => create_pooled(&repo, &root, tip)?,
+                };
+                debug!("Pooled worktree {:?} for {}", path, branch);
+                ready.push(path);
+            }
+
+            self.pool
+                .lock()
+                .unwrap()
+                .ready
+                .entry(branch.clone())
+                .or_default()
+                .extend(ready);
+        }
+
+        // Leftovers that no pool branch needs any more
+        for path in spare {
+            if let Err(e) = self.remove(&path) {
+                warn!("Could not remove pooled worktree {:?}: {}", path, e);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Take a warm worktree tracking `branch` out of the pool
+    fn take_pooled(&self, branch: &str) -> Option<PathBuf> {
+        self.pool.lock().unwrap().ready.get_mut(branch)?.pop()
+    }
+
+    /// Whether `path` is waiting in the pool
+    fn is_pooled(&self, path: &Path) -> bool {
+        self.pool
+            .lock()
+            .unwrap()
+            .ready
+            .values()
+            .any(|paths| paths.iter().any(|p| p == path))
+    }
+
+    /// Canonical form of `path` if it is an existing directory strictly
+    /// inside `worktree_dir`
+    fn managed_path(&self, repo: &Repository, path: &Path) -> DaemonResult<Option<PathBuf>> {
+        let root = worktree_root(repo, &self.config)?;
+        let (Ok(path), Ok(root)) = (path.canonicalize(), root.canonicalize()) else {
+            return Ok(None);
+        };
+
+        if path.starts_with(&root) && path != root {
+            Ok(Some(path))
+        } else {
+            debug!("Not removing {:?}: not under {:?}", path, root);
+            Ok(None)
+        }
+    }
+}
+
+/// Directory that holds agent worktrees
+fn worktree_root(repo: &Repository, config: &Config) -> DaemonResult<PathBuf> {
+    let workdir = repo
+        .workdir()
+        .ok_or_else(|| DaemonError::Worktree("Repository has no working directory".to_string()))?;
+
+    Ok(workdir.join(&config.worktree_dir))
+}
+
+/// Create a pool worktree under `root` with a detached HEAD at `tip`
+fn create_pooled(repo: &Repository, root: &Path, tip: Oid) -> DaemonResult<PathBuf> {
+    let name = format!(
+        "{}{}",
+        POOL_PREFIX,
+        &Uuid::new_v4().simple().to_string()[..8]
+    );
+    let path = root.join(&name);
+
+    // Without a reference git2 checks out a new branch named after the
+    // worktree; detach from it and drop it
+    let worktree = repo.worktree(&name, &path, None)?;
+    let wt_repo = Repository::open_from_worktree(&worktree)?;
+    checkout_detached(&wt_repo, tip)?;
+    repo.find_branch(&name, BranchType::Local)?.delete()?;
+
+    info!("Created pooled worktree {:?}", path);
+    Ok(path)
+}
+
+/// Move a pooled worktree to `tip` if the branch has moved on
+fn refresh_pooled(path: &Path, tip: Oid) -> DaemonResult<()> {
+    let wt_repo = Repository::open(path)?;
+    if wt_repo.head()?.target() != Some(tip) {
+        checkout_detached(&wt_repo, tip)?;
+        debug!("Refreshed pooled worktree {:?} to {}", path, tip);
+    }
+    Ok(())
+}
+
+/// Check out a new agent branch at `tip` in a pooled worktree
+fn claim_pooled(repo: &Repository, path: &Path, branch_name: &str, tip: Oid) -> DaemonResult<()> {
+    refresh_pooled(path, tip)?;
+
+    let commit = repo.find_commit(tip)?;
+    let mut branch = repo.branch(branch_name, &commit, false)?;
+
+    let attached = Repository::open(path)
+        .and_then(|wt_repo| wt_repo.set_head(&format!("refs/heads/{}", branch_name)));
+    if let Err(e) = attached {
+        if let Err(cleanup) = branch.delete() {
+            warn!("Could not delete branch {}: {}", branch_name, cleanup);
+        }
+        return Err(e.into());
+    }
+
+    Ok(())
+}
+
+/// Force the worktree of `repo` to `tip` with a detached HEAD
+fn checkout_detached(repo: &Repository, tip: Oid) -> DaemonResult<()> {
+    let commit = repo.find_commit(tip)?;
+    repo.checkout_tree(
+        commit.as_object(),
+        Some(CheckoutBuilder::new().force().remove_untracked(true)),
+    )?;
+    repo.set_head_detached(tip)?;
+    Ok(())
+}
+
+/// Paths with uncommitted changes in a worktree, excluding ignored files
+/// and the plugin state directory
+fn changed_paths(repo: &Repository) -> DaemonResult<Vec<String>> {
+    let mut opts = StatusOptions::new();
+    opts.include_untracked(true)
+        .recurse_untracked_dirs(true)
+        .include_ignored(false);
+
+    let statuses = repo.statuses(Some(&mut opts))?;
+    Ok(statuses
+        .iter()
+        .filter_map(|s| s.path().map(str::to_string))
+        .filter(|p| !Path::new(p).starts_with(STATE_DIR))
+        .collect())
+}
+
+/// A tracked path relative to the worktree root, or None if it lies
+/// outside the worktree
+fn worktree_relative(root: &Path, path: &str) -> Option<String> {
+    let path = Path::new(path);
+    if path.is_relative() {
+        return Some(path.to_string_lossy().into_owned());
+    }
+
+    let relative = path
+        .strip_prefix(root)
+        .ok()
+        .map(Path::to_path_buf)
+        .or_else(|| {
+            let root = root.canonicalize().ok()?;
+            let parent = path.parent()?.canonicalize().ok()?;
+            Some(parent.strip_prefix(root).ok()?.join(path.file_name()?))
+        })?;
+
+    Some(relative.to_string_lossy().into_owned())
+}
+
+/// Git worktree name for an agent: its ID restricted to safe characters
+fn worktree_name(agent_id: &str) -> String {
+    agent_id
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect()
+}
+
+/// Total size of regular files under `path`, not following symlinks
+fn disk_usage(path: &Path) -> u64 {
+    let Ok(metadata) = std::fs::symlink_metadata(path) else {
+        return 0;
+    };
+
+    if metadata.is_dir() {
+        std::fs::read_dir(path)
+            .map(|dir| dir.flatten().map(|e| disk_usage(&e.path())).sum())
+            .unwrap_or(0)
+    } else if metadata.is_file() {
+        metadata.len()
+    } else {
+        0
+    }
+}
@@ -0,0 +1,52 @@
+//! Talking to the daemon over its socket with DaemonClient
+
+mod common;
+
+use common::{commit_file, init_repo};
+use merge_daemon::client::DaemonClient;
+use merge_daemon::queue::{EnqueueOptions, EntryStatus};
+use merge_daemon::{DaemonBuilder, DaemonError};
+
+#[tokio::test]
+async fn client_round_trips_agent_ids_that_need_escaping() {
+    let dir = tempfile::tempdir().unwrap();
+    let repo_path = dir.path().join("repo");
+    let socket = dir.path().join("run").join("daemon.sock");
+    let repo = init_repo(&repo_path);
+    commit_file(&repo, "agent/a1", "a.txt", "a\n");
+    let daemon = DaemonBuilder::new(&repo_path)
+        .socket(&socket)
+        .start()
+        .await
+        .unwrap();
+    let client = DaemonClient::new(&socket);
+
+    let hello = client.hello().await.unwrap();
+    assert_eq!(hello.daemon_version, env!("CARGO_PKG_VERSION"));
+
+    // Quotes and backslashes must survive the JSON encoding
+    let agent_id = r#"a "1" \ b"#;
+    let options = EnqueueOptions::default();
+    let position = client
+        .enqueue(agent_id, "s1", "agent/a1", &repo_path, "main", options)
+        .await
+        .unwrap();
+    assert_eq!(position, 0);
+
+    let outcome = client.wait(agent_id, Some(10)).await.unwrap();
+    assert_eq!(outcome.result, "MERGED", "{:?}", outcome);
+    let entry = client.entry(agent_id).await.unwrap();
+    assert_eq!(entry.agent_id, agent_id);
+    assert_eq!(entry.status, EntryStatus::Merged);
+
+    // Error replies come back typed
+    match client.entry("nobody").await {
+        Err(error @ DaemonError::Remote(_)) => {
+            assert_eq!(error.code(), "AGENT_NOT_FOUND");
+            assert!(!error.retryable());
+        }
+        other => panic!("expected a remote error, got {:?}", other),
+    }
+
+    daemon.shutdown().await.unwrap();
+}
@@ -0,0 +1,130 @@
+//! Committing agent worktrees through COMMIT_AND_ENQUEUE
+
+mod common;
+
+use common::{commit_file, init_repo, json};
+use merge_daemon::{Config, Daemon, DaemonBuilder, Request};
+use std::path::Path;
+
+fn commit_and_enqueue(worktree: &Path, tracked_only: bool) -> Request {
+    Request::CommitAndEnqueue {
+        agent_id: "a1".to_string(),
+        session_id: "s1".to_string(),
+        worktree: worktree.display().to_string(),
+        message: "Agent work".to_string(),
+        target_branch: "main".to_string(),
+        tracked_only,
+        priority: 0,
+        depends_on: vec![],
+    }
+}
+
+async fn track(daemon: &Daemon, path: &Path) {
+    let request = Request::TrackFile {
+        agent_id: "a1".to_string(),
+        path: path.display().to_string(),
+    };
+    assert_eq!(json(&daemon.handle(request).await)["status"], "OK");
+}
+
+#[tokio::test]
+async fn tracked_only_without_tracked_files_is_an_error() {
+    let dir = tempfile::tempdir().unwrap();
+    init_repo(dir.path());
+    let daemon = DaemonBuilder::new(dir.path()).start().await.unwrap();
+    let worktrees = daemon.queue().worktrees();
+    let agent = worktrees.create("a1", "s1", "main").unwrap();
+    std::fs::write(agent.worktree.join("a.txt"), "a\n").unwrap();
+
+    let request = commit_and_enqueue(&agent.worktree, true);
+    let response = json(&daemon.handle(request).await);
+    assert_eq!(response["code"], "INVALID_REQUEST", "{}", response);
+    let error = response["error"].as_str().unwrap();
+    assert!(error.contains("tracked no files"), "{}", error);
+
+    // The agent's change is still there to commit another way
+    let request = commit_and_enqueue(&agent.worktree, false);
+    let response = json(&daemon.handle(request).await);
+    assert_eq!(
+        response["files"],
+        serde_json::json!(["a.txt"]),
+        "{}",
+        response
+    );
+
+    daemon.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn tracked_only_leaves_out_ignored_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let repo = init_repo(dir.path());
+    commit_file(&repo, "main", ".gitignore", "*.log\n");
+    let daemon = DaemonBuilder::new(dir.path()).start().await.unwrap();
+    let worktrees = daemon.queue().worktrees();
+    let agent = worktrees.create("a1", "s1", "main").unwrap();
+
+    for file in ["a.txt", "build.log"] {
+        let path = agent.worktree.join(file);
+        std::fs::write(&path, "written by the agent\n").unwrap();
+        track(&daemon, &path).await;
+    }
+
+    let request = commit_and_enqueue(&agent.worktree, true);
+    let response = json(&daemon.handle(request).await);
+    assert_eq!(response["status"], "OK", "{}", response);
+    assert_eq!(response["files"], serde_json::json!(["a.txt"]));
+
+    daemon.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn tracked_files_survive_a_restart_until_committed() {
+    let dir = tempfile::tempdir().unwrap();
+    let repo_path = dir.path().join("repo");
+    let db = dir.path().join("state.db");
+    init_repo(&repo_path);
+
+    let daemon = DaemonBuilder::new(&repo_path)
+        .db_path(&db)
+        .start()
+        .await
+        .unwrap();
+    let worktrees = daemon.queue().worktrees();
+    let agent = worktrees.create("a1", "s1", "main").unwrap();
+    let written = agent.worktree.join("a.txt");
+    std::fs::write(&written, "written by the agent\n").unwrap();
+    track(&daemon, &written).await;
+    daemon.shutdown().await.unwrap();
+
+    // A formatter touched another file meanwhile
+    std::fs::write(agent.worktree.join("fmt.txt"), "reformatted\n").unwrap();
+
+    // Keep the worktree after the merge for the second commit
+    let config = Config {
+        preserve_worktrees: true,
+        ..Config::default()
+    };
+    let daemon = DaemonBuilder::new(&repo_path)
+        .config(config)
+        .db_path(&db)
+        .start()
+        .await
+        .unwrap();
+    let request = commit_and_enqueue(&agent.worktree, true);
+    let response = json(&daemon.handle(request).await);
+    assert_eq!(
+        response["files"],
+        serde_json::json!(["a.txt"]),
+        "{}",
+        response
+    );
+    assert_eq!(response["untouched"], serde_json::json!(["fmt.txt"]));
+
+    // Committing used up the tracked files
+    let request = commit_and_enqueue(&agent.worktree, true);
+    let response = json(&daemon.handle(request).await);
+    assert_eq!(response["code"], "INVALID_REQUEST", "{}", response);
+
+    daemon.shutdown().await.unwrap();
+}
@@ -0,0 +1,111 @@
+//! Shared fixtures for the integration tests
+
+#![allow(dead_code)]
+
+use git2::{Repository, RepositoryInitOptions, Signature};
+use merge_daemon::{Daemon, Request, Response};
+use std::path::Path;
+
+/// Create a repository at `path` with one commit on `main` and a committer
+/// identity for the daemon's own commits
+pub fn init_repo(path: &Path) -> Repository {
+    let repo =
+        Repository::init_opts(path, RepositoryInitOptions::new().initial_head("main")).unwrap();
+    let mut config = repo.config().unwrap();
+    config.set_str("user.name", "test").unwrap();
+    config.set_str("user.email", "test@example.com").unwrap();
+    commit_file(&repo, "main", "README", "init\n");
+    repo
+}
+
+/// Commit `path` with `contents` on top of `reference`, creating a branch
+/// from `main` if `reference` doesn't exist yet
+pub fn commit_file(repo: &Repository, reference: &str, path: &str, contents: &str) {
+    let signature = Signature::now("test", "test@example.com").unwrap();
+    let parent = repo
+        .revparse_single(reference)
+        .or_else(|_| repo.revparse_single("main"))
+        .ok()
+        .map(|object| object.peel_to_commit().unwrap());
+
+    let mut builder = repo
+        .treebuilder(parent.as_ref().map(|c| c.tree().unwrap()).as_ref())
+        .unwrap();
+    let blob = repo.blob(contents.as_bytes()).unwrap();
+    builder.insert(path, blob, 0o100644).unwrap();
+    let tree = repo.find_tree(builder.write().unwrap()).unwrap();
+
+    let parents: Vec<_> = parent.iter().collect();
+    repo.commit(
+        Some(&format!("refs/heads/{}", reference)),
+        &signature,
+        &signature,
+        path,
+        &tree,
+        &parents,
+    )
+    .unwrap();
+}
+
+/// An ENQUEUE request for `agent/<agent_id>` into `main`
+pub fn enqueue(repo: &Path, agent_id: &str, session_id: &str, depends_on: &[&str]) -> Request {
+    enqueue_into(repo, agent_id, session_id, "main", 0, depends_on)
+}
+
+/// An ENQUEUE request for `agent/<agent_id>` into `target_branch`
+pub fn enqueue_into(
+    repo: &Path,
+    agent_id: &str,
+    session_id: &str,
+    target_branch: &str,
+    priority: i32,
+    depends_on: &[&str],
+) -> Request {
+    Request::Enqueue {
+        agent_id: agent_id.to_string(),
+        session_id: session_id.to_string(),
+        branch: format!("agent/{}", agent_id),
+        worktree: repo.display().to_string(),
+        target_branch: target_branch.to_string(),
+        priority,
+        depends_on: depends_on.iter().map(|s| s.to_string()).collect(),
+    }
+}
+
+/// The JSON a client would receive for `response`
+pub fn json(response: &Response) -> serde_json::Value {
+    serde_json::to_value(response).unwrap()
+}
+
+/// Wait for `agent_id`'s merge to finish
+pub fn wait(agent_id: &str) -> Request {
+    Request::Wait {
+        agent_id: agent_id.to_string(),
+        timeout_secs: Some(30),
+    }
+}
+
+/// An agent's latest queue entry, as a client would see it
+pub async fn entry(daemon: &Daemon, agent_id: &str) -> serde_json::Value {
+    let request = Request::Entry {
+        agent_id: agent_id.to_string(),
+    };
+    json(&daemon.handle(request).await)["entry"].clone()
+}
+
+/// Agents of `session_id` in the order they merged
+pub async fn merge_order(daemon: &Daemon, session_id: &str) -> Vec<String> {
+    let request = Request::History {
+        session_id: Some(session_id.to_string()),
+        agent_id: None,
+        limit: None,
+    };
+    let history = json(&daemon.handle(request).await);
+    history["merges"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .rev()
+        .map(|merge| merge["agent_id"].as_str().unwrap().to_string())
+        .collect()
+}
@@ -0,0 +1,101 @@
+//! Embedding the daemon with DaemonBuilder
+
+mod common;
+
+use common::{commit_file, enqueue, init_repo, json, wait};
+use git2::Repository;
+use merge_daemon::pidfile::PidFile;
+use merge_daemon::{DaemonBuilder, DaemonError, Request};
+use std::time::Duration;
+
+/// Whether `main` has `path` in its tree
+fn has_file(repo: &Repository, path: &str) -> bool {
+    let tree = repo
+        .revparse_single("main")
+        .unwrap()
+        .peel_to_tree()
+        .unwrap();
+    let found = tree.get_name(path).is_some();
+    found
+}
+
+#[tokio::test]
+async fn embedded_daemon_serves_registered_repos_in_process() {
+    let dir = tempfile::tempdir().unwrap();
+    let default = dir.path().join("default");
+    let other = dir.path().join("other");
+    let default_repo = init_repo(&default);
+    let other_repo = init_repo(&other);
+    commit_file(&other_repo, "agent/a1", "a.txt", "a\n");
+    let daemon = DaemonBuilder::new(&default).start().await.unwrap();
+
+    let request = Request::RegisterRepo {
+        path: other.display().to_string(),
+        config: None,
+    };
+    let registered = json(&daemon.handle(request).await);
+    let repo_id = registered["repo_id"].as_str().unwrap();
+
+    let response = json(
+        &daemon
+            .handle_in(repo_id, enqueue(&other, "a1", "s1", &[]))
+            .await,
+    );
+    assert_eq!(response["status"], "OK", "{}", response);
+    let outcome = json(&daemon.handle_in(repo_id, wait("a1")).await);
+    assert_eq!(outcome["result"], "MERGED", "{}", outcome);
+
+    // The merge landed in the registered repository only
+    assert!(has_file(&other_repo, "a.txt"));
+    assert!(!has_file(&default_repo, "a.txt"));
+    let request = Request::Entry {
+        agent_id: "a1".to_string(),
+    };
+    let response = json(&daemon.handle(request).await);
+    assert_eq!(response["code"], "AGENT_NOT_FOUND", "{}", response);
+
+    // A SHUTDOWN request is left to the embedder to act on
+    assert_eq!(
+        json(&daemon.handle(Request::Shutdown).await)["status"],
+        "OK"
+    );
+    tokio::time::timeout(Duration::from_secs(5), daemon.shutdown_requested())
+        .await
+        .unwrap();
+    daemon.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn a_second_daemon_on_the_same_database_is_refused() {
+    let dir = tempfile::tempdir().unwrap();
+    let repo_path = dir.path().join("repo");
+    let db = dir.path().join("state").join("state.db");
+    let pid_path = PidFile::for_db(&db);
+    init_repo(&repo_path);
+
+    let daemon = DaemonBuilder::new(&repo_path)
+        .db_path(&db)
+        .start()
+        .await
+        .unwrap();
+    assert_eq!(PidFile::read(&pid_path), Some(std::process::id()));
+
+    let second = DaemonBuilder::new(&repo_path).db_path(&db).start().await;
+    match second {
+        Err(DaemonError::AlreadyRunning(pid)) => assert_eq!(pid, Some(std::process::id())),
+        Err(e) => panic!("expected AlreadyRunning, got {}", e),
+        Ok(_) => panic!("a second daemon started on the same database"),
+    }
+    assert!(PidFile::check(&pid_path).is_err());
+
+    // The lock goes with the daemon
+    daemon.shutdown().await.unwrap();
+    assert!(!pid_path.exists());
+    PidFile::check(&pid_path).unwrap();
+    let daemon = DaemonBuilder::new(&repo_path)
+        .db_path(&db)
+        .start()
+        .await
+        .unwrap();
+    daemon.shutdown().await.unwrap();
+}
@@ -0,0 +1,175 @@
+//! Requests to the loopback HTTP API
+
+mod common;
+
+use common::{commit_file, enqueue, init_repo, json};
+use merge_daemon::{Config, Daemon, DaemonBuilder, Request};
+use std::net::SocketAddr;
+use std::path::Path;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+
+const TOKEN: &str = "s3cret";
+
+async fn start(repo: &Path) -> Daemon {
+    let mut config = Config::default();
+    config.http.listen = Some("127.0.0.1:0".parse().unwrap());
+    config.http.token = Some(TOKEN.to_string());
+    DaemonBuilder::new(repo)
+        .config(config)
+        .start()
+        .await
+        .unwrap()
+}
+
+/// Send a request with `token` and return the response head and JSON body
+async fn send(
+    addr: SocketAddr,
+    method: &str,
+    path: &str,
+    token: Option<&str>,
+    body: &str,
+) -> (String, serde_json::Value) {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    let mut request = format!("{} {} HTTP/1.1\r\nHost: localhost\r\n", method, path);
+    if let Some(token) = token {
+        request.push_str(&format!("Authorization: Bearer {}\r\n", token));
+    }
+    request.push_str(&format!("Content-Length: {}\r\n\r\n{}", body.len(), body));
+    stream.write_all(request.as_bytes()).await.unwrap();
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    (head.to_string(), serde_json::from_str(body).unwrap())
+}
+
+async fn get(addr: SocketAddr, path: &str) -> (String, serde_json::Value) {
+    send(addr, "GET", path, Some(TOKEN), "").await
+}
+
+#[tokio::test]
+async fn requests_need_the_bearer_token() {
+    let dir = tempfile::tempdir().unwrap();
+    init_repo(dir.path());
+    let daemon = start(dir.path()).await;
+    let addr = daemon.http_addr().unwrap();
+
+    for token in [None, Some("wrong"), Some("s3cret-and-more")] {
+        let (head, body) = send(addr, "GET", "/queue", token, "").await;
+        assert!(head.starts_with("HTTP/1.1 401"), "{}", head);
+        assert!(head.contains("WWW-Authenticate: Bearer"), "{}", head);
+        assert_eq!(body["code"], "UNAUTHORIZED", "{}", body);
+    }
+
+    let (head, body) = get(addr, "/queue").await;
+    assert!(head.starts_with("HTTP/1.1 200"), "{}", head);
+    assert_eq!(body["queue_length"], 0, "{}", body);
+
+    daemon.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn repo_parameter_selects_the_repository() {
+    let dir = tempfile::tempdir().unwrap();
+    let default = dir.path().join("default");
+    let other = dir.path().join("other");
+    init_repo(&default);
+    let repo = init_repo(&other);
+    commit_file(&repo, "agent/a1", "a.txt", "a\n");
+    let daemon = start(&default).await;
+    let addr = daemon.http_addr().unwrap();
+
+    let registered = json(
+        &daemon
+            .handle(Request::RegisterRepo {
+                path: other.display().to_string(),
+                config: None,
+            })
+            .await,
+    );
+    let repo_id = registered["repo_id"].as_str().unwrap();
+
+    let body = serde_json::json!({
+        "agent_id": "a1",
+        "session_id": "s1",
+        "branch": "agent/a1",
+        "worktree": other,
+        "target_branch": "main",
+    });
+    let path = format!("/enqueue?repo={}", repo_id);
+    let (head, body) = send(addr, "POST", &path, Some(TOKEN), &body.to_string()).await;
+    assert!(head.starts_with("HTTP/1.1 200"), "{}", head);
+    assert_eq!(body["status"], "OK", "{}", body);
+
+    // A path works as well as an ID, percent-encoded or not
+    let encoded = other.display().to_string().replace('/', "%2F");
+    let (head, body) = get(addr, &format!("/entries/a1?repo={}", encoded)).await;
+    assert!(head.starts_with("HTTP/1.1 200"), "{}", head);
+    assert_eq!(body["entry"]["agent_id"], "a1", "{}", body);
+
+    let (head, body) = get(addr, "/entries/a1").await;
+    assert!(head.starts_with("HTTP/1.1 404"), "{}", head);
+    assert_eq!(body["code"], "AGENT_NOT_FOUND", "{}", body);
+
+    let (head, body) = get(addr, "/queue?repo=nope").await;
+    assert!(head.starts_with("HTTP/1.1 404"), "{}", head);
+    assert_eq!(body["code"], "REPO_NOT_FOUND", "{}", body);
+
+    daemon.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn events_stream_entry_updates() {
+    let dir = tempfile::tempdir().unwrap();
+    let repo = init_repo(dir.path());
+    commit_file(&repo, "agent/a1", "a.txt", "a\n");
+    let daemon = start(dir.path()).await;
+    let addr = daemon.http_addr().unwrap();
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    let request = format!(
+        "GET /events HTTP/1.1\r\nHost: localhost\r\nAuthorization: Bearer {}\r\n\r\n",
+        TOKEN
+    );
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut lines = BufReader::new(stream).lines();
+
+    let mut head = Vec::new();
+    while let Some(line) = lines.next_line().await.unwrap() {
+        if line.is_empty() {
+            break;
+        }
+        head.push(line);
+    }
+    assert_eq!(head[0], "HTTP/1.1 200 OK");
+    assert!(head.contains(&"Content-Type: text/event-stream".to_string()));
+
+    let a1 = enqueue(dir.path(), "a1", "s1", &[]);
+    assert_eq!(json(&daemon.handle(a1).await)["status"], "OK");
+
+    // Every change to the entry arrives, up to its merge
+    let merged = tokio::time::timeout(std::time::Duration::from_secs(10), async {
+        let mut event = None;
+        while let Some(line) = lines.next_line().await.unwrap() {
+            if let Some(name) = line.strip_prefix("event: ") {
+                event = Some(name.to_string());
+            } else if let Some(data) = line.strip_prefix("data: ") {
+                assert_eq!(event.as_deref(), Some("ENTRY_UPDATED"), "{}", data);
+                let data: serde_json::Value = serde_json::from_str(data).unwrap();
+                assert_eq!(data["agent_id"], "a1", "{}", data);
+                if data["status"] == "Merged" {
+                    return data;
+                }
+            }
+        }
+        panic!("event stream ended");
+    })
+    .await
+    .unwrap();
+    assert_eq!(merged["session_id"], "s1");
+
+    // The stream ends when the daemon shuts down
+    daemon.shutdown().await.unwrap();
+    while lines.next_line().await.unwrap().is_some() {}
+}
@@ -0,0 +1,93 @@
+//! Scraping the Prometheus metrics listener
+
+mod common;
+
+use merge_daemon::repos::repo_id;
+use merge_daemon::{Config, DaemonBuilder};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+async fn scrape(addr: std::net::SocketAddr, path: &str) -> String {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    let request = format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path);
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    response
+}
+
+#[tokio::test]
+async fn scrape_reports_merges() {
+    let dir = tempfile::tempdir().unwrap();
+    let repo = common::init_repo(dir.path());
+    common::commit_file(&repo, "agent/a1", "a.txt", "a\n");
+
+    let mut config = Config::default();
+    config.metrics.listen = Some("127.0.0.1:0".parse().unwrap());
+    let daemon = DaemonBuilder::new(dir.path())
+        .config(config)
+        .start()
+        .await
+        .unwrap();
+    let addr = daemon.metrics_addr().unwrap();
+
+    daemon
+        .handle(common::enqueue(dir.path(), "a1", "s1", &[]))
+        .await;
+    daemon.handle(common::wait("a1")).await;
+
+    let response = scrape(addr, "/metrics").await;
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    assert!(head.starts_with("HTTP/1.1 200"), "{}", head);
+    assert!(head.contains("text/plain; version=0.0.4"), "{}", head);
+
+    let repo = repo_id(&dir.path().canonicalize().unwrap());
+    for line in [
+        "# TYPE merge_daemon_merges_total counter".to_string(),
+        format!(
+            "merge_daemon_merges_total{{repo=\"{}\",result=\"merged\",strategy=\"rebase\"}} 1",
+            repo
+        ),
+        format!(
+            "merge_daemon_queue_entries{{repo=\"{}\",status=\"merged\"}} 1",
+            repo
+        ),
+        "# TYPE merge_daemon_merge_duration_seconds histogram".to_string(),
+        format!(
+            "merge_daemon_merge_duration_seconds_bucket{{repo=\"{}\",le=\"+Inf\"}} 1",
+            repo
+        ),
+        format!(
+            "merge_daemon_merge_duration_seconds_count{{repo=\"{}\"}} 1",
+            repo
+        ),
+        format!("merge_daemon_wait_seconds_count{{repo=\"{}\"}} 1", repo),
+    ] {
+        assert!(
+            body.lines().any(|l| l == line),
+            "missing {:?} in\n{}",
+            line,
+            body
+        );
+    }
+
+    let response = scrape(addr, "/queue").await;
+    assert!(response.starts_with("HTTP/1.1 404"), "{}", response);
+
+    daemon.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn metrics_require_a_loopback_address() {
+    let dir = tempfile::tempdir().unwrap();
+    common::init_repo(dir.path());
+
+    let overrides = serde_json::json!({"metrics": {"listen": "0.0.0.0:9464"}});
+    assert!(Config::default().with_overrides(&overrides).is_err());
+
+    let mut config = Config::default();
+    config.metrics.listen = Some("0.0.0.0:0".parse().unwrap());
+
+    let result = DaemonBuilder::new(dir.path()).config(config).start().await;
+    assert!(result.is_err());
+}
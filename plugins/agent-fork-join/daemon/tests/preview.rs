@@ -0,0 +1,81 @@
+//! Dry-run merges through PREVIEW
+
+mod common;
+
+use common::{commit_file, init_repo, json};
+use git2::Repository;
+use merge_daemon::{DaemonBuilder, Request};
+use std::path::Path;
+
+/// What a merge would change in a repository
+#[derive(Debug, PartialEq)]
+struct Snapshot {
+    refs: Vec<(String, String)>,
+    head: String,
+    index: Vec<u8>,
+    files: Vec<String>,
+}
+
+/// Refs, HEAD, index and working tree files of the repository at `path`
+fn snapshot(path: &Path) -> Snapshot {
+    let repo = Repository::open(path).unwrap();
+    let refs = repo
+        .references()
+        .unwrap()
+        .map(|r| {
+            let r = r.unwrap();
+            let target = r.target().map(|oid| oid.to_string()).unwrap_or_default();
+            (r.name().unwrap().to_string(), target)
+        })
+        .collect();
+    let head = std::fs::read_to_string(path.join(".git/HEAD")).unwrap();
+    let index = std::fs::read(path.join(".git/index")).unwrap();
+    let mut files: Vec<String> = std::fs::read_dir(path)
+        .unwrap()
+        .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    files.sort();
+    Snapshot {
+        refs,
+        head,
+        index,
+        files,
+    }
+}
+
+fn preview(branch: &str) -> Request {
+    Request::Preview {
+        branch: branch.to_string(),
+        target_branch: "main".to_string(),
+    }
+}
+
+#[tokio::test]
+async fn preview_leaves_the_repository_untouched() {
+    let dir = tempfile::tempdir().unwrap();
+    let repo = init_repo(dir.path());
+    commit_file(&repo, "agent/a1", "a.txt", "one\ntwo\n");
+    commit_file(&repo, "agent/x1", "README", "agent\n");
+    commit_file(&repo, "main", "README", "main\n");
+    repo.checkout_head(Some(git2::build::CheckoutBuilder::new().force()))
+        .unwrap();
+    let daemon = DaemonBuilder::new(dir.path()).start().await.unwrap();
+    let before = snapshot(dir.path());
+
+    let clean = json(&daemon.handle(preview("agent/a1")).await);
+    assert_eq!(clean["clean"], true, "{}", clean);
+    assert_eq!(clean["diffstat"]["files_changed"], 1);
+    assert_eq!(clean["diffstat"]["insertions"], 2);
+    let tree_oid = clean["tree_oid"].as_str().unwrap();
+    let tree = repo.find_tree(tree_oid.parse().unwrap()).unwrap();
+    assert!(tree.get_name("a.txt").is_some());
+
+    let conflict = json(&daemon.handle(preview("agent/x1")).await);
+    assert_eq!(conflict["clean"], false, "{}", conflict);
+    assert_eq!(conflict["conflict_files"], serde_json::json!(["README"]));
+    assert_eq!(conflict["tree_oid"], serde_json::Value::Null);
+
+    assert_eq!(snapshot(dir.path()), before);
+
+    daemon.shutdown().await.unwrap();
+}
@@ -0,0 +1,605 @@
+//! Merge queue behaviour through in-process requests
+
+mod common;
+
+use common::{commit_file, enqueue, enqueue_into, entry, init_repo, json, merge_order, wait};
+use merge_daemon::config::{BranchPolicy, MergeStrategy};
+use merge_daemon::{Config, Daemon, DaemonBuilder, Request};
+
+fn octopus_on(pattern: &str) -> BranchPolicy {
+    BranchPolicy {
+        pattern: pattern.to_string(),
+        merge_strategy: MergeStrategy::Octopus,
+        require_linear_history: None,
+        auto_rebase: None,
+    }
+}
+
+#[tokio::test]
+async fn session_merge_requires_octopus_targets() {
+    let dir = tempfile::tempdir().unwrap();
+    let repo = init_repo(dir.path());
+    commit_file(&repo, "octo", "README", "octo\n");
+    commit_file(&repo, "agent/b1", "b.txt", "b\n");
+    commit_file(&repo, "agent/a1", "a.txt", "a\n");
+
+    let config = Config {
+        branch_policies: vec![octopus_on("octo")],
+        ..Config::default()
+    };
+    let daemon = DaemonBuilder::new(dir.path())
+        .config(config)
+        .start()
+        .await
+        .unwrap();
+
+    // b1 waits for a session merge, so a1 stays pending behind it
+    let b1 = enqueue_into(dir.path(), "b1", "s2", "octo", 0, &[]);
+    assert_eq!(json(&daemon.handle(b1).await)["status"], "OK");
+    let a1 = enqueue(dir.path(), "a1", "s1", &["b1"]);
+    assert_eq!(json(&daemon.handle(a1).await)["status"], "OK");
+
+    let response = json(
+        &daemon
+            .handle(Request::SessionMerge {
+                session_id: "s1".to_string(),
+            })
+            .await,
+    );
+    assert_eq!(response["code"], "INVALID_REQUEST", "{}", response);
+
+    daemon.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn session_merge_takes_ready_entries_by_priority() {
+    let dir = tempfile::tempdir().unwrap();
+    let repo = init_repo(dir.path());
+    for agent in ["a1", "a2", "a3", "b1"] {
+        commit_file(&repo, &format!("agent/{}", agent), agent, "change\n");
+    }
+
+    let config = Config {
+        merge_strategy: MergeStrategy::Octopus,
+        ..Config::default()
+    };
+    let daemon = DaemonBuilder::new(dir.path())
+        .config(config)
+        .start()
+        .await
+        .unwrap();
+
+    for request in [
+        enqueue_into(dir.path(), "b1", "s2", "main", 0, &[]),
+        enqueue_into(dir.path(), "a1", "s1", "main", 0, &[]),
+        enqueue_into(dir.path(), "a2", "s1", "main", 5, &[]),
+        enqueue_into(dir.path(), "a3", "s1", "main", 9, &["b1"]),
+    ] {
+        assert_eq!(json(&daemon.handle(request).await)["status"], "OK");
+    }
+
+    let response = json(
+        &daemon
+            .handle(Request::SessionMerge {
+                session_id: "s1".to_string(),
+            })
+            .await,
+    );
+    assert_eq!(response["status"], "OK", "{}", response);
+    assert_eq!(
+        response["targets"][0]["merged"],
+        serde_json::json!(["a2", "a1"])
+    );
+
+    // a3 still waits for b1
+    let entry = json(
+        &daemon
+            .handle(Request::Entry {
+                agent_id: "a3".to_string(),
+            })
+            .await,
+    );
+    assert_eq!(entry["entry"]["status"], "Pending", "{}", entry);
+
+    daemon.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn session_merge_leaves_out_deleted_branches() {
+    let dir = tempfile::tempdir().unwrap();
+    let repo = init_repo(dir.path());
+    for agent in ["a1", "a2", "a3"] {
+        commit_file(&repo, &format!("agent/{}", agent), agent, "change\n");
+    }
+
+    let config = Config {
+        merge_strategy: MergeStrategy::Octopus,
+        ..Config::default()
+    };
+    let daemon = DaemonBuilder::new(dir.path())
+        .config(config)
+        .start()
+        .await
+        .unwrap();
+
+    for agent in ["a1", "a2", "a3"] {
+        let request = enqueue(dir.path(), agent, "s1", &[]);
+        assert_eq!(json(&daemon.handle(request).await)["status"], "OK");
+    }
+    let mut branch = repo
+        .find_branch("agent/a2", git2::BranchType::Local)
+        .unwrap();
+    branch.delete().unwrap();
+
+    let response = json(
+        &daemon
+            .handle(Request::SessionMerge {
+                session_id: "s1".to_string(),
+            })
+            .await,
+    );
+    assert_eq!(response["status"], "OK", "{}", response);
+    let target = &response["targets"][0];
+    assert_eq!(
+        target["merged"],
+        serde_json::json!(["a1", "a3"]),
+        "{}",
+        target
+    );
+    assert_eq!(target["failed"], serde_json::json!(["a2"]));
+
+    let a2 = entry(&daemon, "a2").await;
+    assert_eq!(a2["status"], "Failed", "{}", a2);
+    assert!(a2["last_error"].as_str().unwrap().contains("agent/a2"));
+    let tree = repo
+        .revparse_single("main")
+        .unwrap()
+        .peel_to_tree()
+        .unwrap();
+    assert!(tree.get_name("a1").is_some() && tree.get_name("a3").is_some());
+    assert!(tree.get_name("a2").is_none());
+
+    daemon.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn enqueue_waits_for_dependencies_enqueued_later() {
+    let dir = tempfile::tempdir().unwrap();
+    let repo = init_repo(dir.path());
+    commit_file(&repo, "agent/impl", "lib.rs", "impl\n");
+    commit_file(&repo, "agent/tests", "tests.rs", "tests\n");
+    let daemon = DaemonBuilder::new(dir.path()).start().await.unwrap();
+
+    // The tests agent finishes while the implementation agent still works
+    let tests = enqueue(dir.path(), "tests", "s1", &["impl"]);
+    assert_eq!(json(&daemon.handle(tests).await)["status"], "OK");
+
+    let status = json(&daemon.handle(Request::Status).await);
+    assert_eq!(status["awaiting"][0]["agent_id"], "tests", "{}", status);
+    assert_eq!(status["awaiting"][0]["missing"][0], "impl");
+    assert_eq!(status["pending"], 1);
+
+    let implementation = enqueue(dir.path(), "impl", "s1", &[]);
+    assert_eq!(json(&daemon.handle(implementation).await)["status"], "OK");
+
+    let merged = json(&daemon.handle(wait("tests")).await);
+    assert_eq!(merged["result"], "MERGED", "{}", merged);
+    assert_eq!(merge_order(&daemon, "s1").await, ["impl", "tests"]);
+
+    daemon.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn retry_only_requeues_conflicts_and_failures() {
+    let dir = tempfile::tempdir().unwrap();
+    let repo = init_repo(dir.path());
+    commit_file(&repo, "agent/a1", "a.txt", "a\n");
+    commit_file(&repo, "agent/x1", "README", "agent\n");
+    commit_file(&repo, "main", "README", "main\n");
+    let daemon = DaemonBuilder::new(dir.path()).start().await.unwrap();
+
+    for agent in ["a1", "x1"] {
+        daemon.handle(enqueue(dir.path(), agent, "s1", &[])).await;
+        daemon.handle(common::wait(agent)).await;
+    }
+    let retry = |agent: &str| Request::Retry {
+        agent_id: agent.to_string(),
+    };
+
+    let merged = json(&daemon.handle(retry("a1")).await);
+    assert_eq!(merged["code"], "INVALID_REQUEST", "{}", merged);
+
+    let conflicted = json(&daemon.handle(retry("x1")).await);
+    assert_eq!(conflicted["status"], "OK", "{}", conflicted);
+
+    daemon.shutdown().await.unwrap();
+}
+
+/// Finished entries still held in memory
+async fn finished_in_memory(daemon: &Daemon) -> usize {
+    let depth = daemon.queue().depth_by_status().await;
+    depth
+        .into_iter()
+        .filter(|(status, _)| status.is_terminal())
+        .map(|(_, count)| count)
+        .sum()
+}
+
+/// Wait for the processing loop to drop every finished entry from memory
+async fn wait_until_archived(daemon: &Daemon) {
+    tokio::time::timeout(std::time::Duration::from_secs(10), async {
+        while finished_in_memory(daemon).await > 0 {
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .expect("finished entries were never archived");
+}
+
+#[tokio::test]
+async fn finished_entries_are_archived_after_retention() {
+    let dir = tempfile::tempdir().unwrap();
+    let repo = init_repo(dir.path());
+    commit_file(&repo, "agent/a1", "a.txt", "a\n");
+
+    let config = Config {
+        archive_retention_secs: 1,
+        ..Config::default()
+    };
+    let daemon = DaemonBuilder::new(dir.path())
+        .config(config)
+        .start()
+        .await
+        .unwrap();
+
+    daemon.handle(enqueue(dir.path(), "a1", "s1", &[])).await;
+    let merged = json(&daemon.handle(wait("a1")).await);
+    assert_eq!(merged["result"], "MERGED", "{}", merged);
+
+    // Kept for the retention window, then dropped from memory only
+    assert_eq!(finished_in_memory(&daemon).await, 1);
+    wait_until_archived(&daemon).await;
+    assert_eq!(entry(&daemon, "a1").await["status"], "Merged");
+
+    daemon.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn enqueue_reloads_archived_dependencies() {
+    let dir = tempfile::tempdir().unwrap();
+    let repo = init_repo(dir.path());
+    for agent in ["a1", "b1", "c1", "d1"] {
+        commit_file(&repo, &format!("agent/{}", agent), agent, "change\n");
+    }
+
+    let config = Config {
+        archive_retention_secs: 0,
+        ..Config::default()
+    };
+    let daemon = DaemonBuilder::new(dir.path())
+        .config(config)
+        .start()
+        .await
+        .unwrap();
+
+    daemon.handle(enqueue(dir.path(), "a1", "s1", &[])).await;
+    daemon.handle(wait("a1")).await;
+    daemon
+        .handle(enqueue(dir.path(), "c1", "s1", &["never"]))
+        .await;
+    daemon
+        .handle(Request::Dequeue {
+            agent_id: "c1".to_string(),
+        })
+        .await;
+    wait_until_archived(&daemon).await;
+
+    // A merged dependency lets the entry merge rather than wait for it
+    let b1 = enqueue(dir.path(), "b1", "s1", &["a1"]);
+    assert_eq!(json(&daemon.handle(b1).await)["status"], "OK");
+    let status = json(&daemon.handle(Request::Status).await);
+    assert_eq!(status["awaiting"], serde_json::json!([]), "{}", status);
+    let merged = json(&daemon.handle(wait("b1")).await);
+    assert_eq!(merged["result"], "MERGED", "{}", merged);
+
+    // A cancelled one fails it
+    let d1 = enqueue(dir.path(), "d1", "s1", &["c1"]);
+    assert_eq!(json(&daemon.handle(d1).await)["status"], "OK");
+    let failed = json(&daemon.handle(wait("d1")).await);
+    assert_eq!(failed["result"], "FAILED", "{}", failed);
+
+    daemon.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn reap_dry_run_changes_nothing() {
+    let dir = tempfile::tempdir().unwrap();
+    let repo = init_repo(dir.path());
+
+    // A zero timeout leaves reaping to REAP requests, which then find every
+    // session stale
+    let config = Config {
+        session_timeout_secs: 0,
+        ..Config::default()
+    };
+    let daemon = DaemonBuilder::new(dir.path())
+        .config(config)
+        .start()
+        .await
+        .unwrap();
+
+    let agent = daemon
+        .queue()
+        .worktrees()
+        .create("a1", "s1", "main")
+        .unwrap();
+    let a1 = Request::Enqueue {
+        agent_id: "a1".to_string(),
+        session_id: "s1".to_string(),
+        branch: agent.branch.clone(),
+        worktree: agent.worktree.display().to_string(),
+        target_branch: "main".to_string(),
+        priority: 0,
+        depends_on: vec!["gate".to_string()],
+    };
+    assert_eq!(json(&daemon.handle(a1).await)["status"], "OK");
+
+    let reap = |dry_run| Request::Reap { dry_run };
+    let report = json(&daemon.handle(reap(true)).await);
+    assert_eq!(report["sessions"], serde_json::json!(["s1"]), "{}", report);
+    assert_eq!(report["branches_deleted"][0], agent.branch.as_str());
+    assert_eq!(entry(&daemon, "a1").await["status"], "Pending");
+    assert!(agent.worktree.exists());
+    assert!(repo
+        .find_branch(&agent.branch, git2::BranchType::Local)
+        .is_ok());
+
+    let report = json(&daemon.handle(reap(false)).await);
+    assert_eq!(report["sessions"], serde_json::json!(["s1"]), "{}", report);
+    assert_eq!(entry(&daemon, "a1").await["status"], "Cancelled");
+    assert!(!agent.worktree.exists());
+    assert!(repo
+        .find_branch(&agent.branch, git2::BranchType::Local)
+        .is_err());
+
+    daemon.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn linear_history_blocks_octopus() {
+    let dir = tempfile::tempdir().unwrap();
+    let repo = init_repo(dir.path());
+    commit_file(&repo, "agent/a1", "a.txt", "a\n");
+
+    let config = Config {
+        merge_strategy: MergeStrategy::Octopus,
+        require_linear_history: true,
+        ..Config::default()
+    };
+    let daemon = DaemonBuilder::new(dir.path())
+        .config(config)
+        .start()
+        .await
+        .unwrap();
+
+    let preview = json(
+        &daemon
+            .handle(Request::Preview {
+                branch: "agent/a1".to_string(),
+                target_branch: "main".to_string(),
+            })
+            .await,
+    );
+    assert_eq!(preview["clean"], false, "{}", preview);
+    assert!(preview["reason"]
+        .as_str()
+        .unwrap()
+        .starts_with("Linear history required"));
+
+    daemon.handle(enqueue(dir.path(), "a1", "s1", &[])).await;
+    let response = json(
+        &daemon
+            .handle(Request::SessionMerge {
+                session_id: "s1".to_string(),
+            })
+            .await,
+    );
+    assert_eq!(response["targets"][0]["failed"][0], "a1", "{}", response);
+
+    daemon.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn higher_priority_merges_first() {
+    let dir = tempfile::tempdir().unwrap();
+    let repo = init_repo(dir.path());
+    for agent in ["gate", "low", "mid", "high", "also-high"] {
+        commit_file(&repo, &format!("agent/{}", agent), agent, "change\n");
+    }
+    let daemon = DaemonBuilder::new(dir.path()).start().await.unwrap();
+
+    // Everything waits for the gate, then competes on priority alone
+    for (agent, priority) in [("low", 0), ("high", 10), ("mid", 5), ("also-high", 10)] {
+        let request = enqueue_into(dir.path(), agent, "s1", "main", priority, &["gate"]);
+        assert_eq!(json(&daemon.handle(request).await)["status"], "OK");
+    }
+    let gate = enqueue(dir.path(), "gate", "s1", &[]);
+    assert_eq!(json(&daemon.handle(gate).await)["status"], "OK");
+
+    let merged = json(&daemon.handle(wait("low")).await);
+    assert_eq!(merged["result"], "MERGED", "{}", merged);
+    assert_eq!(
+        merge_order(&daemon, "s1").await,
+        ["gate", "high", "also-high", "mid", "low"]
+    );
+
+    daemon.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn priority_survives_restart() {
+    let dir = tempfile::tempdir().unwrap();
+    let repo_path = dir.path().join("repo");
+    let db = dir.path().join("state.db");
+    let repo = init_repo(&repo_path);
+    for agent in ["gate", "low", "high"] {
+        commit_file(&repo, &format!("agent/{}", agent), agent, "change\n");
+    }
+
+    let daemon = DaemonBuilder::new(&repo_path)
+        .db_path(&db)
+        .start()
+        .await
+        .unwrap();
+    for (agent, priority) in [("low", 0), ("high", 7)] {
+        let request = enqueue_into(&repo_path, agent, "s1", "main", priority, &["gate"]);
+        assert_eq!(json(&daemon.handle(request).await)["status"], "OK");
+    }
+    daemon.shutdown().await.unwrap();
+
+    let daemon = DaemonBuilder::new(&repo_path)
+        .db_path(&db)
+        .start()
+        .await
+        .unwrap();
+    let high = entry(&daemon, "high").await;
+    assert_eq!(high["priority"], 7, "{}", high);
+    assert_eq!(high["status"], "Pending");
+
+    let gate = enqueue(&repo_path, "gate", "s1", &[]);
+    assert_eq!(json(&daemon.handle(gate).await)["status"], "OK");
+    let merged = json(&daemon.handle(wait("low")).await);
+    assert_eq!(merged["result"], "MERGED", "{}", merged);
+    assert_eq!(merge_order(&daemon, "s1").await, ["gate", "high", "low"]);
+
+    daemon.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn transient_errors_are_retried_until_the_limit() {
+    let dir = tempfile::tempdir().unwrap();
+    let repo = init_repo(dir.path());
+    commit_file(&repo, "agent/a1", "a.txt", "a\n");
+
+    let config = Config {
+        max_retries: 3,
+        retry_base_delay_ms: 200,
+        retry_max_delay_ms: 400,
+        ..Config::default()
+    };
+    // Another git process holds the index
+    let lock = dir.path().join(".git/index.lock");
+    std::fs::write(&lock, "").unwrap();
+    let daemon = DaemonBuilder::new(dir.path())
+        .config(config)
+        .start()
+        .await
+        .unwrap();
+
+    let a1 = enqueue(dir.path(), "a1", "s1", &[]);
+    assert_eq!(json(&daemon.handle(a1).await)["status"], "OK");
+
+    // The first attempt fails on the lock and schedules a retry
+    let retry = loop {
+        let status = json(&daemon.handle(Request::Status).await);
+        if let Some(retry) = status["retrying"].get(0) {
+            break retry.clone();
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    };
+    assert_eq!(retry["agent_id"], "a1");
+    assert_eq!(retry["attempts"], 1);
+    let next_retry_at: chrono::DateTime<chrono::Utc> =
+        serde_json::from_value(retry["next_retry_at"].clone()).unwrap();
+    let delay = next_retry_at - chrono::Utc::now();
+    assert!(delay <= chrono::Duration::milliseconds(200), "{}", delay);
+    assert!(
+        retry["last_error"].as_str().unwrap().contains("lock"),
+        "{}",
+        retry
+    );
+
+    let failed = json(&daemon.handle(wait("a1")).await);
+    assert_eq!(failed["result"], "FAILED", "{}", failed);
+    let a1 = entry(&daemon, "a1").await;
+    assert_eq!(a1["attempts"], 3, "{}", a1);
+    assert_eq!(a1["next_retry_at"], serde_json::Value::Null);
+
+    daemon.shutdown().await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn shutdown_rejects_requests_and_finishes_the_merge_in_flight() {
+    let dir = tempfile::tempdir().unwrap();
+    let repo_path = dir.path().join("repo");
+    let db = dir.path().join("state.db");
+    let repo = init_repo(&repo_path);
+    commit_file(&repo, "agent/a1", "a.txt", "a\n");
+    commit_file(&repo, "agent/b1", "b.txt", "b\n");
+
+    // Checking out this many files keeps the merge busy for a while
+    let blob = repo.blob(b"bulk\n").unwrap();
+    let mut bulk = repo.treebuilder(None).unwrap();
+    for i in 0..5_000 {
+        bulk.insert(format!("{:05}", i), blob, 0o100644).unwrap();
+    }
+    let bulk = bulk.write().unwrap();
+    let main = repo
+        .revparse_single("main")
+        .unwrap()
+        .peel_to_commit()
+        .unwrap();
+    let mut root = repo.treebuilder(Some(&main.tree().unwrap())).unwrap();
+    root.insert("bulk", bulk, 0o040000).unwrap();
+    let tree = repo.find_tree(root.write().unwrap()).unwrap();
+    let signature = git2::Signature::now("test", "test@example.com").unwrap();
+    repo.commit(
+        Some("refs/heads/main"),
+        &signature,
+        &signature,
+        "bulk",
+        &tree,
+        &[&main],
+    )
+    .unwrap();
+
+    let daemon = DaemonBuilder::new(&repo_path)
+        .db_path(&db)
+        .start()
+        .await
+        .unwrap();
+    let a1 = enqueue(&repo_path, "a1", "s1", &[]);
+    assert_eq!(json(&daemon.handle(a1).await)["status"], "OK");
+    // The merge blocks the runtime thread it runs on, timers included. Act
+    // from a thread outside the runtime and wait without tokio's timers.
+    tokio::task::block_in_place(|| {
+        tokio::runtime::Handle::current().block_on(async {
+            let deadline = std::time::Instant::now() + std::time::Duration::from_secs(10);
+            while daemon.queue().status().await.processing == 0 {
+                assert!(
+                    std::time::Instant::now() < deadline,
+                    "the merge never started"
+                );
+                std::thread::sleep(std::time::Duration::from_millis(1));
+            }
+
+            let shutdown = json(&daemon.handle(Request::Shutdown).await);
+            assert_eq!(shutdown["status"], "OK");
+            let b1 = json(&daemon.handle(enqueue(&repo_path, "b1", "s1", &[])).await);
+            assert_eq!(b1["code"], "SHUTTING_DOWN", "{}", b1);
+            assert_eq!(b1["retryable"], true);
+            assert_eq!(entry(&daemon, "a1").await["status"], "Processing");
+        })
+    });
+    daemon.shutdown().await.unwrap();
+
+    let daemon = DaemonBuilder::new(&repo_path)
+        .db_path(&db)
+        .start()
+        .await
+        .unwrap();
+    assert_eq!(entry(&daemon, "a1").await["status"], "Merged");
+    assert_eq!(merge_order(&daemon, "s1").await, ["a1"]);
+
+    daemon.shutdown().await.unwrap();
+}
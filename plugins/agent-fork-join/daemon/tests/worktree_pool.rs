@@ -0,0 +1,43 @@
+//! Handing out pre-warmed worktrees
+
+mod common;
+
+use common::{commit_file, init_repo};
+use git2::Repository;
+use merge_daemon::worktree::WorktreeManager;
+use merge_daemon::Config;
+
+#[test]
+fn agents_get_a_pooled_worktree_at_the_branch_tip() {
+    let dir = tempfile::tempdir().unwrap();
+    let repo = init_repo(dir.path());
+    let config = Config {
+        worktree_pool_size: 1,
+        worktree_pool_branches: vec!["main".to_string()],
+        ..Config::default()
+    };
+    let worktrees = WorktreeManager::new(dir.path().to_path_buf(), config);
+
+    worktrees.fill_pool().unwrap();
+    let pooled = worktrees.list().unwrap();
+    assert_eq!(pooled.len(), 1, "{:?}", pooled);
+
+    // The branch moves on after the pool was filled
+    commit_file(&repo, "main", "new.txt", "new\n");
+    let tip = repo.revparse_single("main").unwrap().id();
+
+    let agent = worktrees.create("a1", "s1", "main").unwrap();
+    assert_eq!(agent.worktree, pooled[0].path);
+    assert_eq!(agent.branch, "agent/s1/a1");
+    let checkout = Repository::open(&agent.worktree).unwrap();
+    let head = checkout.head().unwrap();
+    assert_eq!(head.name(), Some("refs/heads/agent/s1/a1"));
+    assert_eq!(head.target(), Some(tip));
+    assert!(agent.worktree.join("new.txt").exists());
+
+    // The pool is topped up with a fresh worktree
+    worktrees.fill_pool().unwrap();
+    let listed = worktrees.list().unwrap();
+    assert_eq!(listed.len(), 2, "{:?}", listed);
+    assert!(listed.iter().any(|w| w.path != agent.worktree));
+}
@@ -21,8 +21,9 @@ _cache_age() {
 	local now
 	now=$(date +%s)
 	local mtime
-	# macOS uses -f %m, Linux uses -c %Y
-	mtime=$(stat -f %m "$file" 2>/dev/null || stat -c %Y "$file" 2>/dev/null || echo 0)
+	# Linux uses -c %Y, macOS uses -f %m. Try Linux first: GNU stat -f
+	# succeeds but prints file system status instead.
+	mtime=$(stat -c %Y "$file" 2>/dev/null || stat -f %m "$file" 2>/dev/null || echo 0)
 	echo $((now - mtime))
 }
 
@@ -1,16 +1,60 @@
 #!/usr/bin/env bash
 # Merge daemon IPC client functions
 
-# Default socket path
-DAEMON_SOCKET="${FORK_JOIN_DAEMON_SOCKET:-/tmp/merge-daemon.sock}"
 DAEMON_BIN="${FORK_JOIN_DAEMON_BIN:-merge-daemon}"
 
+# Find the daemon binary: the plugin's build, or $DAEMON_BIN on PATH
+daemon_binary() {
+	local plugin_dir
+	plugin_dir="$(dirname "$(dirname "$(dirname "${BASH_SOURCE[0]}")")")"
+
+	if [[ -x "${plugin_dir}/daemon/target/release/${DAEMON_BIN}" ]]; then
+		echo "${plugin_dir}/daemon/target/release/${DAEMON_BIN}"
+	elif [[ -x "${plugin_dir}/daemon/target/debug/${DAEMON_BIN}" ]]; then
+		echo "${plugin_dir}/daemon/target/debug/${DAEMON_BIN}"
+	elif command -v "$DAEMON_BIN" >/dev/null 2>&1; then
+		echo "$DAEMON_BIN"
+	else
+		return 1
+	fi
+}
+
+# Socket of the current user's daemon for the current repository
+daemon_locate() {
+	local daemon_path
+	daemon_path="$(daemon_binary)" || return 1
+	"$daemon_path" locate 2>/dev/null
+}
+
+# Root of the main working tree, also when run from an agent's worktree, so
+# every worktree of a repository talks to the same queue
+daemon_repo_root() {
+	local common_dir
+	common_dir="$(git rev-parse --path-format=absolute --git-common-dir 2>/dev/null)" || return 1
+	dirname "$common_dir"
+}
+
+# Socket path, unless set explicitly
+DAEMON_SOCKET="${FORK_JOIN_DAEMON_SOCKET:-$(daemon_locate || true)}"
+
 # Check if daemon is running
 daemon_is_running() {
 	[[ -S "$DAEMON_SOCKET" ]] && daemon_send '{"type":"STATUS"}' >/dev/null 2>&1
 }
 
-# Send a message to the daemon and get response
+# Handshake: protocol version, daemon version and capabilities
+daemon_hello() {
+	daemon_send '{"type":"HELLO","protocol_version":1}'
+}
+
+# Check whether the daemon supports a request type, e.g. COMMIT_AND_ENQUEUE
+daemon_supports() {
+	local request_type="$1"
+	daemon_hello | jq -e --arg t "$request_type" '.capabilities // [] | index($t) != null' >/dev/null 2>&1
+}
+
+# Send a message to the daemon and get response. The message is sent for
+# the current repository (or $FORK_JOIN_REPO), so one daemon can serve many.
 daemon_send() {
 	local message="$1"
 	local timeout="${2:-5}"
@@ -20,6 +64,11 @@ daemon_send() {
 		return 1
 	fi
 
+	local repo="${FORK_JOIN_REPO:-$(daemon_repo_root)}"
+	if [[ -n "$repo" ]] && command -v jq >/dev/null 2>&1; then
+		message="$(jq -c --arg repo "$repo" '. + {repo: $repo}' <<<"$message")"
+	fi
+
 	# Use netcat to send message and receive response
 	if command -v nc >/dev/null 2>&1; then
 		echo "$message" | nc -U -w "$timeout" "$DAEMON_SOCKET" 2>/dev/null
@@ -34,7 +83,7 @@ daemon_send() {
 
 # Start the daemon
 start_daemon() {
-	local repo_path="${1:-$(git rev-parse --show-toplevel 2>/dev/null || pwd)}"
+	local repo_path="${1:-$(daemon_repo_root || pwd)}"
 	local state_dir="${FORK_JOIN_STATE_DIR:-.fork-join}"
 	local db_path="${state_dir}/state.db"
 
@@ -42,38 +91,30 @@ start_daemon() {
 	mkdir -p "$state_dir"
 
 	# Find daemon binary
-	local daemon_path=""
-
-	# Check if it's in the plugin directory
-	local plugin_dir
-	plugin_dir="$(dirname "$(dirname "${BASH_SOURCE[0]}")")"
-
-	if [[ -x "${plugin_dir}/daemon/target/release/${DAEMON_BIN}" ]]; then
-		daemon_path="${plugin_dir}/daemon/target/release/${DAEMON_BIN}"
-	elif [[ -x "${plugin_dir}/daemon/target/debug/${DAEMON_BIN}" ]]; then
-		daemon_path="${plugin_dir}/daemon/target/debug/${DAEMON_BIN}"
-	elif command -v "$DAEMON_BIN" >/dev/null 2>&1; then
-		daemon_path="$DAEMON_BIN"
-	fi
-
-	if [[ -z "$daemon_path" ]]; then
+	local daemon_path
+	if ! daemon_path="$(daemon_binary)"; then
+		local plugin_dir
+		plugin_dir="$(dirname "$(dirname "$(dirname "${BASH_SOURCE[0]}")")")"
 		log_error "Merge daemon binary not found. Please build it first:"
 		log_error "  cd ${plugin_dir}/daemon && cargo build --release"
 		return 1
 	fi
 
-	# Start daemon in background
-	nohup "$daemon_path" \
+	if [[ -z "$DAEMON_SOCKET" ]]; then
+		DAEMON_SOCKET="$("$daemon_path" --repo "$repo_path" locate)" || return 1
+	fi
+
+	# The daemon detaches itself and returns once it accepts connections
+	if ! "$daemon_path" \
 		--repo "$repo_path" \
 		--socket "$DAEMON_SOCKET" \
 		--db "$db_path" \
-		--foreground \
-		>"${state_dir}/daemon.log" 2>&1 &
-
-	local daemon_pid=$!
-	echo "$daemon_pid" >"${state_dir}/daemon.pid"
+		--log-file "${state_dir}/daemon.log" >/dev/null; then
+		log_error "Failed to start merge daemon, see ${state_dir}/daemon.log"
+		return 1
+	fi
 
-	log_info "Started daemon with PID: $daemon_pid"
+	log_info "Started daemon with PID: $(cat "${state_dir}/daemon.pid")"
 }
 
 # Stop the daemon
@@ -81,10 +122,15 @@ stop_daemon() {
 	local state_dir="${FORK_JOIN_STATE_DIR:-.fork-join}"
 	local pid_file="${state_dir}/daemon.pid"
 
-	# Try graceful shutdown first
+	# Try graceful shutdown first, letting the merge in progress finish
 	if daemon_is_running; then
 		daemon_send '{"type":"SHUTDOWN"}' >/dev/null 2>&1 || true
-		sleep 1
+		local waited=0
+		local drain_timeout="${FORK_JOIN_DRAIN_TIMEOUT:-60}"
+		while [[ -S "$DAEMON_SOCKET" ]] && ((waited < drain_timeout)); do
+			sleep 1
+			waited=$((waited + 1))
+		done
 	fi
 
 	# Kill by PID if still running
@@ -126,41 +172,37 @@ daemon_enqueue() {
 	local worktree="$4"
 	local target_branch="$5"
 
-	daemon_send "$(
-		cat <<EOF
-{
-    "type": "ENQUEUE",
-    "agent_id": "$agent_id",
-    "session_id": "$session_id",
-    "branch": "$branch",
-    "worktree": "$worktree",
-    "target_branch": "$target_branch"
-}
-EOF
-	)"
+	daemon_send "$(jq -cn \
+		--arg agent_id "$agent_id" \
+		--arg session_id "$session_id" \
+		--arg branch "$branch" \
+		--arg worktree "$worktree" \
+		--arg target_branch "$target_branch" \
+		'{type: "ENQUEUE", agent_id: $agent_id, session_id: $session_id, branch: $branch, worktree: $worktree, target_branch: $target_branch}')"
 }
 
 # Dequeue an agent
 daemon_dequeue() {
 	local agent_id="$1"
-	daemon_send '{"type":"DEQUEUE","agent_id":"'"$agent_id"'"}'
+	daemon_send "$(jq -cn --arg agent_id "$agent_id" '{type: "DEQUEUE", agent_id: $agent_id}')"
 }
 
 # Get conflicts for an agent
 daemon_get_conflicts() {
 	local agent_id="$1"
-	daemon_send '{"type":"CONFLICTS","agent_id":"'"$agent_id"'"}'
+	daemon_send "$(jq -cn --arg agent_id "$agent_id" '{type: "CONFLICTS", agent_id: $agent_id}')"
 }
 
 # Retry a failed merge
 daemon_retry() {
 	local agent_id="$1"
-	daemon_send '{"type":"RETRY","agent_id":"'"$agent_id"'"}'
+	daemon_send "$(jq -cn --arg agent_id "$agent_id" '{type: "RETRY", agent_id: $agent_id}')"
 }
 
 # Wait for merge result
 daemon_wait() {
 	local agent_id="$1"
 	local timeout="${2:-300}"
-	daemon_send '{"type":"WAIT","agent_id":"'"$agent_id"'"}' "$timeout"
+	daemon_send "$(jq -cn --arg agent_id "$agent_id" --argjson timeout "$timeout" \
+		'{type: "WAIT", agent_id: $agent_id, timeout_secs: $timeout}')" "$((timeout + 5))"
 }
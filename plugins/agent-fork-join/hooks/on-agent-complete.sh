@@ -4,7 +4,7 @@
 # This hook:
 # 1. Checks for changes in the agent's worktree
 # 2. Requests a commit message from the agent
-# 3. Commits changes and enqueues for merge
+# 3. Has the daemon commit the changes and enqueue them for merge
 # 4. Handles conflict resolution if needed
 
 set -euo pipefail
@@ -60,8 +60,8 @@ OUTPUT ONLY THE COMMIT MESSAGE, nothing else."
 
 # Configuration
 STATE_DIR="${FORK_JOIN_STATE_DIR:-.fork-join}"
-DAEMON_SOCKET="${FORK_JOIN_DAEMON_SOCKET:-/tmp/merge-daemon.sock}"
 MAX_RETRIES="${FORK_JOIN_MAX_RETRIES:-3}"
+TRACKED_ONLY="${FORK_JOIN_TRACKED_ONLY:-false}"
 
 # Arguments
 AGENT_ID="${1:-}"
@@ -152,44 +152,39 @@ EOF
 		log_info "Generated commit message: $COMMIT_MESSAGE"
 	fi
 
-	# Stage all changes
-	git add -A
+	# Commit and enqueue for merge in one step
+	log_info "Committing and enqueueing branch for merge: $branch"
 
-	# Commit changes
-	git commit -m "$COMMIT_MESSAGE" --author="Agent ${AGENT_ID} for $(git config --get user.email)"
-
-	local commit_sha
-	commit_sha="$(git rev-parse HEAD)"
-
-	log_info "Committed changes: $commit_sha"
+	local enqueue_result
+	enqueue_result="$(daemon_send "$(jq -cn \
+		--arg agent_id "$AGENT_ID" \
+		--arg session_id "$session_id" \
+		--arg worktree "$worktree" \
+		--arg message "$COMMIT_MESSAGE" \
+		--arg target_branch "$feature_branch" \
+		--argjson tracked_only "$TRACKED_ONLY" \
+		'{type: "COMMIT_AND_ENQUEUE", agent_id: $agent_id, session_id: $session_id, worktree: $worktree, message: $message, target_branch: $target_branch, tracked_only: $tracked_only}')")"
 
-	# Update session state
-	jq --arg id "$AGENT_ID" --arg msg "$COMMIT_MESSAGE" --arg sha "$commit_sha" \
-		'(.agents[] | select(.agent_id == $id)) |= . + {commit_message: $msg, commit_sha: $sha, status: "COMMITTED"}' \
-		"$session_file" >"${session_file}.tmp" && mv "${session_file}.tmp" "$session_file"
+	local enqueue_status
+	enqueue_status="$(echo "$enqueue_result" | jq -r '.status // "ERROR"')"
 
-	# Enqueue for merge
-	log_info "Enqueueing branch for merge: $branch"
+	if [[ "$enqueue_status" == "NO_CHANGES" ]]; then
+		log_info "Nothing to commit after filtering, cleaning up"
+		cleanup_agent "$session_id" "$AGENT_ID" "$worktree" "$branch"
 
-	local enqueue_result
-	enqueue_result="$(daemon_send "$(
 		cat <<EOF
 {
-    "type": "ENQUEUE",
+    "agent_complete": true,
     "agent_id": "${AGENT_ID}",
-    "session_id": "${session_id}",
-    "branch": "${branch}",
-    "worktree": "${worktree}",
-    "target_branch": "${feature_branch}"
+    "changes": false,
+    "merged": false
 }
 EOF
-	)")"
-
-	local enqueue_status
-	enqueue_status="$(echo "$enqueue_result" | jq -r '.status // "ERROR"')"
+		exit 0
+	fi
 
 	if [[ "$enqueue_status" != "OK" ]]; then
-		log_error "Failed to enqueue merge: $enqueue_result"
+		log_error "Failed to commit and enqueue merge: $enqueue_result"
 
 		cat <<EOF
 {
@@ -197,12 +192,30 @@ EOF
     "agent_id": "${AGENT_ID}",
     "changes": true,
     "error": "Failed to enqueue merge",
+    "error_code": $(echo "$enqueue_result" | jq -c '.code // null'),
+    "retryable": $(echo "$enqueue_result" | jq -c '.retryable // false'),
     "details": $(echo "$enqueue_result" | jq -c .)
 }
 EOF
 		exit 1
 	fi
 
+	local commit_sha
+	commit_sha="$(echo "$enqueue_result" | jq -r '.commit_sha')"
+
+	log_info "Committed changes: $commit_sha"
+
+	local untouched
+	untouched="$(echo "$enqueue_result" | jq -r '.untouched // [] | join(", ")')"
+	if [[ -n "$untouched" ]]; then
+		log_warn "Changed files the agent never wrote: $untouched"
+	fi
+
+	# Update session state
+	jq --arg id "$AGENT_ID" --arg msg "$COMMIT_MESSAGE" --arg sha "$commit_sha" \
+		'(.agents[] | select(.agent_id == $id)) |= . + {commit_message: $msg, commit_sha: $sha, status: "COMMITTED"}' \
+		"$session_file" >"${session_file}.tmp" && mv "${session_file}.tmp" "$session_file"
+
 	local queue_position
 	queue_position="$(echo "$enqueue_result" | jq -r '.position // 0')"
 
@@ -244,11 +257,9 @@ cleanup_agent() {
 	# Return to original directory
 	cd - >/dev/null 2>&1 || true
 
-	# Remove worktree
-	git worktree remove "$worktree" --force 2>/dev/null || true
-
-	# Delete agent branch
-	git branch -D "$branch" 2>/dev/null || true
+	# Remove worktree and agent branch
+	daemon_send "$(jq -cn --arg worktree "$worktree" --arg branch "$branch" \
+		'{type: "WORKTREE_REMOVE", worktree: $worktree, branch: $branch}')" >/dev/null 2>&1 || true
 
 	# Update session state
 	local session_file="${STATE_DIR}/${session_id}.json"
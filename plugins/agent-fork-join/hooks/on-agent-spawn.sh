@@ -3,9 +3,9 @@
 #
 # This hook:
 # 1. Generates a unique agent ID
-# 2. Creates an isolated git worktree for the agent
-# 3. Registers the agent with the merge daemon
-# 4. Updates session state
+# 2. Registers the agent with the merge daemon, which creates an isolated
+#    git worktree and branch for it
+# 3. Updates session state
 
 set -euo pipefail
 
@@ -16,9 +16,6 @@ source "${SCRIPT_DIR}/lib/daemon-client.sh"
 
 # Configuration
 STATE_DIR="${FORK_JOIN_STATE_DIR:-.fork-join}"
-WORKTREE_DIR="${FORK_JOIN_WORKTREE_DIR:-.worktrees}"
-DAEMON_SOCKET="${FORK_JOIN_DAEMON_SOCKET:-/tmp/merge-daemon.sock}"
-AGENT_BRANCH_PREFIX="${FORK_JOIN_AGENT_PREFIX:-agent/}"
 
 # Read agent info from stdin or arguments
 AGENT_TYPE="${1:-worker}"
@@ -52,30 +49,27 @@ main() {
 
 	log_info "Creating worktree for agent: $agent_id"
 
-	# Create worktree directory
-	mkdir -p "$WORKTREE_DIR"
-
-	local worktree_path="${WORKTREE_DIR}/${agent_id}"
-	local agent_branch="${AGENT_BRANCH_PREFIX}${session_id}/${agent_id}"
-
-	# Create the worktree with a new branch
-	git worktree add -b "$agent_branch" "$worktree_path" "$feature_branch"
-
-	log_info "Created worktree at: $worktree_path"
-	log_info "Agent branch: $agent_branch"
-
-	# Register with daemon
+	# Register with daemon, which creates the worktree and agent branch
 	local register_result
-	register_result="$(daemon_send '{"type":"REGISTER","agent_id":"'"$agent_id"'"}')"
+	register_result="$(daemon_send "$(jq -cn \
+		--arg agent_id "$agent_id" \
+		--arg session_id "$session_id" \
+		--arg base_branch "$feature_branch" \
+		'{type: "REGISTER", agent_id: $agent_id, session_id: $session_id, base_branch: $base_branch, create_worktree: true}')")"
 
 	if [[ "$(echo "$register_result" | jq -r '.status // "ERROR"')" != "OK" ]]; then
 		log_error "Failed to register agent with daemon: $register_result"
-		# Cleanup worktree on failure
-		git worktree remove "$worktree_path" --force 2>/dev/null || true
-		git branch -D "$agent_branch" 2>/dev/null || true
 		exit 1
 	fi
 
+	local worktree_path
+	local agent_branch
+	worktree_path="$(echo "$register_result" | jq -r '.worktree')"
+	agent_branch="$(echo "$register_result" | jq -r '.branch')"
+
+	log_info "Created worktree at: $worktree_path"
+	log_info "Agent branch: $agent_branch"
+
 	# Update session state with new agent
 	local timestamp
 	timestamp="$(date -u +%Y-%m-%dT%H:%M:%SZ)"
@@ -83,56 +83,6 @@ prompt_will_make_changes() {
 	return 1
 }
 
-# Append prompt to existing PR
-append_prompt_to_pr() {
-	local pr_number="$1"
-	local new_prompt="$2"
-
-	debug_log "Appending prompt to PR #${pr_number}"
-
-	local current_body
-	current_body=$(cache_get_pr_body)
-
-	if [[ -z "$current_body" ]]; then
-		debug_log "Could not get PR body"
-		return 1
-	fi
-
-	local prompt_timestamp
-	prompt_timestamp=$(date -u +"%Y-%m-%d %H:%M:%S UTC")
-
-	local new_prompt_section="
-<details>
-<summary>📝 Prompt - ${prompt_timestamp}</summary>
-
-\`\`\`
-${new_prompt}
-\`\`\`
-
-</details>"
-
-	local updated_body
-	if [[ "$current_body" == *"## Prompt History"* ]]; then
-		updated_body="${current_body}
-${new_prompt_section}"
-	else
-		updated_body="${current_body}
-
----
-
-## Prompt History
-${new_prompt_section}"
-	fi
-
-	if gh pr edit "$pr_number" --body "$updated_body" 2>/dev/null; then
-		# Refresh cache with new body
-		cache_refresh_pr
-		log_info "Appended new prompt to PR #${pr_number}"
-		return 0
-	fi
-	return 1
-}
-
 main() {
 	log_info "UserPromptSubmit hook triggered"
 
@@ -151,37 +101,7 @@ main() {
 	fi
 
 	# Handle beads/JIRA issue tracking
-	local current_issue=""
-	local jira_key=""
-	local issue_status=""
-
-	if current_issue=$(cache_get_issue_id 2>/dev/null); then
-		jira_key=$(cache_get_jira_key 2>/dev/null || echo "")
-		issue_status=$(cache_get_issue_status 2>/dev/null || echo "open")
-		debug_log "Issue: $current_issue ($jira_key) status=$issue_status"
-
-		# Update to in_progress if not already
-		if [[ "$issue_status" != "in_progress" && "$issue_status" != "closed" ]]; then
-			log_info "Setting $jira_key to In Progress..."
-			if beads_update_status "$current_issue" "in_progress" 2>/dev/null; then
-				cache_update_issue_status "in_progress"
-				echo "Set $jira_key to In Progress"
-			fi
-		fi
-
-		# Check if closed
-		if [[ "$issue_status" == "closed" ]]; then
-			debug_log "Issue $current_issue is closed"
-			beads_clear_current_issue
-			cache_clear_issue
-			echo ""
-			echo "=== Issue Completed ==="
-			echo "BEADS_ISSUE_CLOSED=true"
-			echo "BEADS_COMPLETED_ISSUE=$current_issue"
-			echo "JIRA_COMPLETED_TICKET=$jira_key"
-			echo ""
-		fi
-	fi
+	"${SCRIPT_DIR}/../scripts/publish.sh" issue || true
 
 	# Check if plugin should activate
 	if ! cache_is_github; then
@@ -244,12 +164,7 @@ main() {
 		fi
 
 		# Push branch immediately
-		log_info "Pushing feature branch to origin..."
-		if git push -u origin "$feature_branch" 2>&1; then
-			log_info "Feature branch pushed successfully"
-		else
-			log_warn "Failed to push (may not have remote)"
-		fi
+		"${SCRIPT_DIR}/../scripts/publish.sh" push-branch "$feature_branch" >/dev/null || true
 
 		# Create session state
 		local session_id
@@ -268,15 +183,12 @@ main() {
 	else
 		debug_log "On feature branch: $current_branch"
 
-		# Check for existing PR
-		local existing_pr
-		existing_pr=$(cache_get_pr_number 2>/dev/null || echo "")
+		# Append the prompt to an existing PR
+		local pr_update
+		pr_update=$("${SCRIPT_DIR}/../scripts/publish.sh" append-prompt "$PROMPT" || true)
 
-		if [[ -n "$existing_pr" ]]; then
-			debug_log "Found existing PR #${existing_pr}"
-			log_info "Existing PR #${existing_pr} found, appending prompt..."
-			append_prompt_to_pr "$existing_pr" "$PROMPT"
-			echo "Updated PR #${existing_pr} with new prompt"
+		if [[ -n "$pr_update" ]]; then
+			echo "$pr_update"
 		else
 			echo "Already on feature branch: $current_branch"
 		fi
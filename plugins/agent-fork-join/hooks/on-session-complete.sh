@@ -31,16 +31,12 @@ source "${SCRIPT_DIR}/lib/common.sh" 2>/dev/null || exit 0
 source "${SCRIPT_DIR}/lib/git-utils.sh" 2>/dev/null || exit 0
 source "${SCRIPT_DIR}/lib/cache.sh" 2>/dev/null || exit 0
 
-# Get cached issue/JIRA info
-CURRENT_BEADS_ISSUE=""
+# Get cached JIRA key for the commit message
 CURRENT_JIRA_KEY=""
-JIRA_URL=""
 
-if issue_id=$(cache_get_issue_id 2>/dev/null); then
-	CURRENT_BEADS_ISSUE="$issue_id"
+if cache_get_issue_id >/dev/null 2>&1; then
 	CURRENT_JIRA_KEY=$(cache_get_jira_key 2>/dev/null || echo "")
-	JIRA_URL=$(cache_get_jira_url 2>/dev/null || echo "")
-	debug_log "Issue: $CURRENT_BEADS_ISSUE (JIRA: $CURRENT_JIRA_KEY)"
+	debug_log "JIRA: $CURRENT_JIRA_KEY"
 fi
 
 main() {
@@ -135,111 +131,9 @@ main() {
 		fi
 	fi
 
-	# Push changes
-	debug_log "Pushing to origin"
-	git push origin "$current_branch" 2>&1 || true
-
-	# Check if PR exists (from cache)
-	local existing_pr
-	existing_pr=$(cache_get_pr_number 2>/dev/null || echo "")
-
-	if [[ -n "$existing_pr" ]]; then
-		debug_log "PR #${existing_pr} already exists"
-		echo "Pull request #${existing_pr} already exists for branch $current_branch"
-		exit 0
-	fi
-
-	# Extract commit type for PR title
-	local commit_type="${current_branch%%/*}"
-	local branch_desc
-	branch_desc=$(echo "$current_branch" | sed 's/^[^/]*\///' | tr '-' ' ')
-
-	# Build PR title
-	local pr_title
-	if [[ -n "$CURRENT_JIRA_KEY" ]]; then
-		pr_title="${CURRENT_JIRA_KEY}: ${commit_type}: ${branch_desc}"
-	else
-		pr_title="${commit_type}: ${branch_desc}"
-	fi
-	if [[ ${#pr_title} -gt 72 ]]; then
-		pr_title="${pr_title:0:69}..."
-	fi
-
-	# Get commit log for this branch
-	local base_branch
-	base_branch=$(cache_get_default_branch)
-	local commit_log
-	commit_log=$(git log --oneline "${base_branch}..HEAD" 2>/dev/null || git log --oneline -10 2>/dev/null || echo "")
-
-	# Generate PR body - try AI first unless disabled
-	local pr_body=""
-
-	# First generate the heuristic body (we need the metadata sections)
-	local heuristic_body
-	heuristic_body=$("${SCRIPT_DIR}/../scripts/generate-pr-summary.sh" \
-		"$current_branch" \
-		"$session_prompt" \
-		"$commit_log" \
-		"$CURRENT_JIRA_KEY" \
-		"$JIRA_URL" 2>/dev/null)
-
-	if [[ "${FORK_JOIN_NO_AI:-}" != "1" && -n "$session_prompt" ]]; then
-		export FORK_JOIN_HOOK_CONTEXT=1
-		local ai_summary
-		if ai_summary=$("${SCRIPT_DIR}/../scripts/llm-enhance.sh" pr-summary \
-			"$session_prompt" "$commit_log" "$current_branch" 15 2>/dev/null); then
-			if [[ -n "$ai_summary" ]]; then
-				# Use AI summary but keep our structured metadata sections
-				pr_body="${ai_summary}
-
----
-
-$(echo "$heuristic_body" | sed -n '/^## JIRA Ticket/,$p')"
-				debug_log "Using AI-generated PR summary"
-			fi
-		fi
-		unset FORK_JOIN_HOOK_CONTEXT
-	fi
-
-	# Fallback to heuristics if AI disabled or failed
-	if [[ -z "$pr_body" ]]; then
-		pr_body="$heuristic_body"
-		debug_log "Using heuristic PR summary"
-	fi
-
-	# Create PR
-	debug_log "Creating pull request"
-	local pr_url
-	if pr_url=$(gh pr create --title "$pr_title" --body "$pr_body" --head "$current_branch" 2>&1); then
-		debug_log "PR created successfully"
-		echo "Pull request created for branch $current_branch"
-
-		# Refresh PR cache
-		cache_refresh_pr
-
-		# Comment on beads issue
-		if [[ -n "$CURRENT_BEADS_ISSUE" ]]; then
-			debug_log "Commenting on beads issue"
-
-			local actual_pr_url
-			actual_pr_url=$(cache_get_pr_url 2>/dev/null || echo "$pr_url")
-
-			local beads_comment="Pull request created for this issue:
-
-**PR Title:** ${pr_title}
-**PR URL:** ${actual_pr_url}
-**JIRA Ticket:** ${CURRENT_JIRA_KEY:-N/A}
-
----
-_Automated comment from agent-fork-join_"
-
-			if beads_add_comment "$CURRENT_BEADS_ISSUE" "$beads_comment" 2>/dev/null; then
-				echo "Commented on beads issue $CURRENT_BEADS_ISSUE"
-			fi
-		fi
-	else
-		debug_log "Failed to create PR"
-	fi
+	# Push and create the PR if it doesn't exist
+	debug_log "Publishing branch"
+	"${SCRIPT_DIR}/../scripts/publish.sh" pull-request "$current_branch" "$session_prompt" || true
 
 	# Cleanup tracked files
 	rm -f "${STATE_DIR}/tracked_files.txt"
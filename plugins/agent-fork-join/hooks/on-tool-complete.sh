@@ -3,7 +3,8 @@
 #
 # This hook:
 # 1. Detects when Write/Edit tools complete
-# 2. Records the file path for the session-end commit
+# 2. Records the file path for the session-end commit, and with the merge
+#    daemon for the agent that wrote it
 # 3. Does NOT commit immediately - the Stop hook handles that
 #
 # This ensures a single commit per agent session rather than per-file commits.
@@ -71,6 +72,23 @@ if [[ -z "$FILE_PATH" ]]; then
 	exit 0
 fi
 
+# Report the write to the merge daemon when it belongs to a fork-join agent,
+# so the agent's commit can be limited to the files it actually wrote
+STATE_DIR="${FORK_JOIN_STATE_DIR:-.fork-join}"
+if [[ -f "${STATE_DIR}/current_session" ]] && [[ -f "${SCRIPT_DIR}/lib/daemon-client.sh" ]]; then
+	session_file="${STATE_DIR}/$(cat "${STATE_DIR}/current_session").json"
+	agent_id="$(jq -r --arg file "$FILE_PATH" \
+		'.agents[]? | select(.worktree as $w | $file | startswith($w + "/")) | .agent_id' \
+		"$session_file" 2>/dev/null | head -1 || true)"
+
+	if [[ -n "$agent_id" ]]; then
+		source "${SCRIPT_DIR}/lib/daemon-client.sh"
+		track_result="$(daemon_send "$(jq -cn --arg agent_id "$agent_id" --arg path "$FILE_PATH" \
+			'{type: "TRACK_FILE", agent_id: $agent_id, path: $path}')" 2>/dev/null || true)"
+		debug_log "Tracked $FILE_PATH for agent $agent_id: $track_result"
+	fi
+fi
+
 # Ensure we're in a git repository
 if ! git_is_repo; then
 	debug_log "Not in a git repository, skipping"
@@ -107,9 +125,6 @@ fi
 
 debug_log "File has changes, recording for later commit: $FILE_PATH"
 
-# Session state directory
-STATE_DIR="${FORK_JOIN_STATE_DIR:-.fork-join}"
-
 # Record the file for later commit (append to tracked files list)
 mkdir -p "$STATE_DIR"
 ensure_fork_join_gitignored # Add .fork-join/ to .gitignore if not already
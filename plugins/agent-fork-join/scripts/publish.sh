@@ -0,0 +1,255 @@
+#!/usr/bin/env bash
+# GitHub and issue tracking steps shared by the hooks
+#
+# Uses AI by default for PR summaries.
+# Set FORK_JOIN_NO_AI=1 to disable AI and use heuristics only.
+#
+# Usage:
+#   publish.sh issue                         Mark the current issue in progress
+#   publish.sh push-branch <branch>          Push a new feature branch
+#   publish.sh append-prompt <prompt>        Add a prompt to the branch's PR
+#   publish.sh pull-request <branch> <prompt>
+#                                            Push and open a PR if there is none
+#
+# Messages for the user go to stdout. Every step does nothing outside a
+# GitHub repository.
+
+set -euo pipefail
+
+SCRIPT_DIR="$(cd "$(dirname "${BASH_SOURCE[0]}")" && pwd)"
+
+# Source dependencies
+source "${SCRIPT_DIR}/../hooks/lib/common.sh"
+source "${SCRIPT_DIR}/../hooks/lib/git-utils.sh"
+source "${SCRIPT_DIR}/../hooks/lib/cache.sh"
+
+# Set the current beads/JIRA issue to in_progress, or report it completed
+publish_issue() {
+	local current_issue=""
+	if ! current_issue=$(cache_get_issue_id 2>/dev/null); then
+		return 0
+	fi
+
+	local jira_key
+	jira_key=$(cache_get_jira_key 2>/dev/null || echo "")
+	local issue_status
+	issue_status=$(cache_get_issue_status 2>/dev/null || echo "open")
+
+	# Update to in_progress if not already
+	if [[ "$issue_status" != "in_progress" && "$issue_status" != "closed" ]]; then
+		log_info "Setting $jira_key to In Progress..."
+		if beads_update_status "$current_issue" "in_progress" 2>/dev/null; then
+			cache_update_issue_status "in_progress"
+			echo "Set $jira_key to In Progress"
+		fi
+	fi
+
+	if [[ "$issue_status" == "closed" ]]; then
+		beads_clear_current_issue
+		cache_clear_issue
+		echo ""
+		echo "=== Issue Completed ==="
+		echo "BEADS_ISSUE_CLOSED=true"
+		echo "BEADS_COMPLETED_ISSUE=$current_issue"
+		echo "JIRA_COMPLETED_TICKET=$jira_key"
+		echo ""
+	fi
+}
+
+# Push a newly created feature branch
+publish_push_branch() {
+	local branch="$1"
+
+	log_info "Pushing feature branch to origin..."
+	if git push -u origin "$branch" >&2; then
+		log_info "Feature branch pushed successfully"
+		echo "Feature branch '$branch' pushed to origin."
+	else
+		log_warn "Failed to push (may not have remote)"
+	fi
+}
+
+# Append a prompt to the "Prompt History" of the branch's PR
+publish_append_prompt() {
+	local new_prompt="$1"
+
+	local pr_number
+	pr_number=$(cache_get_pr_number 2>/dev/null || echo "")
+	if [[ -z "$pr_number" ]]; then
+		return 0
+	fi
+
+	log_info "Existing PR #${pr_number} found, appending prompt..."
+
+	local current_body
+	current_body=$(cache_get_pr_body)
+	if [[ -z "$current_body" ]]; then
+		log_warn "Could not get PR body"
+		return 1
+	fi
+
+	local prompt_timestamp
+	prompt_timestamp=$(date -u +"%Y-%m-%d %H:%M:%S UTC")
+
+	local new_prompt_section="
+<details>
+<summary>📝 Prompt - ${prompt_timestamp}</summary>
+
+\`\`\`
+${new_prompt}
+\`\`\`
+
+</details>"
+
+	local updated_body
+	if [[ "$current_body" == *"## Prompt History"* ]]; then
+		updated_body="${current_body}
+${new_prompt_section}"
+	else
+		updated_body="${current_body}
+
+---
+
+## Prompt History
+${new_prompt_section}"
+	fi
+
+	if gh pr edit "$pr_number" --body "$updated_body" >/dev/null 2>&1; then
+		# Refresh cache with new body
+		cache_refresh_pr
+		echo "Updated PR #${pr_number} with new prompt"
+		return 0
+	fi
+	return 1
+}
+
+# Push the branch and create its PR (AI-generated summary) if it has none
+publish_pull_request() {
+	local current_branch="$1"
+	local session_prompt="${2:-}"
+
+	local current_issue=""
+	local jira_key=""
+	local jira_url=""
+	if current_issue=$(cache_get_issue_id 2>/dev/null); then
+		jira_key=$(cache_get_jira_key 2>/dev/null || echo "")
+		jira_url=$(cache_get_jira_url 2>/dev/null || echo "")
+	fi
+
+	git push origin "$current_branch" >&2 || true
+
+	local existing_pr
+	existing_pr=$(cache_get_pr_number 2>/dev/null || echo "")
+	if [[ -n "$existing_pr" ]]; then
+		echo "Pull request #${existing_pr} already exists for branch $current_branch"
+		return 0
+	fi
+
+	# Extract commit type for PR title
+	local commit_type="${current_branch%%/*}"
+	local branch_desc
+	branch_desc=$(echo "$current_branch" | sed 's/^[^/]*\///' | tr '-' ' ')
+
+	# Build PR title
+	local pr_title
+	if [[ -n "$jira_key" ]]; then
+		pr_title="${jira_key}: ${commit_type}: ${branch_desc}"
+	else
+		pr_title="${commit_type}: ${branch_desc}"
+	fi
+	if [[ ${#pr_title} -gt 72 ]]; then
+		pr_title="${pr_title:0:69}..."
+	fi
+
+	# Get commit log for this branch
+	local base_branch
+	base_branch=$(cache_get_default_branch)
+	local commit_log
+	commit_log=$(git log --oneline "${base_branch}..HEAD" 2>/dev/null || git log --oneline -10 2>/dev/null || echo "")
+
+	# First generate the heuristic body (we need the metadata sections)
+	local heuristic_body
+	heuristic_body=$("${SCRIPT_DIR}/generate-pr-summary.sh" \
+		"$current_branch" \
+		"$session_prompt" \
+		"$commit_log" \
+		"$jira_key" \
+		"$jira_url" 2>/dev/null)
+
+	local pr_body=""
+	if [[ "${FORK_JOIN_NO_AI:-}" != "1" && -n "$session_prompt" ]]; then
+		local ai_summary
+		if ai_summary=$(FORK_JOIN_HOOK_CONTEXT=1 "${SCRIPT_DIR}/llm-enhance.sh" pr-summary \
+			"$session_prompt" "$commit_log" "$current_branch" 15 2>/dev/null); then
+			if [[ -n "$ai_summary" ]]; then
+				# Use AI summary but keep our structured metadata sections
+				pr_body="${ai_summary}
+
+---
+
+$(echo "$heuristic_body" | sed -n '/^## JIRA Ticket/,$p')"
+			fi
+		fi
+	fi
+
+	# Fallback to heuristics if AI disabled or failed
+	if [[ -z "$pr_body" ]]; then
+		pr_body="$heuristic_body"
+	fi
+
+	local pr_url
+	if ! pr_url=$(gh pr create --title "$pr_title" --body "$pr_body" --head "$current_branch" 2>&1); then
+		log_warn "Failed to create pull request: $pr_url"
+		return 0
+	fi
+
+	echo "Pull request created for branch $current_branch"
+
+	# Refresh PR cache
+	cache_refresh_pr
+
+	if [[ -n "$current_issue" ]]; then
+		local actual_pr_url
+		actual_pr_url=$(cache_get_pr_url 2>/dev/null || echo "$pr_url")
+
+		local beads_comment="Pull request created for this issue:
+
+**PR Title:** ${pr_title}
+**PR URL:** ${actual_pr_url}
+**JIRA Ticket:** ${jira_key:-N/A}
+
+---
+_Automated comment from agent-fork-join_"
+
+		if beads_add_comment "$current_issue" "$beads_comment" 2>/dev/null; then
+			echo "Commented on beads issue $current_issue"
+		fi
+	fi
+}
+
+main() {
+	local step="${1:-}"
+	shift || true
+
+	if ! git_is_repo; then
+		exit 0
+	fi
+
+	cache_git_state
+	if ! cache_is_github; then
+		exit 0
+	fi
+
+	case "$step" in
+	issue) publish_issue ;;
+	push-branch) publish_push_branch "$@" ;;
+	append-prompt) publish_append_prompt "$@" ;;
+	pull-request) publish_pull_request "$@" ;;
+	*)
+		echo "Usage: $0 {issue|push-branch|append-prompt|pull-request} [args...]" >&2
+		exit 1
+		;;
+	esac
+}
+
+main "$@"